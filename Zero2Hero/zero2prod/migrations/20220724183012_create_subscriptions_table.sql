@@ -0,0 +1,9 @@
+-- create subscriptions table
+-- SQLite no tiene tipos para uuid ni para fechas: el id se guarda como los 16 bytes del uuid y
+-- `subscribed_at` como texto RFC 3339, que es como los manda sqlx
+CREATE TABLE subscriptions(
+   id BLOB NOT NULL PRIMARY KEY,
+   email TEXT NOT NULL UNIQUE,
+   name TEXT NOT NULL,
+   subscribed_at TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- add status to subscriptions
+-- las suscripciones que ya estaban se dan por confirmadas, las nuevas arrancan en
+-- 'pending_confirmation' hasta que se usa el link del email
+ALTER TABLE subscriptions ADD COLUMN status TEXT NOT NULL DEFAULT 'confirmed';
@@ -0,0 +1,5 @@
+-- create subscription tokens table
+CREATE TABLE subscription_tokens(
+   subscription_token TEXT NOT NULL PRIMARY KEY,
+   subscriber_id BLOB NOT NULL REFERENCES subscriptions (id)
+);
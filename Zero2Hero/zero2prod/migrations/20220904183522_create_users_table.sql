@@ -0,0 +1,8 @@
+-- create users table
+-- los que pueden entrar a `/admin`, la password se guarda como hash de Argon2 en formato PHC
+-- (algoritmo, parametros y salt van adentro del mismo string)
+CREATE TABLE users(
+   user_id BLOB NOT NULL PRIMARY KEY,
+   username TEXT NOT NULL UNIQUE,
+   password_hash TEXT NOT NULL
+);
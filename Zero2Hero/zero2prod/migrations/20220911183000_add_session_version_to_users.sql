@@ -0,0 +1,5 @@
+-- add session_version to users
+-- la sesion vive en una cookie firmada que no podemos borrar del lado del server. Cada sesion
+-- guarda la version que tenia el usuario al entrar y solo vale mientras siga siendo la misma,
+-- salir o cambiar la password la sube y todas las cookies anteriores dejan de servir
+ALTER TABLE users ADD COLUMN session_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,68 @@
+use crate::authentication::get_session_version;
+use crate::session_state::TypedSession;
+use crate::utils::see_other;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{error, web, FromRequest, HttpMessage};
+use sqlx::SqlitePool;
+use std::ops::Deref;
+use uuid::Uuid;
+
+/// El usuario que inicio sesion, los handlers de `/admin` lo sacan con `web::ReqData<UserId>`
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Si no hay nadie en la sesion (o la sesion ya no vale porque el usuario salio o cambio la
+/// password desde otro lado) lo mandamos al login, si hay alguien lo dejamos en el request
+pub async fn reject_anonymous_users(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+
+    let user_id = session
+        .get_user_id()
+        .map_err(error::ErrorInternalServerError)?;
+    let session_version = session
+        .get_session_version()
+        .map_err(error::ErrorInternalServerError)?;
+    let (user_id, session_version) = match (user_id, session_version) {
+        (Some(user_id), Some(session_version)) => (user_id, session_version),
+        _ => {
+            tracing::warn!("Rejected an anonymous user");
+            return Ok(req.into_response(see_other("/login")).map_into_right_body());
+        }
+    };
+
+    let pool = req
+        .app_data::<web::Data<SqlitePool>>()
+        .ok_or_else(|| error::ErrorInternalServerError("Missing the database pool"))?;
+    let current_version = get_session_version(user_id, pool)
+        .await
+        .map_err(|_| error::ErrorInternalServerError("Failed to check the session"))?;
+    if current_version != Some(session_version) {
+        tracing::warn!("Rejected a revoked session");
+        session.log_out();
+        return Ok(req.into_response(see_other("/login")).map_into_right_body());
+    }
+    req.extensions_mut().insert(UserId(user_id));
+    next.call(req).await.map(|res| res.map_into_left_body())
+}
@@ -0,0 +1,12 @@
+//! Quien puede entrar a `/admin`: las passwords de los usuarios, las versiones de sus sesiones y
+//! el middleware que deja afuera a los que no iniciaron sesion (o cuya sesion ya no vale)
+mod middleware;
+mod password;
+mod sessions;
+
+pub use middleware::{reject_anonymous_users, UserId};
+pub use password::{
+    change_password, compute_password_hash, create_first_admin, validate_credentials, AuthError,
+    Credentials,
+};
+pub use sessions::{get_session_version, revoke_sessions};
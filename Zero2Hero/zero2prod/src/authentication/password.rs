@@ -0,0 +1,223 @@
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// El hash de una password que nadie tiene, con los mismos parametros que los de verdad. Lo
+/// verificamos cuando el usuario no existe para tardar lo mismo que con uno que existe, si no
+/// midiendo el tiempo se podria saber que usuarios hay
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$bTCEVNg5QmLQaiVbC2w6+w$\
+     vKQiGOkjkcPTknzWK9PpVhWQrPfKl5kX9A1wbnAEHos";
+
+/// Lo que manda alguien para entrar
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// el usuario no existe o la password no es la suya, no le decimos cual de las dos
+    InvalidCredentials,
+    /// algo fallo de nuestro lado (la base, un hash roto), ya quedo en los logs
+    Unexpected,
+}
+
+/// Devuelve el id del usuario si la password es la suya
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &SqlitePool,
+) -> Result<Uuid, AuthError> {
+    let mut user_id = None;
+    let mut expected_password_hash = Secret::new(DUMMY_PASSWORD_HASH.to_string());
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, pool).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await??;
+
+    user_id.ok_or(AuthError::InvalidCredentials)
+}
+
+/// Guarda el hash de la nueva password del usuario y cierra todas sus sesiones, devuelve la
+/// version nueva para que la sesion que la cambio pueda seguir
+#[tracing::instrument(name = "Change password", skip(password, pool))]
+pub async fn change_password(
+    user_id: Uuid,
+    password: Secret<String>,
+    pool: &SqlitePool,
+) -> Result<i64, AuthError> {
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password)).await??;
+    let (session_version,): (i64,) = sqlx::query_as(
+        r#"
+        UPDATE users SET password_hash = $1, session_version = session_version + 1
+        WHERE user_id = $2
+        RETURNING session_version
+        "#,
+    )
+    .bind(password_hash.expose_secret())
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        AuthError::Unexpected
+    })?;
+    Ok(session_version)
+}
+
+/// Crea al usuario si todavia no hay ninguno, devuelve `false` si ya habia alguien (y no toca
+/// nada). Asi se entra la primera vez sin que la password quede en las migraciones
+#[tracing::instrument(name = "Create the first admin", skip(password, pool))]
+pub async fn create_first_admin(
+    username: &str,
+    password: Secret<String>,
+    pool: &SqlitePool,
+) -> Result<bool, AuthError> {
+    if password.expose_secret().is_empty() {
+        tracing::error!("The password of the first admin is empty");
+        return Err(AuthError::Unexpected);
+    }
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password)).await??;
+    let created = sqlx::query(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        SELECT $1, $2, $3
+        WHERE NOT EXISTS (SELECT 1 FROM users)
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(username)
+    .bind(password_hash.expose_secret())
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        AuthError::Unexpected
+    })?;
+    Ok(created.rows_affected() == 1)
+}
+
+/// El hash en formato PHC, con un salt nuevo cada vez
+///
+/// NOTE(elsuizo:2022-09-04): Argon2id con los parametros minimos que recomienda OWASP, calcularlo
+/// tarda a proposito, por eso desde un handler va en `spawn_blocking`
+pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, AuthError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let params = Params::new(15000, 2, 1, None).map_err(|e| {
+        tracing::error!("Invalid Argon2 parameters: {:?}", e);
+        AuthError::Unexpected
+    })?;
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)
+        .map_err(|e| {
+            tracing::error!("Failed to hash the password: {:?}", e);
+            AuthError::Unexpected
+        })?
+        .to_string();
+    Ok(Secret::new(password_hash))
+}
+
+#[tracing::instrument(
+    name = "Verify password hash",
+    skip(expected_password_hash, password_candidate)
+)]
+fn verify_password_hash(
+    expected_password_hash: Secret<String>,
+    password_candidate: Secret<String>,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .map_err(|e| {
+            tracing::error!("Failed to parse the stored hash in PHC format: {:?}", e);
+            AuthError::Unexpected
+        })?;
+    // los parametros salen del hash guardado, no de los que usamos ahora para los nuevos
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .map_err(|_| AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
+async fn get_stored_credentials(
+    username: &str,
+    pool: &SqlitePool,
+) -> Result<Option<(Uuid, Secret<String>)>, AuthError> {
+    let row: Option<(Uuid, String)> =
+        sqlx::query_as(r#"SELECT user_id, password_hash FROM users WHERE username = $1"#)
+            .bind(username)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to execute query: {:?}", e);
+                AuthError::Unexpected
+            })?;
+    Ok(row.map(|(user_id, password_hash)| (user_id, Secret::new(password_hash))))
+}
+
+/// Corre `f` en los threads de tokio para cosas bloqueantes, adentro del span de quien la llama
+/// para que sus logs no queden sueltos
+async fn spawn_blocking_with_tracing<F, R>(f: F) -> Result<R, AuthError>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+        .await
+        .map_err(|e| {
+            tracing::error!("The blocking task failed: {:?}", e);
+            AuthError::Unexpected
+        })
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_hash_verifies_only_its_own_password() {
+        let password = || Secret::new("everythinghastostartsomewhere".to_string());
+        let password_hash = compute_password_hash(password()).unwrap();
+        assert!(verify_password_hash(password_hash.clone(), password()).is_ok());
+        assert!(matches!(
+            verify_password_hash(password_hash, Secret::new("wrong password".to_string())),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[test]
+    fn the_same_password_gets_a_different_salt_each_time() {
+        let password = || Secret::new("everythinghastostartsomewhere".to_string());
+        let first = compute_password_hash(password()).unwrap();
+        let second = compute_password_hash(password()).unwrap();
+        assert_ne!(first.expose_secret(), second.expose_secret());
+    }
+
+    #[test]
+    fn the_dummy_hash_is_valid() {
+        // si estuviera roto los usuarios que no existen darian 500 en lugar de credenciales malas
+        assert!(matches!(
+            verify_password_hash(
+                Secret::new(DUMMY_PASSWORD_HASH.to_string()),
+                Secret::new("everythinghastostartsomewhere".to_string())
+            ),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+}
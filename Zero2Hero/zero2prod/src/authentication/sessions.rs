@@ -0,0 +1,36 @@
+use crate::authentication::AuthError;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// La version de las sesiones del usuario, `None` si el usuario ya no existe
+#[tracing::instrument(name = "Get session version", skip(pool))]
+pub async fn get_session_version(
+    user_id: Uuid,
+    pool: &SqlitePool,
+) -> Result<Option<i64>, AuthError> {
+    let row: Option<(i64,)> =
+        sqlx::query_as(r#"SELECT session_version FROM users WHERE user_id = $1"#)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to execute query: {:?}", e);
+                AuthError::Unexpected
+            })?;
+    Ok(row.map(|(session_version,)| session_version))
+}
+
+/// Sube la version y todas las sesiones del usuario dejan de valer, aunque alguien tenga una
+/// copia de la cookie
+#[tracing::instrument(name = "Revoke sessions", skip(pool))]
+pub async fn revoke_sessions(user_id: Uuid, pool: &SqlitePool) -> Result<(), AuthError> {
+    sqlx::query(r#"UPDATE users SET session_version = session_version + 1 WHERE user_id = $1"#)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            AuthError::Unexpected
+        })?;
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Las opciones de la app, en capas: primero `configuration/base.yaml`, despues el archivo del
+//! entorno (`local.yaml` o `production.yaml`, segun `APP_ENVIRONMENT`) y al final las variables
+//! de entorno que empiezan con `APP_`
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use secrecy::Secret;
+use sqlx::sqlite::SqliteConnectOptions;
+use std::time::Duration;
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct Settings {
+    pub application: ApplicationSettings,
+    pub database: DatabaseSettings,
+    pub email_client: EmailClientSettings,
+    pub admin: AdminSettings,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ApplicationSettings {
+    pub host: String,
+    pub port: u16,
+    /// como nos ven desde afuera, para armar los links que van en los emails
+    pub base_url: String,
+    /// la clave para firmar las cookies de sesion, de al menos 64 bytes
+    pub hmac_secret: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct DatabaseSettings {
+    /// el archivo de SQLite
+    pub path: String,
+    pub create_if_missing: bool,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct EmailClientSettings {
+    pub base_url: String,
+    pub sender_email: String,
+    pub authorization_token: Secret<String>,
+    pub timeout_milliseconds: u64,
+}
+
+/// El primer usuario de `/admin`, en lugar de tenerlo en una migracion con su password a la vista
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct AdminSettings {
+    pub username: String,
+    /// viene de `APP_ADMIN__PASSWORD`, sin ella no se crea nadie
+    pub password: Option<Secret<String>>,
+}
+
+impl ApplicationSettings {
+    /// Para pasarle a `TcpListener::bind`
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+impl DatabaseSettings {
+    pub fn connect_options(&self) -> SqliteConnectOptions {
+        SqliteConnectOptions::new()
+            .filename(&self.path)
+            .create_if_missing(self.create_if_missing)
+    }
+}
+
+impl EmailClientSettings {
+    pub fn sender(&self) -> Result<SubscriberEmail, String> {
+        SubscriberEmail::parse(self.sender_email.clone())
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+
+    pub fn client(&self) -> Result<EmailClient, String> {
+        Ok(EmailClient::new(
+            self.base_url.clone(),
+            self.sender()?,
+            self.authorization_token.clone(),
+            self.timeout(),
+        ))
+    }
+}
+
+/// Donde corre la app, elige el segundo archivo de configuracion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+    Local,
+    Production,
+}
+
+impl Environment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Environment::Local => "local",
+            Environment::Production => "production",
+        }
+    }
+}
+
+impl TryFrom<String> for Environment {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(Self::Local),
+            "production" => Ok(Self::Production),
+            other => Err(format!(
+                "{} is not a supported environment. Use either `local` or `production`.",
+                other
+            )),
+        }
+    }
+}
+
+/// Lee la configuracion desde el directorio `configuration` que esta donde se corre la app
+pub fn get_configuration() -> Result<Settings, config::ConfigError> {
+    let base_path = std::env::current_dir().expect("Failed to determine the current directory");
+    let configuration_directory = base_path.join("configuration");
+
+    // si no nos dicen nada estamos corriendo en la maquina de alguien
+    let environment: Environment = std::env::var("APP_ENVIRONMENT")
+        .unwrap_or_else(|_| "local".into())
+        .try_into()
+        .map_err(config::ConfigError::Message)?;
+
+    let settings = config::Config::builder()
+        .add_source(config::File::from(
+            configuration_directory.join("base.yaml"),
+        ))
+        .add_source(config::File::from(
+            configuration_directory.join(format!("{}.yaml", environment.as_str())),
+        ))
+        // `APP_APPLICATION__PORT=5000` pisa `application.port`
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__")
+                .try_parsing(true),
+        )
+        .build()?;
+
+    settings.try_deserialize::<Settings>()
+}
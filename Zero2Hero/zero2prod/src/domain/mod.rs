@@ -0,0 +1,9 @@
+//! Los datos de la app ya validados: si existe un `SubscriberName` es un nombre que se puede
+//! guardar, no hay que volver a chequearlo en ningun lado
+mod new_subscriber;
+mod subscriber_email;
+mod subscriber_name;
+
+pub use new_subscriber::NewSubscriber;
+pub use subscriber_email::SubscriberEmail;
+pub use subscriber_name::SubscriberName;
@@ -0,0 +1,7 @@
+use crate::domain::{SubscriberEmail, SubscriberName};
+
+#[derive(Debug)]
+pub struct NewSubscriber {
+    pub email: SubscriberEmail,
+    pub name: SubscriberName,
+}
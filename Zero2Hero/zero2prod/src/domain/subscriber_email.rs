@@ -0,0 +1,94 @@
+use std::fmt;
+use validator::validate_email;
+
+pub struct SubscriberEmail(String);
+
+impl SubscriberEmail {
+    /// Devuelve el motivo si el email no sirve, para mandarselo al cliente
+    pub fn parse(s: String) -> Result<Self, String> {
+        if s.trim().is_empty() {
+            return Err("the email cannot be empty".to_string());
+        }
+        if validate_email(&s) {
+            Ok(Self(s))
+        } else {
+            Err(format!("'{}' is not a valid email", s))
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberEmail {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// NOTE(elsuizo:2022-08-14): el email es un dato personal, asi no aparece en los logs cuando se
+/// loguea un `NewSubscriber` entero
+impl fmt::Debug for SubscriberEmail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SubscriberEmail([REDACTED])")
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_eq!(
+            SubscriberEmail::parse("".to_string()).unwrap_err(),
+            "the email cannot be empty"
+        );
+    }
+
+    #[test]
+    fn email_missing_at_symbol_is_rejected() {
+        assert_eq!(
+            SubscriberEmail::parse("ursuladomain.com".to_string()).unwrap_err(),
+            "'ursuladomain.com' is not a valid email"
+        );
+    }
+
+    #[test]
+    fn email_missing_subject_is_rejected() {
+        assert!(SubscriberEmail::parse("@domain.com".to_string()).is_err());
+    }
+
+    #[test]
+    fn debug_output_does_not_show_the_email() {
+        let email = SubscriberEmail::parse("ursula@domain.com".to_string()).unwrap();
+        assert_eq!(format!("{:?}", email), "SubscriberEmail([REDACTED])");
+    }
+
+    /// Emails validos inventados por `fake`
+    #[derive(Debug, Clone)]
+    struct ValidEmailFixture(String);
+
+    impl quickcheck::Arbitrary for ValidEmailFixture {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            // NOTE(elsuizo:2022-08-14): `fake` usa el rng de `rand` y quickcheck no nos deja
+            // usar el suyo, asi que armamos uno con una semilla que si sale de quickcheck
+            let mut rng = StdRng::seed_from_u64(u64::arbitrary(g));
+            Self(SafeEmail().fake_with_rng(&mut rng))
+        }
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
+        SubscriberEmail::parse(valid_email.0).is_ok()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn emails_without_an_at_symbol_are_rejected(valid_email: ValidEmailFixture) -> bool {
+        SubscriberEmail::parse(valid_email.0.replace('@', "")).is_err()
+    }
+}
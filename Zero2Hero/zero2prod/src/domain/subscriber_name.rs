@@ -0,0 +1,126 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Lo maximo que puede tener un nombre, en graphemes (lo que una persona cuenta como letras)
+pub const MAX_NAME_LENGTH: usize = 256;
+/// Caracteres que no dejamos pasar porque se usan en inyecciones de HTML o SQL
+const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+
+#[derive(Debug)]
+pub struct SubscriberName(String);
+
+impl SubscriberName {
+    /// Devuelve el motivo si el nombre no sirve, para mandarselo al cliente
+    pub fn parse(s: String) -> Result<Self, String> {
+        if s.trim().is_empty() {
+            return Err("the name cannot be empty".to_string());
+        }
+        // NOTE(elsuizo:2022-08-14): contamos graphemes y no bytes, asi un nombre con acentos o
+        // emojis tiene el mismo limite que uno en ASCII
+        if s.graphemes(true).count() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "the name cannot be longer than {} characters",
+                MAX_NAME_LENGTH
+            ));
+        }
+        if s.chars().any(|c| FORBIDDEN_CHARACTERS.contains(&c)) {
+            return Err(format!(
+                "the name cannot contain any of {}",
+                String::from_iter(FORBIDDEN_CHARACTERS)
+            ));
+        }
+        Ok(Self(s))
+    }
+}
+
+impl AsRef<str> for SubscriberName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_256_grapheme_long_name_is_valid() {
+        let name = "ё".repeat(MAX_NAME_LENGTH);
+        assert!(SubscriberName::parse(name).is_ok());
+    }
+
+    #[test]
+    fn a_name_longer_than_256_graphemes_is_rejected() {
+        let name = "a".repeat(MAX_NAME_LENGTH + 1);
+        assert_eq!(
+            SubscriberName::parse(name).unwrap_err(),
+            "the name cannot be longer than 256 characters"
+        );
+    }
+
+    #[test]
+    fn whitespace_only_names_are_rejected() {
+        for name in ["", " ", "\t\n "] {
+            assert_eq!(
+                SubscriberName::parse(name.to_string()).unwrap_err(),
+                "the name cannot be empty"
+            );
+        }
+    }
+
+    #[test]
+    fn names_containing_an_invalid_character_are_rejected() {
+        for c in FORBIDDEN_CHARACTERS {
+            let name = format!("Ursula {}", c);
+            assert_eq!(
+                SubscriberName::parse(name).unwrap_err(),
+                r#"the name cannot contain any of /()"<>\{}"#
+            );
+        }
+    }
+
+    #[test]
+    fn a_valid_name_is_parsed_successfully() {
+        let name = SubscriberName::parse("Ursula Le Guin".to_string()).unwrap();
+        assert_eq!(name.as_ref(), "Ursula Le Guin");
+    }
+
+    /// Nombres de cualquier largo valido hechos de letras que se permiten
+    #[derive(Debug, Clone)]
+    struct ValidNameFixture(String);
+
+    impl quickcheck::Arbitrary for ValidNameFixture {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            let letters = ['a', 'Z', 'ñ', 'é', '7', '-', '\'', 'ё', '語'];
+            let length = usize::arbitrary(g) % MAX_NAME_LENGTH + 1;
+            let mut name: Vec<char> = (0..length).map(|_| *g.choose(&letters).unwrap()).collect();
+            // un nombre valido no es solo espacios, pero puede tenerlos en el medio
+            if length > 2 {
+                name[1] = ' ';
+            }
+            Self(name.into_iter().collect())
+        }
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn valid_names_are_parsed_successfully(valid_name: ValidNameFixture) -> bool {
+        SubscriberName::parse(valid_name.0).is_ok()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn names_with_a_forbidden_character_are_always_rejected(
+        valid_name: ValidNameFixture,
+        position: usize,
+        forbidden: usize,
+    ) -> bool {
+        let mut name: Vec<char> = valid_name.0.chars().collect();
+        let position = position % (name.len() + 1);
+        name.insert(
+            position,
+            FORBIDDEN_CHARACTERS[forbidden % FORBIDDEN_CHARACTERS.len()],
+        );
+        SubscriberName::parse(name.into_iter().collect()).is_err()
+    }
+}
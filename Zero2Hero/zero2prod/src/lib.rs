@@ -1,25 +1,16 @@
-use actix_web::dev::Server;
-use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Responder};
-use std::net::TcpListener;
+pub mod authentication;
+pub mod configuration;
+pub mod domain;
+pub mod email_client;
+pub mod routes;
+pub mod session_state;
+pub mod startup;
+pub mod telemetry;
+pub mod utils;
 
-async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().finish()
-}
-
-async fn subscribe() -> HttpResponse {
-    HttpResponse::Ok().finish()
-}
-
-/// run method
-pub fn run(listener: TcpListener) -> Result<Server, std::io::Error> {
-    let server = HttpServer::new(|| {
-        App::new()
-            .route("/health_check", web::get().to(health_check))
-            // ponemos la nueva entrada en nuestra tabla de routes
-            .route("/subcriptions", web::get().to(subscribe))
-    })
-    .listen(listener)?
-    .run();
+use sqlx::SqlitePool;
 
-    Ok(server)
+/// Crea las tablas que todavia no existen, las migraciones van adentro del binario
+pub async fn migrate(pool: &SqlitePool) -> Result<(), sqlx::migrate::MigrateError> {
+    sqlx::migrate!("./migrations").run(pool).await
 }
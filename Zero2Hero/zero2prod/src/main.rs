@@ -1,10 +1,37 @@
 use std::error::Error;
 use std::net::TcpListener;
-use zero2prod::run;
+use zero2prod::authentication::create_first_admin;
+use zero2prod::configuration::get_configuration;
+use zero2prod::startup::{get_connection_pool, run};
+use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
-// #[tokio::main]
-fn main() -> Result<(), Box<dyn Error>> {
-    let _server = run(TcpListener::bind("127.0.0.1:0")?)?;
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
+    init_subscriber(subscriber);
+
+    let configuration = get_configuration().expect("Failed to read configuration");
+    let db_pool = get_connection_pool(&configuration.database).await?;
+    if let Some(password) = configuration.admin.password.clone() {
+        let created = create_first_admin(&configuration.admin.username, password, &db_pool)
+            .await
+            .map_err(|e| format!("Failed to create the first admin: {:?}", e))?;
+        if created {
+            tracing::info!("created the first admin, {}", configuration.admin.username);
+        }
+    }
+    let email_client = configuration.email_client.client()?;
+    let listener = TcpListener::bind(configuration.application.address())?;
+    tracing::info!("listening on http://{}", listener.local_addr()?);
+    // el server corre hasta que lo paremos, si no lo esperamos el programa termina enseguida
+    run(
+        listener,
+        db_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+    )?
+    .await?;
 
     Ok(())
 }
@@ -0,0 +1,54 @@
+use crate::authentication::UserId;
+use crate::utils::escape_html;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+pub async fn admin_dashboard(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<SqlitePool>,
+) -> HttpResponse {
+    let username = match get_username(**user_id, &pool).await {
+        Ok(username) => username,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    <p>Welcome {}!</p>
+    <p>Available actions:</p>
+    <ol>
+        <li><a href="/admin/password">Change password</a></li>
+        <li>
+            <form name="logoutForm" action="/admin/logout" method="post">
+                <input type="submit" value="Logout">
+            </form>
+        </li>
+    </ol>
+</body>
+</html>"#,
+            // el username lo eligio alguien, no es HTML nuestro
+            escape_html(&username)
+        ))
+}
+
+#[tracing::instrument(name = "Get username", skip(pool))]
+pub async fn get_username(user_id: Uuid, pool: &SqlitePool) -> Result<String, sqlx::Error> {
+    let (username,): (String,) = sqlx::query_as(r#"SELECT username FROM users WHERE user_id = $1"#)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(username)
+}
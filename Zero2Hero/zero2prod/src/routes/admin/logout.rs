@@ -0,0 +1,19 @@
+use crate::authentication::{revoke_sessions, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::see_other_with_flash;
+use actix_web::{web, HttpResponse};
+use sqlx::SqlitePool;
+
+/// Sale de todas las sesiones del usuario, no solo de esta: una copia de la cookie tampoco sirve
+#[tracing::instrument(name = "Log out", skip(session, pool))]
+pub async fn log_out(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<SqlitePool>,
+    session: TypedSession,
+) -> HttpResponse {
+    if revoke_sessions(**user_id, &pool).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    session.log_out();
+    see_other_with_flash(&session, "You have successfully logged out.", "/login")
+}
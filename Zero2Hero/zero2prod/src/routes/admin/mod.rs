@@ -0,0 +1,8 @@
+//! Lo que hay detras del login, todo pasa por `reject_anonymous_users`
+mod dashboard;
+mod logout;
+mod password;
+
+pub use dashboard::*;
+pub use logout::*;
+pub use password::*;
@@ -0,0 +1,118 @@
+use crate::authentication::{self, validate_credentials, AuthError, Credentials, UserId};
+use crate::routes::admin::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::{flash_html, see_other_with_flash};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::SqlitePool;
+
+/// Cuantos caracteres puede tener una password nueva
+const MIN_PASSWORD_LENGTH: usize = 12;
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+#[derive(serde::Deserialize)]
+pub struct PasswordFormData {
+    current_password: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+pub async fn change_password_form(session: TypedSession) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Change Password</title>
+</head>
+<body>
+    {}
+    <form action="/admin/password" method="post">
+        <label>Current password
+            <input type="password" placeholder="Enter current password" name="current_password">
+        </label>
+        <br>
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" placeholder="Type the new password again" name="new_password_check">
+        </label>
+        <br>
+        <button type="submit">Change password</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            flash_html(&session)
+        ))
+}
+
+/// Para cambiarla hay que saber la actual, la sesion sola no alcanza
+#[tracing::instrument(name = "Change password form", skip(form, pool, session))]
+pub async fn change_password(
+    form: web::Form<PasswordFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<SqlitePool>,
+    session: TypedSession,
+) -> HttpResponse {
+    let user_id = user_id.into_inner();
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        return see_other_with_flash(
+            &session,
+            "You entered two different new passwords - the field values must match.",
+            "/admin/password",
+        );
+    }
+    let length = form.new_password.expose_secret().chars().count();
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length) {
+        return see_other_with_flash(
+            &session,
+            &format!(
+                "The new password must have between {} and {} characters.",
+                MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+            ),
+            "/admin/password",
+        );
+    }
+
+    let username = match get_username(*user_id, &pool).await {
+        Ok(username) => username,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let credentials = Credentials {
+        username,
+        password: form.0.current_password,
+    };
+    match validate_credentials(credentials, &pool).await {
+        Ok(_) => {}
+        Err(AuthError::InvalidCredentials) => {
+            return see_other_with_flash(
+                &session,
+                "The current password is incorrect.",
+                "/admin/password",
+            );
+        }
+        Err(AuthError::Unexpected) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    // las otras sesiones se cierran, esta sigue con la version nueva
+    let session_version =
+        match authentication::change_password(*user_id, form.0.new_password, &pool).await {
+            Ok(session_version) => session_version,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+    if let Err(e) = session.insert_session_version(session_version) {
+        tracing::error!("Failed to store the session version: {:?}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    see_other_with_flash(
+        &session,
+        "Your password has been changed.",
+        "/admin/password",
+    )
+}
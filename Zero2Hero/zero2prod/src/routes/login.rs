@@ -0,0 +1,81 @@
+use crate::authentication::{get_session_version, validate_credentials, AuthError, Credentials};
+use crate::session_state::TypedSession;
+use crate::utils::{flash_html, see_other, see_other_with_flash};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use secrecy::Secret;
+use sqlx::SqlitePool;
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: Secret<String>,
+}
+
+pub async fn login_form(session: TypedSession) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {}
+    <form action="/login" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter Username" name="username">
+        </label>
+        <label>Password
+            <input type="password" placeholder="Enter Password" name="password">
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+</html>"#,
+            flash_html(&session)
+        ))
+}
+
+/// Si la password es la del usuario lo deja en la sesion y lo manda al dashboard, si no vuelve
+/// al form con un aviso
+#[tracing::instrument(
+    name = "Log in",
+    skip(form, pool, session),
+    fields(username = %form.username, user_id = tracing::field::Empty)
+)]
+pub async fn login(
+    form: web::Form<LoginFormData>,
+    pool: web::Data<SqlitePool>,
+    session: TypedSession,
+) -> HttpResponse {
+    let credentials = Credentials {
+        username: form.0.username,
+        password: form.0.password,
+    };
+    match validate_credentials(credentials, &pool).await {
+        Ok(user_id) => {
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            let session_version = match get_session_version(user_id, &pool).await {
+                Ok(Some(session_version)) => session_version,
+                _ => return HttpResponse::InternalServerError().finish(),
+            };
+            session.renew();
+            if let Err(e) = session
+                .insert_user_id(user_id)
+                .and_then(|_| session.insert_session_version(session_version))
+            {
+                tracing::error!("Failed to store the user in the session: {:?}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+            see_other("/admin/dashboard")
+        }
+        Err(AuthError::InvalidCredentials) => {
+            tracing::warn!("Rejected the credentials");
+            see_other_with_flash(&session, "Authentication failed", "/login")
+        }
+        Err(AuthError::Unexpected) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,13 @@
+mod admin;
+mod health_check;
+mod login;
+mod newsletters;
+mod subscriptions;
+mod subscriptions_confirm;
+
+pub use admin::*;
+pub use health_check::*;
+pub use login::*;
+pub use newsletters::*;
+pub use subscriptions::*;
+pub use subscriptions_confirm::*;
@@ -0,0 +1,99 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use actix_web::{web, HttpResponse};
+use sqlx::SqlitePool;
+
+/// El newsletter que llega en el body, en JSON
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+}
+
+/// Cada email va en HTML y en texto, el cliente de email del suscriptor elige cual mostrar
+#[derive(serde::Deserialize)]
+pub struct Content {
+    html: String,
+    text: String,
+}
+
+/// Un suscriptor confirmado con un email que sigue siendo valido
+struct ConfirmedSubscriber {
+    email: SubscriberEmail,
+}
+
+/// Le manda el newsletter a todos los suscriptores confirmados, los que no confirmaron no
+/// reciben nada. Si algun envio falla contesta 500 con cuantos fallaron, pero despues de
+/// intentar con todos
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(body, pool, email_client),
+    fields(title = %body.title)
+)]
+pub async fn publish_newsletter(
+    body: web::Json<BodyData>,
+    pool: web::Data<SqlitePool>,
+    email_client: web::Data<EmailClient>,
+) -> HttpResponse {
+    let subscribers = match get_confirmed_subscribers(&pool).await {
+        Ok(subscribers) => subscribers,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // NOTE(elsuizo:2026-10-19): un envio que falla no frena a los demas, seguimos con el resto y
+    // al final contamos cuantos no salieron
+    let mut failed = 0;
+    for subscriber in subscribers {
+        match subscriber {
+            Ok(subscriber) => {
+                if let Err(e) = email_client
+                    .send_email(
+                        &subscriber.email,
+                        &body.title,
+                        &body.content.html,
+                        &body.content.text,
+                    )
+                    .await
+                {
+                    tracing::error!("Failed to send the newsletter issue: {:?}", e);
+                    failed += 1;
+                }
+            }
+            // NOTE(elsuizo:2022-08-28): las reglas para validar los emails pueden cambiar
+            // despues de guardarlos, no dejamos que uno viejo frene a todos los demas. El motivo
+            // tiene el email, por eso no va en el log
+            Err(_) => {
+                tracing::warn!("Skipping a confirmed subscriber, their stored email is invalid");
+            }
+        }
+    }
+    if failed > 0 {
+        tracing::error!(
+            "The newsletter issue could not be sent to {} subscribers",
+            failed
+        );
+        return HttpResponse::InternalServerError().body(format!(
+            "Failed to send the newsletter issue to {} subscribers",
+            failed
+        ));
+    }
+    HttpResponse::Ok().finish()
+}
+
+#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
+async fn get_confirmed_subscribers(
+    pool: &SqlitePool,
+) -> Result<Vec<Result<ConfirmedSubscriber, String>>, sqlx::Error> {
+    let rows: Vec<(String,)> =
+        sqlx::query_as(r#"SELECT email FROM subscriptions WHERE status = 'confirmed'"#)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to execute query: {:?}", e);
+                e
+            })?;
+    let confirmed_subscribers = rows
+        .into_iter()
+        .map(|(email,)| SubscriberEmail::parse(email).map(|email| ConfirmedSubscriber { email }))
+        .collect();
+    Ok(confirmed_subscribers)
+}
@@ -0,0 +1,205 @@
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+use actix_web::{error, web, HttpResponse};
+use chrono::Utc;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use uuid::Uuid;
+
+/// Cuantos caracteres tiene cada token de confirmacion
+const TOKEN_LENGTH: usize = 25;
+
+/// Lo que llega en el body del form de `/subscriptions`, todavia sin validar
+#[derive(serde::Deserialize, Debug)]
+pub struct FormData {
+    /// NOTE(elsuizo:2022-08-07): el email es un dato personal, con `Secret` no aparece en los logs
+    /// aunque se loguee todo el form
+    email: Secret<String>,
+    name: String,
+}
+
+impl TryFrom<FormData> for NewSubscriber {
+    /// el motivo, para mandarselo al cliente
+    type Error = String;
+
+    fn try_from(form: FormData) -> Result<Self, Self::Error> {
+        let name = SubscriberName::parse(form.name)?;
+        let email = SubscriberEmail::parse(form.email.expose_secret().clone())?;
+        Ok(Self { email, name })
+    }
+}
+
+/// Guarda al suscriptor como pendiente y le manda el email con el link para confirmar
+#[tracing::instrument(
+    name = "Adding a new subscriber",
+    skip(form, pool, email_client, base_url),
+    fields(subscriber_name = %form.name)
+)]
+pub async fn subscribe(
+    form: web::Form<FormData>,
+    pool: web::Data<SqlitePool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let validation = tracing::info_span!("Validating the new subscriber");
+    let new_subscriber = match validation.in_scope(|| NewSubscriber::try_from(form.0)) {
+        Ok(new_subscriber) => new_subscriber,
+        Err(reason) => {
+            // el motivo puede tener el email, solo se lo contamos al cliente
+            tracing::warn!("Rejected the new subscriber");
+            return HttpResponse::BadRequest().body(reason);
+        }
+    };
+
+    // el suscriptor y su token se guardan juntos o ninguno de los dos
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let subscriber_id = match insert_subscriber(&new_subscriber, &mut transaction).await {
+        Ok(subscriber_id) => subscriber_id,
+        // el email es unico en la tabla, que ya este no es un error nuestro
+        Err(e) if is_unique_violation(&e) => {
+            return HttpResponse::Conflict().body("This email is already subscribed")
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let subscription_token = generate_subscription_token();
+    if store_token(&mut transaction, subscriber_id, &subscription_token)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // NOTE(elsuizo:2026-10-19): el email se manda antes del commit, si falla la transaccion se
+    // descarta (rollback) y no queda un pendiente que despues bloquee a la persona con un 409
+    if send_confirmation_email(
+        &email_client,
+        new_subscriber,
+        &base_url.0,
+        &subscription_token,
+    )
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().finish()
+}
+
+#[tracing::instrument(
+    name = "Saving new subscriber details in the database",
+    skip(transaction)
+)]
+async fn insert_subscriber(
+    new_subscriber: &NewSubscriber,
+    transaction: &mut Transaction<'_, Sqlite>,
+) -> Result<Uuid, sqlx::Error> {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        "#,
+    )
+    .bind(subscriber_id)
+    .bind(new_subscriber.email.as_ref())
+    .bind(new_subscriber.name.as_ref())
+    .bind(Utc::now())
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(subscriber_id)
+}
+
+#[tracing::instrument(
+    name = "Store subscription token in the database",
+    skip(transaction, subscription_token)
+)]
+async fn store_token(
+    transaction: &mut Transaction<'_, Sqlite>,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        VALUES ($1, $2)
+        "#,
+    )
+    .bind(subscription_token)
+    .bind(subscriber_id)
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send a confirmation email to a new subscriber",
+    skip(email_client, new_subscriber, base_url, subscription_token)
+)]
+async fn send_confirmation_email(
+    email_client: &EmailClient,
+    new_subscriber: NewSubscriber,
+    base_url: &str,
+    subscription_token: &str,
+) -> Result<(), reqwest::Error> {
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        base_url, subscription_token
+    );
+    let html_body = format!(
+        "Welcome to our newsletter!<br />\
+         Click <a href=\"{}\">here</a> to confirm your subscription.",
+        confirmation_link
+    );
+    let plain_body = format!(
+        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
+        confirmation_link
+    );
+    email_client
+        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to send the confirmation email: {:?}", e);
+            e
+        })
+}
+
+/// `true` si el insert fallo porque el valor de una columna `UNIQUE` ya estaba
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|db_error| db_error.is_unique_violation())
+}
+
+/// Un token al azar, con letras y numeros para que se pueda poner tal cual en una URL
+fn generate_subscription_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(TOKEN_LENGTH)
+        .collect()
+}
+
+/// Si el form no se puede deserializar (por ejemplo si falta un campo) contestamos 400 con el
+/// motivo, en lugar del mensaje generico de actix
+pub fn form_config() -> web::FormConfig {
+    web::FormConfig::default().error_handler(|err, _request| {
+        let response = HttpResponse::BadRequest().body(err.to_string());
+        error::InternalError::from_response(err, response).into()
+    })
+}
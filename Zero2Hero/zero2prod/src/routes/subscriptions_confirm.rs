@@ -0,0 +1,62 @@
+use actix_web::{web, HttpResponse};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// La query del link que mandamos en el email de bienvenida
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscription_token: String,
+}
+
+/// Si falta el token actix contesta 400 solo, si el token no es de nadie contestamos 401
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<SqlitePool>,
+) -> HttpResponse {
+    let subscriber_id =
+        match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
+            Ok(subscriber_id) => subscriber_id,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+    match subscriber_id {
+        None => HttpResponse::Unauthorized().finish(),
+        Some(subscriber_id) => {
+            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            HttpResponse::Ok().finish()
+        }
+    }
+}
+
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool))]
+async fn confirm_subscriber(pool: &SqlitePool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#)
+        .bind(subscriber_id)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get subscriber_id from token", skip(pool, subscription_token))]
+async fn get_subscriber_id_from_token(
+    pool: &SqlitePool,
+    subscription_token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result: Option<(Uuid,)> = sqlx::query_as(
+        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+    )
+    .bind(subscription_token)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|(subscriber_id,)| subscriber_id))
+}
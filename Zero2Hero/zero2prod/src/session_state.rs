@@ -0,0 +1,68 @@
+//! Lo que guardamos en la sesion (una cookie firmada), con nombres en lugar de las claves sueltas
+//!
+//! NOTE(elsuizo:2022-09-11): la cookie la tiene el navegador y no la podemos borrar desde aca,
+//! por eso guarda la `session_version` del usuario y `reject_anonymous_users` la compara con la de
+//! la base en cada request
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use std::future::{ready, Ready};
+use uuid::Uuid;
+
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_VERSION_KEY: &'static str = "session_version";
+    const FLASH_KEY: &'static str = "flash";
+
+    /// Una sesion nueva al iniciar sesion, asi no sirve una cookie que alguien consiguio antes
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+
+    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)
+    }
+
+    pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::USER_ID_KEY)
+    }
+
+    /// La `session_version` que tenia el usuario cuando entro, la sesion vale mientras sea la
+    /// misma que en la base
+    pub fn insert_session_version(&self, session_version: i64) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_VERSION_KEY, session_version)
+    }
+
+    pub fn get_session_version(&self) -> Result<Option<i64>, SessionGetError> {
+        self.0.get(Self::SESSION_VERSION_KEY)
+    }
+
+    /// Se olvida de todo lo que habia
+    ///
+    /// NOTE(elsuizo:2022-09-04): no usamos `purge` porque borra la cookie entera y despues no se
+    /// puede dejar el mensaje de que salio bien
+    pub fn log_out(&self) {
+        self.0.clear();
+        self.0.renew();
+    }
+
+    /// Un mensaje para mostrar en la proxima pagina, una sola vez
+    pub fn set_flash(&self, message: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::FLASH_KEY, message)
+    }
+
+    pub fn take_flash(&self) -> Option<String> {
+        self.0.remove_as(Self::FLASH_KEY).and_then(Result::ok)
+    }
+}
+
+impl FromRequest for TypedSession {
+    type Error = <Session as FromRequest>::Error;
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
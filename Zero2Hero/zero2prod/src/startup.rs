@@ -0,0 +1,77 @@
+use crate::authentication::reject_anonymous_users;
+use crate::configuration::DatabaseSettings;
+use crate::email_client::EmailClient;
+use crate::routes::{
+    admin_dashboard, change_password, change_password_form, confirm, form_config, health_check,
+    log_out, login, login_form, publish_newsletter, subscribe,
+};
+use actix_session::storage::CookieSessionStore;
+use actix_session::SessionMiddleware;
+use actix_web::cookie::Key;
+use actix_web::dev::Server;
+use actix_web::middleware::from_fn;
+use actix_web::{web, App, HttpServer};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::SqlitePool;
+use std::net::TcpListener;
+use tracing_actix_web::TracingLogger;
+
+/// La direccion publica de la app. Va en su propio type porque `web::Data` busca por type y un
+/// `String` suelto no dice nada
+pub struct ApplicationBaseUrl(pub String);
+
+/// Abre la base de la configuracion y le aplica las migraciones que falten
+pub async fn get_connection_pool(settings: &DatabaseSettings) -> Result<SqlitePool, sqlx::Error> {
+    let db_pool = SqlitePool::connect_with(settings.connect_options()).await?;
+    crate::migrate(&db_pool).await?;
+    Ok(db_pool)
+}
+
+/// run method
+pub fn run(
+    listener: TcpListener,
+    db_pool: SqlitePool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+) -> Result<Server, std::io::Error> {
+    // con la misma clave firmamos las cookies de sesion, si alguien la toca deja de valer
+    let secret_key = Key::try_from(hmac_secret.expose_secret().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // `web::Data` es un `Arc`, cada worker de actix se lleva una copia del mismo pool
+    let db_pool = web::Data::new(db_pool);
+    let email_client = web::Data::new(email_client);
+    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(SessionMiddleware::new(
+                CookieSessionStore::default(),
+                secret_key.clone(),
+            ))
+            // cada request tiene su span con un `request_id` nuevo, los logs de adentro lo llevan
+            .wrap(TracingLogger::default())
+            .app_data(form_config())
+            .route("/health_check", web::get().to(health_check))
+            // ponemos la nueva entrada en nuestra tabla de routes
+            .route("/subscriptions", web::post().to(subscribe))
+            .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/login", web::get().to(login_form))
+            .route("/login", web::post().to(login))
+            .service(
+                web::scope("/admin")
+                    .wrap(from_fn(reject_anonymous_users))
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/password", web::get().to(change_password_form))
+                    .route("/password", web::post().to(change_password))
+                    .route("/logout", web::post().to(log_out))
+                    .route("/newsletters", web::post().to(publish_newsletter)),
+            )
+            .app_data(db_pool.clone())
+            .app_data(email_client.clone())
+            .app_data(base_url.clone())
+    })
+    .listen(listener)?
+    .run();
+
+    Ok(server)
+}
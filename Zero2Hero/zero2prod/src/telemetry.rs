@@ -0,0 +1,37 @@
+//! Los logs de la app: cada request tiene su span (con un `request_id`) y todo sale como JSON en
+//! el formato de bunyan, una linea por evento
+use tracing::subscriber::set_global_default;
+use tracing::Subscriber;
+use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
+use tracing_log::LogTracer;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
+
+/// Arma el subscriber que escribe en `sink`. `env_filter` es el nivel que usamos si no esta
+/// `RUST_LOG`
+///
+/// NOTE(elsuizo:2022-08-07): devolvemos `impl Subscriber` para no escribir el type entero, que es
+/// enorme
+pub fn get_subscriber<Sink>(
+    name: String,
+    env_filter: String,
+    sink: Sink,
+) -> impl Subscriber + Send + Sync
+where
+    Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let env_filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let formatting_layer = BunyanFormattingLayer::new(name, sink);
+    Registry::default()
+        .with(env_filter)
+        .with(JsonStorageLayer)
+        .with(formatting_layer)
+}
+
+/// Registra el subscriber para todo el proceso, solo se puede llamar una vez
+pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
+    // los logs de actix (y de todo lo que use `log`) tambien pasan por tracing
+    LogTracer::init().expect("Failed to set logger");
+    set_global_default(subscriber).expect("Failed to set subscriber");
+}
@@ -0,0 +1,64 @@
+use crate::session_state::TypedSession;
+use actix_web::http::header::LOCATION;
+use actix_web::HttpResponse;
+
+/// Un 303 que manda al navegador a `location` con un GET, lo que se usa despues de un form
+pub fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish()
+}
+
+/// Como `see_other` pero dejando `message` para mostrar en la pagina a la que va
+pub fn see_other_with_flash(session: &TypedSession, message: &str, location: &str) -> HttpResponse {
+    if let Err(e) = session.set_flash(message) {
+        tracing::error!("Failed to store the flash message: {:?}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    see_other(location)
+}
+
+/// Para meter texto que no escribimos nosotros (por ejemplo un username) adentro del HTML sin que
+/// el navegador lo tome como tags
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// El mensaje que dejo la pagina anterior, como un parrafo de HTML
+///
+/// NOTE(elsuizo:2022-09-04): los mensajes los escribimos nosotros, nunca llevan lo que manda el
+/// usuario, por eso no hace falta escaparlos
+pub fn flash_html(session: &TypedSession) -> String {
+    session
+        .take_flash()
+        .map(|message| format!("<p><i>{}</i></p>", message))
+        .unwrap_or_default()
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_is_escaped() {
+        assert_eq!(
+            escape_html(r#"<script>alert("&'")</script>"#),
+            "&lt;script&gt;alert(&quot;&amp;&#x27;&quot;)&lt;/script&gt;"
+        );
+        assert_eq!(escape_html("le guin"), "le guin");
+    }
+}
@@ -0,0 +1,84 @@
+//! tests/admin.rs
+//!
+//! El dashboard solo se ve con sesion, y salir la cierra (tambien las copias de la cookie)
+mod common;
+use common::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.get_admin_dashboard().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logout_clears_session_state() {
+    // arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}!", app.test_user.username)));
+
+    // act
+    let response = app.post_logout().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>You have successfully logged out.</i></p>"));
+
+    // ya no hay nadie en la sesion
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_log_out() {
+    let app = spawn_app().await;
+
+    let response = app.post_logout().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_username_is_escaped_in_the_dashboard() {
+    let app = spawn_app().await;
+    app.login().await;
+    sqlx::query("UPDATE users SET username = $1 WHERE user_id = $2")
+        .bind("<script>alert('hi')</script>")
+        .bind(app.test_user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let html_page = app.get_admin_dashboard_html().await;
+
+    assert!(html_page.contains("Welcome &lt;script&gt;alert(&#x27;hi&#x27;)&lt;/script&gt;!"));
+    assert!(!html_page.contains("<script>"));
+}
+
+#[tokio::test]
+async fn logout_revokes_copies_of_the_session_cookie() {
+    let app = spawn_app().await;
+    let copied_cookie = app.login_and_copy_cookie().await;
+    let response = app
+        .get_with_cookie("/admin/dashboard", &copied_cookie)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // act: el usuario sale desde su navegador
+    app.login().await;
+    app.post_logout().await;
+
+    // assert: la cookie vieja sigue bien firmada pero ya no sirve
+    let response = app
+        .get_with_cookie("/admin/dashboard", &copied_cookie)
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
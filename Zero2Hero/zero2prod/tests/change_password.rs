@@ -0,0 +1,166 @@
+//! tests/change_password.rs
+//!
+//! Cambiar la password desde `/admin/password`
+use uuid::Uuid;
+
+mod common;
+use common::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_change_password_form() {
+    let app = spawn_app().await;
+
+    let response = app.get_change_password().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_your_password() {
+    // arrange
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn new_password_fields_must_match() {
+    // arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    // act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains(
+        "<p><i>You entered two different new passwords - the field values must match.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn new_password_must_have_a_reasonable_length() {
+    // arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    for new_password in ["too-short".to_string(), "a".repeat(129)] {
+        // act
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "current_password": &app.test_user.password,
+                "new_password": &new_password,
+                "new_password_check": &new_password,
+            }))
+            .await;
+
+        // assert
+        assert_is_redirect_to(&response, "/admin/password");
+        let html_page = app.get_change_password_html().await;
+        assert!(html_page
+            .contains("<p><i>The new password must have between 12 and 128 characters.</i></p>"));
+    }
+}
+
+#[tokio::test]
+async fn current_password_must_be_valid() {
+    // arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>The current password is incorrect.</i></p>"));
+}
+
+#[tokio::test]
+async fn changing_password_works() {
+    // arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>Your password has been changed.</i></p>"));
+
+    // assert: salimos y la vieja ya no sirve, la nueva si
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn changing_password_revokes_the_other_sessions() {
+    let app = spawn_app().await;
+    let copied_cookie = app.login_and_copy_cookie().await;
+    app.login().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    // la sesion que la cambio sigue, la otra no
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .get_with_cookie("/admin/dashboard", &copied_cookie)
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
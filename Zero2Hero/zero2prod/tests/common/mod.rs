@@ -0,0 +1,303 @@
+//! Helpers compartidos por los tests de integracion: levantan la app con su propia base en
+//! memoria y un server de mentira en el lugar de la API de emails
+#![allow(dead_code)]
+
+use secrecy::{ExposeSecret, Secret};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use std::net::TcpListener;
+use std::sync::LazyLock;
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::MockServer;
+use zero2prod::authentication::compute_password_hash;
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::email_client::EmailClient;
+use zero2prod::startup::run;
+use zero2prod::telemetry::{get_subscriber, init_subscriber};
+
+/// El subscriber de los logs se registra una sola vez para todos los tests. Los logs solo se
+/// muestran con `TEST_LOG=true cargo test`, si no se tiran
+static TRACING: LazyLock<()> = LazyLock::new(|| {
+    let default_filter_level = "info".to_string();
+    let subscriber_name = "test".to_string();
+    if std::env::var("TEST_LOG").is_ok() {
+        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        init_subscriber(subscriber);
+    } else {
+        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        init_subscriber(subscriber);
+    }
+});
+
+/// La app corriendo en background, su base de datos para mirar lo que escribio y el server que
+/// recibe los emails que manda
+pub struct TestApp {
+    pub address: String,
+    pub db_pool: SqlitePool,
+    pub email_server: MockServer,
+    pub test_user: TestUser,
+    /// guarda las cookies como un navegador y no sigue las redirecciones, asi las vemos
+    pub api_client: reqwest::Client,
+}
+
+/// Un admin con una password que conocemos, cada app tiene el suyo
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, pool: &SqlitePool) {
+        let password_hash = compute_password_hash(Secret::new(self.password.clone())).unwrap();
+        sqlx::query("INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)")
+            .bind(self.user_id)
+            .bind(&self.username)
+            .bind(password_hash.expose_secret())
+            .execute(pool)
+            .await
+            .expect("Failed to store the test user");
+    }
+}
+
+/// Los dos links (el del HTML y el del texto) de un email de confirmacion
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
+impl TestApp {
+    pub async fn post_subscriptions(&self, body: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/newsletters", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_login<Body: serde::Serialize>(&self, body: &Body) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Inicia sesion con el `test_user`
+    pub async fn login(&self) {
+        let response = self
+            .post_login(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password,
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+
+    /// Inicia sesion con el `test_user` desde otro cliente y devuelve su cookie, como si alguien
+    /// se la hubiera copiado
+    pub async fn login_and_copy_cookie(&self) -> String {
+        let response = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+            .post(format!("{}/login", &self.address))
+            .form(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password,
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_is_redirect_to(&response, "/admin/dashboard");
+        let cookie = response
+            .headers()
+            .get("Set-Cookie")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        // solo `nombre=valor`, lo demas son los atributos de la cookie
+        cookie.split(';').next().unwrap().to_string()
+    }
+
+    /// Un GET con la cookie que le pasamos en lugar de las que guarda `api_client`
+    pub async fn get_with_cookie(&self, path: &str, cookie: &str) -> reqwest::Response {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+            .get(format!("{}{}", &self.address, path))
+            .header("Cookie", cookie)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.get_html("/login").await
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.get("/admin/dashboard").await
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_html("/admin/dashboard").await
+    }
+
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.get("/admin/password").await
+    }
+
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_html("/admin/password").await
+    }
+
+    pub async fn post_change_password<Body: serde::Serialize>(
+        &self,
+        body: &Body,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    async fn get(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    async fn get_html(&self, path: &str) -> String {
+        self.get(path).await.text().await.unwrap()
+    }
+
+    /// Saca los links de confirmacion del request que le llego a la API de emails
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let get_link = |s: &str| {
+            let links: Vec<_> = linkify::LinkFinder::new()
+                .links(s)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .collect();
+            assert_eq!(links.len(), 1);
+            let link = reqwest::Url::parse(links[0].as_str()).unwrap();
+            // que no mandemos a nadie a otro lado
+            assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+            link
+        };
+        ConfirmationLinks {
+            html: get_link(body["HtmlBody"].as_str().unwrap()),
+            plain_text: get_link(body["TextBody"].as_str().unwrap()),
+        }
+    }
+}
+
+// lanzamos la aplicacion en el backgroud de alguna manera
+//
+pub async fn spawn_app() -> TestApp {
+    LazyLock::force(&TRACING);
+    launch().await
+}
+
+/// Como `spawn_app` pero sin registrar el subscriber de los logs, para los tests que usan el suyo
+pub async fn launch() -> TestApp {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bin random port");
+    // guardamos el port que nos ha asignado por el Sistema operativo
+    let port = listener.local_addr().unwrap().port();
+    let address = format!("http://127.0.0.1:{}", port);
+    let db_pool = test_database().await;
+    let email_server = MockServer::start().await;
+    let email_client = EmailClient::new(
+        email_server.uri(),
+        SubscriberEmail::parse("newsletter@example.com".to_string()).unwrap(),
+        Secret::new("my-secret-token".to_string()),
+        Duration::from_millis(200),
+    );
+    // cada app firma sus cookies con una clave distinta (de 72 bytes, hacen falta 64)
+    let hmac_secret = Secret::new(Uuid::new_v4().to_string().repeat(2));
+    let server = run(
+        listener,
+        db_pool.clone(),
+        email_client,
+        address.clone(),
+        hmac_secret,
+    )
+    .expect("Failed to bin address");
+    // lanzamos el server como un proceso en el backgroud
+    // tokio::spawn retorna un handle para spamear un Future
+    // pero aca no lo usamos por ahora...
+    tokio::spawn(server);
+    let test_user = TestUser::generate();
+    test_user.store(&db_pool).await;
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    TestApp {
+        address,
+        db_pool,
+        email_server,
+        test_user,
+        api_client,
+    }
+}
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
+/// Cada test tiene su propia base en memoria, ya migrada
+///
+/// NOTE(elsuizo:2022-07-24): una base `:memory:` vive lo que vive su conexion, por eso el pool
+/// tiene una sola y nunca la cierra
+async fn test_database() -> SqlitePool {
+    let db_pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .min_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to create the test database");
+    zero2prod::migrate(&db_pool)
+        .await
+        .expect("Failed to migrate the test database");
+    db_pool
+}
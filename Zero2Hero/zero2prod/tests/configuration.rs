@@ -0,0 +1,62 @@
+//! tests/configuration.rs
+//!
+//! Las capas de la configuracion: los archivos de `configuration` y las variables `APP_*`
+
+use secrecy::ExposeSecret;
+use zero2prod::configuration::get_configuration;
+
+// NOTE(elsuizo:2022-08-02): todo va en un solo test porque cambia las variables de entorno del
+// proceso, y los tests de un mismo archivo corren en paralelo
+#[test]
+fn configuration_is_layered() {
+    // sin `APP_ENVIRONMENT` es la configuracion local
+    let settings = get_configuration().expect("Failed to read configuration");
+    assert_eq!(settings.application.address(), "127.0.0.1:8000");
+    assert_eq!(settings.database.path, "zero2prod.db");
+    assert!(settings.database.create_if_missing);
+
+    assert_eq!(settings.application.base_url, "http://127.0.0.1:8000");
+    assert_eq!(settings.email_client.base_url, "localhost");
+    // el primer admin no tiene password si no viene de `APP_ADMIN__PASSWORD`
+    assert_eq!(settings.admin.username, "admin");
+    assert!(settings.admin.password.is_none());
+
+    // en produccion ni la direccion publica ni la clave de las sesiones tienen default
+    std::env::set_var("APP_ENVIRONMENT", "production");
+    assert!(get_configuration().is_err());
+    std::env::set_var("APP_APPLICATION__BASE_URL", "https://zero2prod.example.com");
+    assert!(get_configuration().is_err());
+
+    // el archivo del entorno pisa a `base.yaml`
+    std::env::set_var("APP_APPLICATION__HMAC_SECRET", "a".repeat(64));
+    let settings = get_configuration().expect("Failed to read configuration");
+    assert_eq!(settings.application.address(), "0.0.0.0:8000");
+    assert!(!settings.database.create_if_missing);
+    assert_eq!(
+        settings.application.base_url,
+        "https://zero2prod.example.com"
+    );
+    assert_eq!(
+        settings.email_client.base_url,
+        "https://api.postmarkapp.com"
+    );
+
+    // y las variables pisan a los dos
+    std::env::set_var("APP_APPLICATION__PORT", "5000");
+    std::env::set_var("APP_DATABASE__PATH", "/var/lib/zero2prod/subscriptions.db");
+    std::env::set_var("APP_ADMIN__PASSWORD", "the first admin password");
+    let settings = get_configuration().expect("Failed to read configuration");
+    assert_eq!(
+        settings.admin.password.unwrap().expose_secret(),
+        "the first admin password"
+    );
+    assert_eq!(settings.application.address(), "0.0.0.0:5000");
+    assert_eq!(
+        settings.database.path,
+        "/var/lib/zero2prod/subscriptions.db"
+    );
+
+    std::env::set_var("APP_ENVIRONMENT", "staging");
+    let error = get_configuration().unwrap_err().to_string();
+    assert!(error.contains("staging is not a supported environment"));
+}
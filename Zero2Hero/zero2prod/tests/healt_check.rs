@@ -2,14 +2,27 @@
 
 // `tokio::test` es el equivalente de testeo para el tokio::main
 
-use std::net::TcpListener;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+mod common;
+use common::{spawn_app, TestApp};
+
+/// La API de emails contesta bien a todo lo que le llega
+async fn accept_emails(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
 
 #[tokio::test]
 async fn healt_check_works() {
-    let address = spawn_app();
+    let address = spawn_app().await.address;
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("{}/health_check", &address))
+        .get(format!("{}/health_check", &address))
         .send()
         .await
         .expect("Failed to executed");
@@ -22,62 +35,186 @@ async fn healt_check_works() {
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
     // arrange
-    let app_address = spawn_app();
-    let client = reqwest::Client::new();
+    let app = spawn_app().await;
+    accept_emails(&app).await;
 
     // act
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-    let response = client
-        .post(&format!("{}/subcriptions", &app_address))
-        .header("Content-Type", "application/x-www-form-urlencoded")
-        .body(body)
-        .send()
-        .await
-        .expect("Failed to execute request");
+    let response = app.post_subscriptions(body).await;
 
     assert_eq!(200, response.status().as_u16());
+
+    let (email, name, status): (String, String, String) =
+        sqlx::query_as("SELECT email, name, status FROM subscriptions")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch saved subscription");
+    assert_eq!(email, "ursula_le_guin@gmail.com");
+    assert_eq!(name, "le guin");
+    // hasta que use el link del email
+    assert_eq!(status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_with_a_link() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    // los dos formatos del email llevan el mismo link
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
+
+#[tokio::test]
+async fn subscribe_fails_if_the_confirmation_email_cannot_be_sent() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    assert_eq!(500, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_again_after_the_confirmation_email_failed() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    accept_emails(&app).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    // act
+    let mut statuses = Vec::new();
+    for _ in 0..2 {
+        let response = app.post_subscriptions(body).await;
+        statuses.push(response.status().as_u16());
+    }
+
+    // el primer intento no deja nada guardado, asi que el segundo no es un 409
+    assert_eq!(statuses, [500, 200]);
+    let (saved,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscriptions");
+    assert_eq!(saved, 1);
+    // y el email del segundo intento trae un link que funciona
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
 }
 
-// #[tokio::test]
-// async fn subscribe_returns_a_400_when_data_is_missing() {
-//     // arrange
-//     let app_address = spawn_app();
-//     let client = reqwest::Client::new();
-//     let test_cases = vec![
-//         ("name=le%20guin", "missing the mail"),
-//         ("email=ursula_le_guin%40gmail.com", "missing the name"),
-//         ("", "missing both name and email"),
-//     ];
-//
-//     for (invalid_body, error_message) in test_cases {
-//         // act
-//         let response = client
-//             .post(&format!("{}/subcriptions", &app_address))
-//             .header("Content-Type", "application/x-www-form-urlencoded")
-//             .body(invalid_body)
-//             .send()
-//             .await
-//             .expect("Failed to execute request");
-//
-//         assert_eq!(
-//             400,
-//             response.status().as_u16(),
-//             // mensaje adicional que ponemos para que sea mas claro todo
-//             "The API did not fail with 400 Bad Request when the payload was {}",
-//             error_message
-//         );
-//     }
-// }
-// lanzamos la aplicacion en el backgroud de alguna manera
-//
-fn spawn_app() -> String {
-    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bin random port");
-    // guardamos el port que nos ha asignado por el Sistema operativo
-    let port = listener.local_addr().unwrap().port();
-    let server = zero2prod::run(listener).expect("Failed to bin address");
-    // lanzamos el server como un proceso en el backgroud
-    // tokio::spawn retorna un handle para spamear un Future
-    // pero aca no lo usamos por ahora...
-    let _ = tokio::spawn(server);
-    format!("http://127.0.0.1:{}", port)
+#[tokio::test]
+async fn subscribe_twice_with_the_same_email_returns_a_409() {
+    // arrange
+    let app = spawn_app().await;
+    accept_emails(&app).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    // act
+    let mut statuses = Vec::new();
+    for _ in 0..2 {
+        let response = app.post_subscriptions(body).await;
+        statuses.push(response.status().as_u16());
+    }
+
+    // el email es unico en la tabla, asi que la segunda no se guarda y se avisa con un 409
+    assert_eq!(statuses, [200, 409]);
+    let (saved,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscriptions");
+    assert_eq!(saved, 1);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_when_data_is_missing() {
+    // arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("name=le%20guin", "missing the mail"),
+        ("email=ursula_le_guin%40gmail.com", "missing the name"),
+        ("", "missing both name and email"),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        // act
+        let response = app.post_subscriptions(invalid_body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            // mensaje adicional que ponemos para que sea mas claro todo
+            "The API did not fail with 400 Bad Request when the payload was {}",
+            error_message
+        );
+        // el body dice que es lo que fallo
+        assert!(!response.text().await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
+    // arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            "name=&email=ursula_le_guin%40gmail.com",
+            "empty name",
+            "the name cannot be empty",
+        ),
+        (
+            "name=Ursula&email=",
+            "empty email",
+            "the email cannot be empty",
+        ),
+        (
+            "name=Ursula&email=definitely-not-an-email",
+            "invalid email",
+            "'definitely-not-an-email' is not a valid email",
+        ),
+        (
+            "name=Ursula%20%3Cscript%3E&email=ursula_le_guin%40gmail.com",
+            "forbidden characters in the name",
+            r#"the name cannot contain any of /()"<>\{}"#,
+        ),
+    ];
+
+    for (invalid_body, description, expected) in test_cases {
+        // act
+        let response = app.post_subscriptions(invalid_body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload was {}",
+            description
+        );
+        assert_eq!(expected, response.text().await.unwrap());
+    }
 }
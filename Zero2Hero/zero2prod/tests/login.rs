@@ -0,0 +1,99 @@
+//! tests/login.rs
+//!
+//! El form de `/login` y la sesion que queda despues
+mod common;
+use common::{assert_is_redirect_to, spawn_app};
+use secrecy::Secret;
+use zero2prod::authentication::create_first_admin;
+
+#[tokio::test]
+async fn an_error_flash_message_is_set_on_failure() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    let response = app.post_login(&login_body).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+
+    // seguimos la redireccion
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed</i></p>"));
+
+    // y si recargamos el mensaje ya no esta
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed"));
+}
+
+#[tokio::test]
+async fn a_wrong_password_for_an_existing_user_is_rejected() {
+    let app = spawn_app().await;
+
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "not-the-right-password"
+    });
+    let response = app.post_login(&login_body).await;
+
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    app.login().await;
+
+    // assert
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}!", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn the_first_admin_can_log_in() {
+    let app = spawn_app().await;
+    // como en una base recien creada
+    sqlx::query("DELETE FROM users")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let password = || Secret::new("the first admin password".to_string());
+    assert!(create_first_admin("admin", password(), &app.db_pool)
+        .await
+        .unwrap());
+
+    let login_body = serde_json::json!({
+        "username": "admin",
+        "password": "the first admin password"
+    });
+    let response = app.post_login(&login_body).await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_first_admin_is_only_created_once() {
+    // ya hay un usuario, el de los tests
+    let app = spawn_app().await;
+
+    let password = Secret::new("another password".to_string());
+    assert!(!create_first_admin("admin", password, &app.db_pool)
+        .await
+        .unwrap());
+
+    let login_body = serde_json::json!({
+        "username": "admin",
+        "password": "another password"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,220 @@
+//! tests/newsletters.rs
+//!
+//! Los newsletters solo les llegan a los suscriptores que confirmaron
+use wiremock::matchers::{any, body_string_contains, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+mod common;
+use common::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+
+/// Un suscriptor que todavia no uso el link, nos devuelve los links del email de bienvenida
+async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    // el mock solo vive en esta funcion, no se mezcla con lo que espera cada test
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_links = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+fn newsletter() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        // no tiene que salir ningun email
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.login().await;
+    let response = app.post_newsletters(newsletter()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.login().await;
+    let response = app.post_newsletters(newsletter()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_with_invalid_stored_emails_are_skipped() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    // uno que se guardo antes de que cambiaran las reglas para validar los emails
+    sqlx::query(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'not-an-email', 'old subscriber', $2, 'confirmed')
+        "#,
+    )
+    .bind(uuid::Uuid::new_v4())
+    .bind(chrono::Utc::now())
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.login().await;
+    let response = app.post_newsletters(newsletter()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_failed_send_does_not_stop_the_rest_of_the_newsletter() {
+    // arrange
+    let app = spawn_app().await;
+    for email in ["ada@example.com", "grace@example.com", "alan@example.com"] {
+        sqlx::query(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, $2, 'subscriber', $3, 'confirmed')
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4())
+        .bind(email)
+        .bind(chrono::Utc::now())
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    // el email de grace no sale, los otros dos si
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_string_contains("grace@example.com"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.login().await;
+    let response = app.post_newsletters(newsletter()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 500);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Failed to send the newsletter issue to 1 subscribers"
+    );
+}
+
+#[tokio::test]
+async fn newsletters_returns_400_for_invalid_data() {
+    // arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing title",
+        ),
+        (
+            serde_json::json!({"title": "Newsletter!"}),
+            "missing content",
+        ),
+    ];
+
+    app.login().await;
+
+    for (invalid_body, error_message) in test_cases {
+        // act
+        let response = app.post_newsletters(invalid_body).await;
+
+        // assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_publish_a_newsletter() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app.post_newsletters(newsletter()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,63 @@
+//! tests/subscriptions_confirm.rs
+//!
+//! El link del email de bienvenida confirma la suscripcion
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+mod common;
+use common::spawn_app;
+
+#[tokio::test]
+async fn confirmations_without_token_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/subscriptions/confirm", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn confirmations_with_an_unknown_token_are_rejected_with_a_401() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=nobodyhasthistoken",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let (email, name, status): (String, String, String) =
+        sqlx::query_as("SELECT email, name, status FROM subscriptions")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch saved subscription");
+    assert_eq!(email, "ursula_le_guin@gmail.com");
+    assert_eq!(name, "le guin");
+    assert_eq!(status, "confirmed");
+}
@@ -0,0 +1,64 @@
+//! tests/telemetry.rs
+//!
+//! Lo que sale en los logs de un request: JSON de bunyan con el `request_id` y sin el email del
+//! que se suscribe
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::telemetry::{get_subscriber, init_subscriber};
+
+mod common;
+use common::launch;
+
+/// Junta en memoria todo lo que se loguea
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// NOTE(elsuizo:2022-08-07): el subscriber es global, por eso este test va solo en su archivo
+#[tokio::test]
+async fn request_logs_have_a_request_id_and_no_email() {
+    let captured = Captured::default();
+    let sink = captured.clone();
+    init_subscriber(get_subscriber("test".into(), "info".into(), move || {
+        sink.clone()
+    }));
+
+    let app = launch().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let records: Vec<serde_json::Value> = logs
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Every log line is a JSON record"))
+        .collect();
+    let saved = records
+        .iter()
+        .find(|record| record["msg"] == "[SAVING NEW SUBSCRIBER DETAILS IN THE DATABASE - START]")
+        .expect("The insert has its own span");
+    // los spans de adentro heredan los campos del request
+    assert!(saved["request_id"].is_string());
+    assert_eq!(saved["subscriber_name"], "le guin");
+    assert!(!logs.contains("ursula_le_guin"));
+    assert!(logs.contains("[REDACTED"));
+}
@@ -0,0 +1,67 @@
+//! Lo que comparten el server y el cliente para pasarse archivos
+//!
+//! Un archivo viaja en pedazos de `CHUNK_SIZE` para que ningun packet pase el limite de tamanio
+//! (en JSON cada byte puede ocupar hasta 4 caracteres). Al final se compara el SHA-256 del
+//! archivo entero con el que se anuncio al principio
+use ring::digest::{Context, SHA256};
+
+/// Lo maximo que lleva cada `UploadChunk` o `AttachmentChunk`
+pub const CHUNK_SIZE: usize = 8 * 1024;
+
+/// El SHA-256 de un archivo que va llegando de a pedazos
+pub struct Checksum(Context);
+
+impl Checksum {
+    pub fn new() -> Self {
+        Self(Context::new(&SHA256))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    /// En hexa, que es como viaja en los packets
+    pub fn finish(self) -> String {
+        self.0
+            .finish()
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Para cuando ya tenemos el archivo entero
+    pub fn of(data: &[u8]) -> String {
+        let mut checksum = Self::new();
+        checksum.update(data);
+        checksum.finish()
+    }
+}
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(
+            Checksum::of(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        // de a pedazos da lo mismo que todo junto
+        let mut checksum = Checksum::new();
+        for chunk in b"Samoyeds rock!!!".chunks(3) {
+            checksum.update(chunk);
+        }
+        assert_eq!(checksum.finish(), Checksum::of(b"Samoyeds rock!!!"));
+    }
+}
@@ -0,0 +1,341 @@
+// aca es cuando importamos la libreria que tenemos en `src/lib.rs`
+use async_chat_book::codec::Codec;
+use async_chat_book::utils::ChatResult;
+use async_std::io;
+use async_std::prelude::*;
+use std::sync::Arc;
+
+// la conexion con el server, que se reconecta sola
+mod session;
+// los comandos para subir y bajar archivos
+mod transfer;
+// la interfaz de pantalla completa (`--tui`)
+mod tui;
+
+use async_std::channel::{Receiver, Sender};
+use session::{Event, Session};
+use transfer::Transfers;
+
+// NOTE(elsuizo:2021-11-12): capaz que es mejor hacer los imports al lado de cada funcion que los
+// utiliza, porque asi queda mas claro y no todo arriba
+use async_chat_book::{AdminCommand, FromClient, GroupAccess};
+/// Funcion para parsear la entrada del usuario, los comandos de administrador solo se pueden usar
+/// si tenemos un `admin_token`. Si no se entiende el comando devolvemos el error para mostrarle al
+/// usuario
+fn parse_command(input: &str, admin_token: Option<&str>) -> Result<FromClient, String> {
+    let unrecognized = || format!("Unrecognized command: {:?}", input);
+    let (command, rest) = get_next_token(input).ok_or_else(unrecognized)?;
+    // salvo el mensaje de `post` todos los argumentos son palabras sueltas
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    let group = |name: &str| Arc::new(name.to_string());
+    match command {
+        "post" => {
+            let (group, rest) = get_next_token(rest).ok_or("Usage: post GROUP MESSAGE...")?;
+            let message = rest.trim_start().to_string();
+            Ok(FromClient::Post {
+                group_name: Arc::new(group.to_string()),
+                message: Arc::new(message),
+            })
+        }
+        "join" => match words[..] {
+            [name] => Ok(FromClient::Join {
+                group_name: group(name),
+                password: None,
+            }),
+            [name, password] => Ok(FromClient::Join {
+                group_name: group(name),
+                password: Some(password.to_string()),
+            }),
+            _ => Err("Usage: join GROUP [PASSWORD]".to_string()),
+        },
+        "create" => {
+            let access = match words.get(1..) {
+                Some([]) => GroupAccess::Open,
+                Some(["password", password]) => GroupAccess::Password(password.to_string()),
+                Some(["invite", members @ ..]) if !members.is_empty() => GroupAccess::InviteOnly(
+                    members.iter().map(|member| member.to_string()).collect(),
+                ),
+                _ => return Err("Usage: create GROUP [password PASSWORD | invite NAME...]".into()),
+            };
+            Ok(FromClient::CreateGroup {
+                group_name: group(words[0]),
+                access,
+            })
+        }
+        "name" => match words[..] {
+            [name] => Ok(FromClient::SetName {
+                name: name.to_string(),
+                password: None,
+            }),
+            [name, password] => Ok(FromClient::SetName {
+                name: name.to_string(),
+                password: Some(password.to_string()),
+            }),
+            _ => Err("Usage: name NAME [PASSWORD]".to_string()),
+        },
+        "invite" => match words[..] {
+            [name, member] => Ok(FromClient::Invite {
+                group_name: group(name),
+                member: member.to_string(),
+            }),
+            _ => Err("Usage: invite GROUP NAME".to_string()),
+        },
+        "promote" => match words[..] {
+            [name, member] => Ok(FromClient::Promote {
+                group_name: group(name),
+                member: member.to_string(),
+            }),
+            _ => Err("Usage: promote GROUP NAME".to_string()),
+        },
+        _ => match (parse_admin_command(command, rest), admin_token) {
+            (Some(command), Some(token)) => Ok(FromClient::Admin {
+                token: token.to_string(),
+                command,
+            }),
+            (Some(_), None) => Err("Admin commands need --admin-token".to_string()),
+            (None, _) => Err(unrecognized()),
+        },
+    }
+}
+
+fn parse_admin_command(command: &str, rest: &str) -> Option<AdminCommand> {
+    match command {
+        "kick" => {
+            let (peer, _) = get_next_token(rest)?;
+            Some(AdminCommand::Kick {
+                peer: peer.to_string(),
+            })
+        }
+        "close" => {
+            let (group, _) = get_next_token(rest)?;
+            Some(AdminCommand::CloseGroup {
+                group_name: Arc::new(group.to_string()),
+            })
+        }
+        "broadcast" => Some(AdminCommand::Broadcast {
+            message: rest.trim_start().to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Dado un string como input retornamos un `Some((token, rest))` donde token es la primer palabra
+/// sin contar los espacios y rest es el resto del string
+fn get_next_token(mut input: &str) -> Option<(&str, &str)> {
+    input = input.trim_start();
+    if input.is_empty() {
+        return None;
+    }
+    match input.find(char::is_whitespace) {
+        Some(space) => Some((&input[0..space], &input[space..])),
+        None => Some((input, "")),
+    }
+}
+
+/// Lee los comandos del usuario y los encola en la sesion, termina cuando se cierra la entrada
+async fn send_commands(
+    to_server: Sender<FromClient>,
+    transfers: Transfers,
+    options: &Options,
+) -> ChatResult<()> {
+    println!(
+        "Commands: \n\
+             name NAME [PASSWORD]\n\
+             create GROUP [password PASSWORD | invite NAME...]\n\
+             join GROUP [PASSWORD]\n\
+             post GROUP MESSAGE...\n\
+             invite GROUP NAME\n\
+             promote GROUP NAME\n\
+             upload GROUP PATH\n\
+             get ATTACHMENT_ID PATH\n\
+             Type Control-D(on UNIX) or Control-Z(on Windows)\
+             to close connection"
+    );
+    if options.admin_token.is_some() {
+        println!(
+            "Admin commands: \n\
+                 kick ADDRESS:PORT\n\
+                 close GROUP\n\
+                 broadcast MESSAGE..."
+        );
+    }
+
+    let mut command_lines = io::BufReader::new(io::stdin()).lines();
+    while let Some(command_result) = command_lines.next().await {
+        let command = command_result?;
+        if command.trim().is_empty() {
+            continue;
+        }
+        // un comando de archivos puede ser muchos packets
+        let requests = match transfers.parse(&command) {
+            Some(requests) => requests,
+            None => parse_command(&command, options.admin_token.as_deref()).map(|r| vec![r]),
+        };
+        let requests = match requests {
+            Ok(requests) => requests,
+            Err(message) => {
+                eprintln!("{}", message);
+                continue;
+            }
+        };
+        for request in requests {
+            // si la sesion termino no hay a quien mandarselo
+            if to_server.send(request).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+use async_chat_book::FromServer;
+
+async fn handle_replies(from_server: Receiver<Event>) -> ChatResult<()> {
+    // aca es cuando usamos la magia de los Streams(que son como iterators pero asincronicos)
+    // capaz que en proximas versiones de Rust podamos hacer un simple for aca...
+    let mut events = from_server;
+    while let Some(event) = events.next().await {
+        let reply = match event {
+            Event::Status(status) => {
+                println!("[status] {}", status);
+                continue;
+            }
+            Event::Failed { command, error } => {
+                println!("error from server: {} (after '{}')", error, command);
+                continue;
+            }
+            Event::Downloaded {
+                attachment_id,
+                path,
+            } => {
+                println!("attachment #{} saved to {}", attachment_id, path.display());
+                continue;
+            }
+            Event::Reply(reply) => reply,
+        };
+        match reply {
+            Ok(FromServer::Message {
+                group_name,
+                message,
+                ..
+            }) => {
+                println!("message posted to: {}: {}", group_name, message);
+            }
+            Ok(FromServer::Ack { group_name, seq }) => {
+                println!("message #{} delivered to {}", seq, group_name)
+            }
+            // la sesion ya los convirtio en mensajes sueltos
+            Ok(FromServer::History { .. }) => {}
+            Ok(FromServer::AttachmentAvailable(attachment)) => println!(
+                "attachment #{} posted to {}: {} ({} bytes), fetch it with: get {} PATH",
+                attachment.id,
+                attachment.group_name,
+                attachment.file_name,
+                attachment.size,
+                attachment.id
+            ),
+            // los de las descargas que pedimos se los queda la sesion
+            Ok(FromServer::AttachmentChunk { .. }) => {}
+            Ok(FromServer::Error(message)) => {
+                println!("error from server: {}", message)
+            }
+            Ok(FromServer::Notice(message)) => {
+                println!("notice from the administrators: {}", message)
+            }
+            // la sesion se va a reconectar sola cuando el server vuelva
+            Ok(FromServer::ServerShutdown) => {
+                println!("the server is shutting down");
+            }
+            Err(err) => println!("bad packet from server: {}", err),
+        }
+    }
+    Ok(())
+}
+
+//-------------------------------------------------------------------------
+//                        main function
+//-------------------------------------------------------------------------
+use async_std::task;
+
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: client ADDRESS:PORT [--codec json|binary] \
+                     [--tls-ca CERT.pem [--tls-domain NAME]] [--admin-token TOKEN] \
+                     [--tui]";
+
+/// Opciones de la linea de comandos
+#[derive(Clone)]
+struct Options {
+    address: String,
+    codec: Codec,
+    /// si esta nos conectamos por TLS confiando en este certificado
+    tls_ca: Option<PathBuf>,
+    /// el nombre del server en el certificado, por default el host de `address`
+    tls_domain: Option<String>,
+    /// para poder usar los comandos de administrador
+    admin_token: Option<String>,
+    /// usar la interfaz de pantalla completa en vez de leer comandos linea por linea
+    tui: bool,
+}
+
+fn parse_options<I>(mut args: I) -> ChatResult<Options>
+where
+    I: Iterator<Item = String>,
+{
+    let address = args.next().ok_or(USAGE)?;
+    let mut options = Options {
+        address,
+        codec: Codec::JsonLines,
+        tls_ca: None,
+        tls_domain: None,
+        admin_token: None,
+        tui: false,
+    };
+
+    while let Some(flag) = args.next() {
+        // el unico flag que no lleva valor
+        if flag == "--tui" {
+            options.tui = true;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}\n{}", flag, USAGE))?;
+        match flag.as_str() {
+            "--codec" => {
+                options.codec = Codec::from_name(&value)
+                    .ok_or_else(|| format!("unknown codec {}\n{}", value, USAGE))?
+            }
+            "--tls-ca" => options.tls_ca = Some(value.into()),
+            "--tls-domain" => options.tls_domain = Some(value),
+            "--admin-token" => options.admin_token = Some(value),
+            _ => return Err(format!("unknown option {}\n{}", flag, USAGE).into()),
+        }
+    }
+
+    Ok(options)
+}
+
+/// Mandamos y recibimos packets hasta que se cierre la entrada, la sesion se encarga de
+/// reconectarse si se corta la conexion
+async fn chat(options: &Options) -> ChatResult<()> {
+    let Session {
+        requests,
+        events,
+        transfers,
+    } = session::start(options)?;
+    if options.tui {
+        return tui::run(requests, events, transfers, options).await;
+    }
+    // cuando se cierra la entrada la sesion manda lo que quedaba y cierra `events`
+    let to_server = send_commands(requests, transfers, options);
+    let from_server = handle_replies(events);
+    let (sent, received) = to_server.join(from_server).await;
+    sent.and(received)
+}
+
+fn main() -> ChatResult<()> {
+    let options = parse_options(std::env::args().skip(1))?;
+
+    task::block_on(chat(&options))
+}
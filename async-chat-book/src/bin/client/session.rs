@@ -0,0 +1,680 @@
+//! La conexion con el server vive en su propia tarea y se reconecta sola
+//!
+//! Si se corta esperamos un rato (cada vez el doble, hasta `MAX_DELAY`), nos volvemos a conectar
+//! y mandamos de nuevo nuestro nombre y los `CreateGroup`/`Join` de todos los grupos en los que
+//! estabamos. La interfaz (la de
+//! linea por linea o la de pantalla completa) le manda los packets por un channel y recibe por
+//! otro lo que llega del server y los cambios de estado de la conexion
+//!
+//! Cada packet sale en un `Envelope` con un id, cuando el server contesta un error con ese id le
+//! podemos decir al usuario que comando fue el que fallo
+//!
+//! Los mensajes de cada grupo vienen numerados: si el numero salta (por ejemplo porque estuvimos
+//! desconectados un rato) le pedimos al server los que faltan con `FromClient::History`
+//!
+//! Los pedazos de los archivos que pedimos con `get` no le llegan a la interfaz, se van guardando
+//! en `Transfers` y cuando se completa el archivo le avisamos
+use crate::transfer::{Received, Transfers};
+use crate::Options;
+use async_chat_book::tls;
+use async_chat_book::utils::{self, ChatResult, DEFAULT_MAX_PACKET_SIZE};
+use async_chat_book::Versioned;
+use async_chat_book::{AdminCommand, Envelope, FromClient, FromServer, GroupAccess, Logged};
+use async_std::channel::{self, Receiver, Sender};
+use async_std::io;
+use async_std::net;
+use async_std::prelude::*;
+use async_std::task;
+use futures_rustls::pki_types::ServerName;
+use futures_rustls::TlsConnector;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Lo que esperamos antes del primer reintento
+const INITIAL_DELAY: Duration = Duration::from_millis(250);
+/// Lo maximo que esperamos entre dos intentos
+const MAX_DELAY: Duration = Duration::from_secs(10);
+/// De cuantos comandos nos acordamos para explicar los errores, los errores llegan enseguida asi
+/// que no hacen falta muchos
+const REMEMBERED_COMMANDS: usize = 64;
+
+/// Lo que le llega a la interfaz
+pub enum Event {
+    Status(Status),
+    Reply(ChatResult<FromServer>),
+    /// el server contesto con un error a uno de nuestros comandos
+    Failed {
+        command: String,
+        error: String,
+    },
+    /// termino bien la descarga de un archivo que pedimos
+    Downloaded {
+        attachment_id: u64,
+        path: PathBuf,
+    },
+}
+
+/// Como esta la conexion con el server, para mostrarselo al usuario
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    /// `attempt` empieza en 1 y vuelve a empezar cada vez que nos conectamos
+    Connecting { attempt: u32 },
+    /// `rejoined` es la cantidad de grupos en los que volvimos a entrar
+    Connected { rejoined: usize },
+    /// se corto (o no nos pudimos conectar) y volvemos a probar en `retry_in`
+    Disconnected { reason: String, retry_in: Duration },
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Connecting { attempt: 1 } => write!(f, "connecting..."),
+            Status::Connecting { attempt } => write!(f, "connecting (attempt {})...", attempt),
+            Status::Connected { rejoined: 0 } => write!(f, "connected"),
+            Status::Connected { rejoined } => {
+                write!(f, "connected, rejoined {} group(s)", rejoined)
+            }
+            Status::Disconnected { reason, retry_in } => write!(
+                f,
+                "disconnected ({}), retrying in {:.1}s",
+                reason,
+                retry_in.as_secs_f64()
+            ),
+        }
+    }
+}
+
+/// Espera exponencial entre reintentos
+#[derive(Debug)]
+pub struct Backoff {
+    next: Duration,
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            next: initial,
+            initial,
+            max,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    /// Para cuando nos pudimos conectar
+    pub fn reset(&mut self) {
+        self.next = self.initial;
+    }
+}
+
+/// Lo que le queda a la interfaz para hablar con la tarea de la conexion
+pub struct Session {
+    /// los packets se encolan aca hasta que haya una conexion por donde mandarlos, cuando se
+    /// dropea termina la sesion (despues de mandar lo que quedaba)
+    pub requests: Sender<FromClient>,
+    /// se cierra cuando termina la sesion
+    pub events: Receiver<Event>,
+    /// para los comandos de archivos, ver `Transfers::parse`
+    pub transfers: Transfers,
+}
+
+/// Arranca la tarea que mantiene la conexion, falla solo si la configuracion de TLS esta mal
+pub fn start(options: &Options) -> ChatResult<Session> {
+    let tls = match &options.tls_ca {
+        Some(ca) => {
+            let domain = match &options.tls_domain {
+                Some(domain) => domain.as_str(),
+                None => options
+                    .address
+                    .rsplit_once(':')
+                    .map_or("", |(host, _)| host),
+            };
+            Some((tls::connector(ca)?, tls::server_name(domain)?))
+        }
+        None => None,
+    };
+    let (requests, pending) = channel::unbounded();
+    let (events_sender, events) = channel::unbounded();
+    let transfers = Transfers::default();
+    let connection = Connection {
+        options: options.clone(),
+        tls,
+        requests: pending,
+        events: events_sender,
+        name: None,
+        joined: Mutex::new(Vec::new()),
+        unsent: None,
+        sent: Mutex::new(Sent::default()),
+        sequences: Sequences::default(),
+        gaps: channel::unbounded(),
+        transfers: transfers.clone(),
+        backoff: Backoff::new(INITIAL_DELAY, MAX_DELAY),
+        attempt: 0,
+    };
+    task::spawn(connection.keep_alive());
+    Ok(Session {
+        requests,
+        events,
+        transfers,
+    })
+}
+
+struct Connection {
+    options: Options,
+    tls: Option<(TlsConnector, ServerName<'static>)>,
+    requests: Receiver<FromClient>,
+    events: Sender<Event>,
+    /// el `SetName` con el que elegimos el nombre (con la password, si lo reclamamos)
+    name: Option<FromClient>,
+    /// el `CreateGroup` o `Join` de cada grupo en el que entramos, en orden. Los que el server
+    /// rechaza se sacan, si no fallarian de nuevo en cada reconexion. La mitad que lee tambien lo
+    /// usa, por eso va en un `Mutex`
+    joined: Mutex<Vec<FromClient>>,
+    /// el packet que estabamos mandando cuando se corto, lo mandamos de nuevo al reconectarnos
+    unsent: Option<FromClient>,
+    /// la mitad que lee tambien lo usa, por eso va en un `Mutex`
+    sent: Mutex<Sent>,
+    sequences: Sequences,
+    /// los `History` que hay que pedir, los encola la mitad que lee y los manda la que escribe
+    gaps: (Sender<FromClient>, Receiver<FromClient>),
+    transfers: Transfers,
+    backoff: Backoff,
+    /// los intentos desde la ultima vez que estuvimos conectados
+    attempt: u32,
+}
+
+impl Connection {
+    async fn keep_alive(mut self) {
+        // si la interfaz termino y no queda nada por mandar no tiene sentido reconectarse
+        while !(self.requests.is_closed() && self.requests.is_empty() && self.unsent.is_none()) {
+            self.attempt += 1;
+            self.status(Status::Connecting {
+                attempt: self.attempt,
+            })
+            .await;
+            let reason = match self.connect().await {
+                Ok(()) => return,
+                Err(err) => err.to_string(),
+            };
+            let retry_in = self.backoff.next_delay();
+            self.status(Status::Disconnected { reason, retry_in }).await;
+            task::sleep(retry_in).await;
+        }
+    }
+
+    async fn status(&self, status: Status) {
+        // si la interfaz ya no esta nadie se va a enterar, no pasa nada
+        let _ = self.events.send(Event::Status(status)).await;
+    }
+
+    /// Devuelve `Ok` cuando ya no hay nada que mandar y un error cuando se corta la conexion
+    async fn connect(&mut self) -> ChatResult<()> {
+        let socket = net::TcpStream::connect(&self.options.address).await?;
+        socket.set_nodelay(true)?;
+        match self.tls.clone() {
+            Some((connector, domain)) => {
+                let socket = connector.connect(domain, socket).await?;
+                self.serve(socket).await
+            }
+            None => self.serve(socket).await,
+        }
+    }
+
+    async fn serve<S>(&mut self, mut socket: S) -> ChatResult<()>
+    where
+        S: io::Read + io::Write + Unpin + Send,
+    {
+        use futures::io::AsyncReadExt;
+
+        let codec = self.options.codec;
+        codec.announce(&mut socket).await?;
+        let (reader, mut writer) = socket.split();
+
+        // si lo que no llego a salir se repite igual, no lo mandamos dos veces
+        if self.unsent.as_ref().is_some_and(is_replayed) {
+            self.unsent = None;
+        }
+        if let Some(name) = &self.name {
+            let envelope = self.sent.get_mut().unwrap().envelope(name);
+            codec.send(&mut writer, &envelope).await?;
+        }
+        for request in self.joined.get_mut().unwrap().iter() {
+            // si al repetirlo falla no lo sacamos, la primera vez si entramos
+            let sent = self.sent.get_mut().unwrap();
+            let envelope = match request {
+                // si el server no se reinicio el grupo sigue existiendo y el `CreateGroup` falla,
+                // ese error no se muestra
+                FromClient::CreateGroup { .. } => sent.envelope_quiet(request),
+                _ => sent.envelope(request),
+            };
+            codec.send(&mut writer, &envelope).await?;
+            // y entramos como cualquier otro, si esto falla si nos enteramos
+            if let FromClient::CreateGroup { group_name, access } = request {
+                let password = match access {
+                    GroupAccess::Password(password) => Some(password.clone()),
+                    _ => None,
+                };
+                let join = FromClient::Join {
+                    group_name: group_name.clone(),
+                    password,
+                };
+                let envelope = self.sent.get_mut().unwrap().envelope(&join);
+                codec.send(&mut writer, &envelope).await?;
+            }
+        }
+        writer.flush().await?;
+        self.backoff.reset();
+        self.attempt = 0;
+        let rejoined = self.joined.get_mut().unwrap().len();
+        self.status(Status::Connected { rejoined }).await;
+
+        let Connection {
+            requests,
+            events,
+            name,
+            joined,
+            unsent,
+            sent,
+            sequences,
+            gaps: (gaps, missing),
+            transfers,
+            ..
+        } = self;
+        // las dos mitades lo usan a la vez
+        let sent = &*sent;
+        let joined = &*joined;
+        let from_server = async {
+            let mut replies = codec.receive::<_, Versioned<FromServer>>(
+                io::BufReader::new(reader),
+                DEFAULT_MAX_PACKET_SIZE,
+            );
+            while let Some(reply) = replies.next().await {
+                let event = match reply {
+                    Err(err) if !utils::is_recoverable(&err) => return Err(err),
+                    Err(err) => Event::Reply(Err(err)),
+                    Ok(versioned) => {
+                        let id = versioned.id();
+                        let command = id.and_then(|id| sent.lock().unwrap().command(id));
+                        let quiet = id.is_some_and(|id| sent.lock().unwrap().is_quiet(id));
+                        match (versioned.into_packet(), command) {
+                            (FromServer::Error(_), Some(_)) if quiet => continue,
+                            (FromServer::Error(error), Some(command)) => {
+                                // un grupo mal escrito o una password equivocada no se repite
+                                let rejected =
+                                    id.and_then(|id| sent.lock().unwrap().rejected_join(id));
+                                if let Some(rejected) = rejected {
+                                    joined
+                                        .lock()
+                                        .unwrap()
+                                        .retain(|request| *request != rejected);
+                                }
+                                Event::Failed { command, error }
+                            }
+                            // la interfaz los recibe como si fueran mensajes nuevos
+                            (
+                                FromServer::History {
+                                    group_name,
+                                    messages,
+                                },
+                                _,
+                            ) => {
+                                for Logged { seq, message } in messages {
+                                    let message = FromServer::Message {
+                                        group_name: group_name.clone(),
+                                        message,
+                                        seq,
+                                    };
+                                    let _ = events.send(Event::Reply(Ok(message))).await;
+                                }
+                                continue;
+                            }
+                            (
+                                reply @ (FromServer::AttachmentAvailable(_)
+                                | FromServer::AttachmentChunk { .. }),
+                                _,
+                            ) => match transfers.receive(&reply) {
+                                Received::NotOurs => Event::Reply(Ok(reply)),
+                                Received::Partial => continue,
+                                Received::Done {
+                                    attachment_id,
+                                    result: Ok(path),
+                                } => Event::Downloaded {
+                                    attachment_id,
+                                    path,
+                                },
+                                Received::Done {
+                                    attachment_id,
+                                    result: Err(error),
+                                } => Event::Failed {
+                                    command: format!("get {}", attachment_id),
+                                    error,
+                                },
+                            },
+                            (reply, _) => {
+                                if let FromServer::Message {
+                                    group_name, seq, ..
+                                } = &reply
+                                {
+                                    if let Some((from, to)) = sequences.observe(group_name, *seq) {
+                                        let history = FromClient::History {
+                                            group_name: group_name.clone(),
+                                            from,
+                                            to,
+                                        };
+                                        let _ = gaps.send(history).await;
+                                    }
+                                }
+                                Event::Reply(Ok(reply))
+                            }
+                        }
+                    }
+                };
+                let _ = events.send(event).await;
+            }
+            Err("the server closed the connection".into())
+        };
+        let to_server = async {
+            loop {
+                let request = match unsent.take() {
+                    Some(request) => request,
+                    None => match requests.recv().race(missing.recv()).await {
+                        Ok(request) => request,
+                        // la interfaz termino y ya mandamos todo
+                        Err(_) => return Ok(()),
+                    },
+                };
+                let mut newly_joined = false;
+                match &request {
+                    FromClient::SetName { .. } => *name = Some(request.clone()),
+                    FromClient::CreateGroup { group_name, .. }
+                    | FromClient::Join { group_name, .. } => {
+                        let mut joined = joined.lock().unwrap();
+                        if !joined
+                            .iter()
+                            .any(|joined| group_of(joined) == Some(group_name))
+                        {
+                            joined.push(request.clone());
+                            newly_joined = true;
+                        }
+                    }
+                    _ => {}
+                }
+                *unsent = Some(request.clone());
+                let envelope = if newly_joined {
+                    sent.lock().unwrap().envelope_join(&request)
+                } else {
+                    sent.lock().unwrap().envelope(&request)
+                };
+                codec.send(&mut writer, &envelope).await?;
+                writer.flush().await?;
+                *unsent = None;
+            }
+        };
+        from_server.race(to_server).await
+    }
+}
+
+/// El ultimo numero de secuencia que vimos en cada grupo, no se borra al reconectarnos para poder
+/// pedir lo que nos perdimos mientras tanto
+#[derive(Debug, Default)]
+struct Sequences {
+    last: HashMap<Arc<String>, u64>,
+}
+
+impl Sequences {
+    /// Devuelve los numeros que faltan entre el ultimo que vimos y `seq`, si falta alguno
+    fn observe(&mut self, group_name: &Arc<String>, seq: u64) -> Option<(u64, u64)> {
+        // un server viejo no numera los mensajes
+        if seq == 0 {
+            return None;
+        }
+        match self.last.insert(group_name.clone(), seq) {
+            Some(last) if seq > last + 1 => Some((last + 1, seq - 1)),
+            // si el numero vuelve para atras el server se reinicio y empezamos de nuevo
+            _ => None,
+        }
+    }
+}
+
+/// Los ids que le pusimos a los ultimos comandos que mandamos
+#[derive(Debug, Default)]
+struct Sent {
+    next_id: u64,
+    commands: VecDeque<Remembered>,
+}
+
+#[derive(Debug)]
+struct Remembered {
+    id: u64,
+    /// como lo escribiria el usuario
+    command: String,
+    /// si era un `CreateGroup` o `Join` que anotamos en `joined`, el packet
+    join: Option<FromClient>,
+    /// si el server lo rechaza no se lo mostramos al usuario
+    quiet: bool,
+}
+
+impl Sent {
+    /// Le pone un id nuevo al packet y se acuerda de que comando era
+    fn envelope<'a>(&mut self, request: &'a FromClient) -> Envelope<&'a FromClient> {
+        self.remember(request, None, false)
+    }
+
+    /// Como `envelope` para un packet que anotamos en `joined`, si el server lo rechaza
+    /// `rejected_join` lo devuelve para sacarlo
+    fn envelope_join<'a>(&mut self, request: &'a FromClient) -> Envelope<&'a FromClient> {
+        self.remember(request, Some(request.clone()), false)
+    }
+
+    /// Como `envelope` para un packet que repetimos nosotros y que puede fallar sin que importe
+    fn envelope_quiet<'a>(&mut self, request: &'a FromClient) -> Envelope<&'a FromClient> {
+        self.remember(request, None, true)
+    }
+
+    fn remember<'a>(
+        &mut self,
+        request: &'a FromClient,
+        join: Option<FromClient>,
+        quiet: bool,
+    ) -> Envelope<&'a FromClient> {
+        let id = self.next_id;
+        self.next_id += 1;
+        if self.commands.len() == REMEMBERED_COMMANDS {
+            self.commands.pop_front();
+        }
+        self.commands.push_back(Remembered {
+            id,
+            command: describe(request),
+            join,
+            quiet,
+        });
+        Envelope::new(request, Some(id))
+    }
+
+    fn find(&self, id: u64) -> Option<&Remembered> {
+        self.commands.iter().find(|remembered| remembered.id == id)
+    }
+
+    fn command(&self, id: u64) -> Option<String> {
+        self.find(id).map(|remembered| remembered.command.clone())
+    }
+
+    fn is_quiet(&self, id: u64) -> bool {
+        self.find(id).is_some_and(|remembered| remembered.quiet)
+    }
+
+    /// El `CreateGroup` o `Join` con el id `id`, una sola vez
+    fn rejected_join(&mut self, id: u64) -> Option<FromClient> {
+        self.commands
+            .iter_mut()
+            .find(|remembered| remembered.id == id)
+            .and_then(|remembered| remembered.join.take())
+    }
+}
+
+/// El comando como lo escribiria el usuario (sin el mensaje ni las passwords)
+fn describe(request: &FromClient) -> String {
+    match request {
+        FromClient::SetName { name, .. } => format!("name {}", name),
+        FromClient::CreateGroup { group_name, .. } => format!("create {}", group_name),
+        FromClient::Join { group_name, .. } => format!("join {}", group_name),
+        FromClient::Post { group_name, .. } => format!("post {}", group_name),
+        FromClient::Invite { group_name, member } => format!("invite {} {}", group_name, member),
+        FromClient::Promote { group_name, member } => {
+            format!("promote {} {}", group_name, member)
+        }
+        FromClient::History { group_name, .. } => format!("history {}", group_name),
+        FromClient::UploadStart {
+            group_name,
+            file_name,
+            ..
+        } => format!("upload {} {}", group_name, file_name),
+        FromClient::UploadChunk { upload_id, .. } | FromClient::UploadFinish { upload_id } => {
+            format!("upload #{}", upload_id)
+        }
+        FromClient::Download { attachment_id } => format!("get {}", attachment_id),
+        FromClient::Admin { command, .. } => match command {
+            AdminCommand::Kick { peer } => format!("kick {}", peer),
+            AdminCommand::CloseGroup { group_name } => format!("close {}", group_name),
+            AdminCommand::Broadcast { .. } => "broadcast".to_string(),
+        },
+    }
+}
+
+/// Los packets que se vuelven a mandar solos al reconectarnos
+fn is_replayed(request: &FromClient) -> bool {
+    matches!(
+        request,
+        FromClient::SetName { .. } | FromClient::CreateGroup { .. } | FromClient::Join { .. }
+    )
+}
+
+fn group_of(request: &FromClient) -> Option<&Arc<String>> {
+    match request {
+        FromClient::CreateGroup { group_name, .. } | FromClient::Join { group_name, .. } => {
+            Some(group_name)
+        }
+        _ => None,
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_max() {
+        let mut backoff = Backoff::new(Duration::from_millis(250), Duration::from_secs(1));
+        let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().as_millis()).collect();
+        assert_eq!(delays, [250, 500, 1000, 1000, 1000]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_status_display() {
+        let status = Status::Disconnected {
+            reason: "the server closed the connection".to_string(),
+            retry_in: Duration::from_millis(500),
+        };
+        assert_eq!(
+            status.to_string(),
+            "disconnected (the server closed the connection), retrying in 0.5s"
+        );
+        assert_eq!(
+            Status::Connected { rejoined: 2 }.to_string(),
+            "connected, rejoined 2 group(s)"
+        );
+    }
+
+    #[test]
+    fn test_sequence_gaps() {
+        let dogs = Arc::new("Dogs".to_string());
+        let cats = Arc::new("Cats".to_string());
+        let mut sequences = Sequences::default();
+        // el primero que vemos no cuenta como salto aunque no sea el 1
+        assert_eq!(sequences.observe(&dogs, 5), None);
+        assert_eq!(sequences.observe(&dogs, 6), None);
+        assert_eq!(sequences.observe(&cats, 1), None);
+        assert_eq!(sequences.observe(&dogs, 10), Some((7, 9)));
+        assert_eq!(sequences.observe(&cats, 2), None);
+        // el server se reinicio
+        assert_eq!(sequences.observe(&dogs, 1), None);
+        assert_eq!(sequences.observe(&dogs, 3), Some((2, 2)));
+        assert_eq!(sequences.observe(&dogs, 0), None);
+    }
+
+    #[test]
+    fn test_sent_commands() {
+        let mut sent = Sent::default();
+        let join = FromClient::Join {
+            group_name: Arc::new("Dogs".to_string()),
+            password: Some("woof".to_string()),
+        };
+        assert_eq!(sent.envelope(&join).id, Some(0));
+        assert_eq!(sent.command(0).as_deref(), Some("join Dogs"));
+        assert_eq!(sent.command(1), None);
+
+        // de los mas viejos nos olvidamos
+        let name = FromClient::SetName {
+            name: "tom".to_string(),
+            password: None,
+        };
+        for _ in 0..REMEMBERED_COMMANDS {
+            sent.envelope(&name);
+        }
+        assert_eq!(sent.command(0), None);
+        assert_eq!(
+            sent.command(REMEMBERED_COMMANDS as u64).as_deref(),
+            Some("name tom")
+        );
+    }
+
+    #[test]
+    fn test_rejected_joins() {
+        let mut sent = Sent::default();
+        let join = FromClient::Join {
+            group_name: Arc::new("Dgos".to_string()),
+            password: None,
+        };
+        let post = FromClient::Post {
+            group_name: Arc::new("Dogs".to_string()),
+            message: Arc::new("woof".to_string()),
+        };
+        sent.envelope_join(&join);
+        sent.envelope(&post);
+        assert_eq!(sent.rejected_join(1), None);
+        assert_eq!(sent.rejected_join(0), Some(join));
+        // ya lo devolvio, no se saca dos veces
+        assert_eq!(sent.rejected_join(0), None);
+        assert_eq!(sent.command(0).as_deref(), Some("join Dgos"));
+    }
+
+    #[test]
+    fn test_quiet_commands() {
+        let mut sent = Sent::default();
+        let create = FromClient::CreateGroup {
+            group_name: Arc::new("Dogs".to_string()),
+            access: GroupAccess::Open,
+        };
+        sent.envelope(&create);
+        sent.envelope_quiet(&create);
+        assert!(!sent.is_quiet(0));
+        assert!(sent.is_quiet(1));
+        assert!(!sent.is_quiet(2));
+        // no se anota en `joined`, no hay nada que sacar
+        assert_eq!(sent.rejected_join(1), None);
+    }
+}
@@ -0,0 +1,326 @@
+//! Los comandos que mueven archivos: `upload GROUP PATH` y `get ATTACHMENT_ID PATH`
+//!
+//! Para subir leemos el archivo entero y lo mandamos en pedazos como cualquier otro packet. Para
+//! bajar nos acordamos de donde hay que guardar cada archivo que pedimos, y la sesion nos va
+//! pasando los pedazos a medida que llegan
+use async_chat_book::attachment::{Checksum, CHUNK_SIZE};
+use async_chat_book::{Attachment, FromClient, FromServer};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Lo que comparten la interfaz (que pide los archivos) y la sesion (que los recibe)
+#[derive(Clone, Default)]
+pub struct Transfers(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+    next_upload: u64,
+    downloads: HashMap<u64, Download>,
+}
+
+enum Download {
+    /// pedimos el archivo pero todavia no llego nada
+    Requested(PathBuf),
+    Receiving {
+        attachment: Attachment,
+        path: PathBuf,
+        file: File,
+        received: u64,
+        /// el contexto de SHA-256 es grande, mejor no agrandar todas las descargas
+        checksum: Box<Checksum>,
+    },
+}
+
+/// Que paso con un packet que llego del server
+#[derive(Debug, PartialEq)]
+pub enum Received {
+    /// no es de ninguna descarga nuestra, es para mostrarselo al usuario
+    NotOurs,
+    /// la descarga sigue
+    Partial,
+    /// termino la descarga del archivo, bien o mal
+    Done {
+        attachment_id: u64,
+        result: Result<PathBuf, String>,
+    },
+}
+
+impl Transfers {
+    /// Si `input` es un comando de archivos devuelve los packets que hay que mandar (o el error
+    /// para el usuario), si es otro comando devuelve `None`
+    pub fn parse(&self, input: &str) -> Option<Result<Vec<FromClient>, String>> {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        match words[..] {
+            ["upload", group, path] => Some(self.upload(group, Path::new(path))),
+            ["upload", ..] => Some(Err("Usage: upload GROUP PATH".to_string())),
+            ["get", id, path] => match id.parse() {
+                Ok(attachment_id) => Some(Ok(self.download(attachment_id, PathBuf::from(path)))),
+                Err(_) => Some(Err("Usage: get ATTACHMENT_ID PATH".to_string())),
+            },
+            ["get", ..] => Some(Err("Usage: get ATTACHMENT_ID PATH".to_string())),
+            _ => None,
+        }
+    }
+
+    fn upload(&self, group: &str, path: &Path) -> Result<Vec<FromClient>, String> {
+        let data = std::fs::read(path)
+            .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("{} is not a file", path.display()))?;
+        let upload_id = {
+            let mut state = self.0.lock().unwrap();
+            state.next_upload += 1;
+            state.next_upload
+        };
+
+        let mut packets = vec![FromClient::UploadStart {
+            upload_id,
+            group_name: Arc::new(group.to_string()),
+            file_name,
+            size: data.len() as u64,
+            checksum: Checksum::of(&data),
+        }];
+        packets.extend(
+            data.chunks(CHUNK_SIZE)
+                .map(|chunk| FromClient::UploadChunk {
+                    upload_id,
+                    data: chunk.to_vec(),
+                }),
+        );
+        packets.push(FromClient::UploadFinish { upload_id });
+        Ok(packets)
+    }
+
+    fn download(&self, attachment_id: u64, path: PathBuf) -> Vec<FromClient> {
+        let mut state = self.0.lock().unwrap();
+        state
+            .downloads
+            .insert(attachment_id, Download::Requested(path));
+        vec![FromClient::Download { attachment_id }]
+    }
+
+    /// Le pasa a la descarga que corresponda lo que llego del server
+    pub fn receive(&self, packet: &FromServer) -> Received {
+        let mut state = self.0.lock().unwrap();
+        let downloads = &mut state.downloads;
+        match packet {
+            FromServer::AttachmentAvailable(attachment) => {
+                let id = attachment.id;
+                let path = match downloads.get(&id) {
+                    Some(Download::Requested(path)) => path.clone(),
+                    // un aviso de un archivo nuevo, aunque lo estemos bajando
+                    _ => return Received::NotOurs,
+                };
+                let file = match File::create(&path) {
+                    Ok(file) => file,
+                    Err(err) => {
+                        downloads.remove(&id);
+                        let error = format!("Cannot create {}: {}", path.display(), err);
+                        return done(id, Err(error));
+                    }
+                };
+                downloads.insert(
+                    id,
+                    Download::Receiving {
+                        attachment: attachment.clone(),
+                        path,
+                        file,
+                        received: 0,
+                        checksum: Box::new(Checksum::new()),
+                    },
+                );
+                // un archivo vacio no tiene pedazos
+                finish_if_complete(downloads, id)
+            }
+            FromServer::AttachmentChunk {
+                attachment_id,
+                data,
+            } => {
+                let id = *attachment_id;
+                let Some(Download::Receiving {
+                    path,
+                    file,
+                    received,
+                    checksum,
+                    ..
+                }) = downloads.get_mut(&id)
+                else {
+                    return Received::NotOurs;
+                };
+                if let Err(err) = file.write_all(data) {
+                    let error = format!("Cannot write {}: {}", path.display(), err);
+                    downloads.remove(&id);
+                    return done(id, Err(error));
+                }
+                *received += data.len() as u64;
+                checksum.update(data);
+                finish_if_complete(downloads, id)
+            }
+            _ => Received::NotOurs,
+        }
+    }
+}
+
+fn done(attachment_id: u64, result: Result<PathBuf, String>) -> Received {
+    Received::Done {
+        attachment_id,
+        result,
+    }
+}
+
+/// Si ya llego todo chequeamos el archivo, si no coincide lo borramos
+fn finish_if_complete(downloads: &mut HashMap<u64, Download>, id: u64) -> Received {
+    match downloads.get(&id) {
+        Some(Download::Receiving {
+            attachment,
+            received,
+            ..
+        }) if *received >= attachment.size => {}
+        _ => return Received::Partial,
+    }
+    let Some(Download::Receiving {
+        attachment,
+        path,
+        received,
+        checksum,
+        ..
+    }) = downloads.remove(&id)
+    else {
+        unreachable!()
+    };
+    if received == attachment.size && checksum.finish() == attachment.checksum {
+        return done(id, Ok(path));
+    }
+    let _ = std::fs::remove_file(&path);
+    done(
+        id,
+        Err(format!("{} arrived corrupted", attachment.file_name)),
+    )
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chat-transfer-{}-{}", std::process::id(), name))
+    }
+
+    fn attachment(data: &[u8]) -> Attachment {
+        Attachment {
+            id: 7,
+            group_name: Arc::new("Dogs".to_string()),
+            file_name: "bark.txt".to_string(),
+            size: data.len() as u64,
+            checksum: Checksum::of(data),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let transfers = Transfers::default();
+        assert_eq!(transfers.parse("post Dogs woof"), None);
+        assert_eq!(
+            transfers.parse("get seven bark.txt"),
+            Some(Err("Usage: get ATTACHMENT_ID PATH".to_string()))
+        );
+        assert_eq!(
+            transfers.parse("upload Dogs"),
+            Some(Err("Usage: upload GROUP PATH".to_string()))
+        );
+        assert!(matches!(
+            transfers.parse("upload Dogs /this/does/not/exist"),
+            Some(Err(message)) if message.starts_with("Cannot read /this/does/not/exist")
+        ));
+    }
+
+    #[test]
+    fn test_upload_packets() {
+        let path = temp_path("upload.bin");
+        let data = vec![42; CHUNK_SIZE + 10];
+        std::fs::write(&path, &data).unwrap();
+        let transfers = Transfers::default();
+        let command = format!("upload Dogs {}", path.display());
+        let packets = transfers.parse(&command).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(packets.len(), 4);
+        assert_eq!(
+            packets[0],
+            FromClient::UploadStart {
+                upload_id: 1,
+                group_name: Arc::new("Dogs".to_string()),
+                file_name: path.file_name().unwrap().to_string_lossy().into_owned(),
+                size: data.len() as u64,
+                checksum: Checksum::of(&data),
+            }
+        );
+        assert!(matches!(&packets[2], FromClient::UploadChunk { data, .. } if data.len() == 10));
+        assert_eq!(packets[3], FromClient::UploadFinish { upload_id: 1 });
+    }
+
+    #[test]
+    fn test_download() {
+        let path = temp_path("download.txt");
+        let transfers = Transfers::default();
+        let command = format!("get 7 {}", path.display());
+        assert_eq!(
+            transfers.parse(&command),
+            Some(Ok(vec![FromClient::Download { attachment_id: 7 }]))
+        );
+
+        let data = b"woof woof";
+        let available = FromServer::AttachmentAvailable(attachment(data));
+        assert_eq!(transfers.receive(&available), Received::Partial);
+        for chunk in data.chunks(4) {
+            let chunk = FromServer::AttachmentChunk {
+                attachment_id: 7,
+                data: chunk.to_vec(),
+            };
+            let received = transfers.receive(&chunk);
+            if received != Received::Partial {
+                assert_eq!(
+                    received,
+                    Received::Done {
+                        attachment_id: 7,
+                        result: Ok(path.clone())
+                    }
+                );
+            }
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        std::fs::remove_file(&path).unwrap();
+
+        // los avisos de archivos que no pedimos son para el usuario
+        assert_eq!(transfers.receive(&available), Received::NotOurs);
+    }
+
+    #[test]
+    fn test_corrupted_download() {
+        let path = temp_path("corrupted.txt");
+        let transfers = Transfers::default();
+        transfers.parse(&format!("get 7 {}", path.display()));
+        let available = FromServer::AttachmentAvailable(attachment(b"woof"));
+        assert_eq!(transfers.receive(&available), Received::Partial);
+        let chunk = FromServer::AttachmentChunk {
+            attachment_id: 7,
+            data: b"meow".to_vec(),
+        };
+        assert_eq!(
+            transfers.receive(&chunk),
+            Received::Done {
+                attachment_id: 7,
+                result: Err("bark.txt arrived corrupted".to_string())
+            }
+        );
+        assert!(!path.exists());
+    }
+}
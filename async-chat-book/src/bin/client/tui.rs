@@ -0,0 +1,575 @@
+//! La interfaz de pantalla completa del cliente (con `--tui`)
+//!
+//! A la izquierda estan los grupos (con la cantidad de mensajes sin leer), a la derecha los
+//! mensajes del grupo elegido y abajo la linea donde escribimos. Lo que se escribe va directo al
+//! grupo elegido y los comandos van con una `/` adelante (`/join Dogs`). Por abajo se siguen
+//! usando los mismos packets y el mismo codec que el modo de linea por linea, y la misma sesion
+//! que se reconecta sola (abajo de todo se ve como esta la conexion)
+use crate::session::{Event, Status};
+use crate::transfer::Transfers;
+use crate::{parse_command, Options};
+use async_chat_book::utils::ChatResult;
+use async_chat_book::{FromClient, FromServer};
+use async_std::channel::{Receiver, Sender};
+use async_std::prelude::*;
+use crossterm::event::{self, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures::stream;
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use std::sync::Arc;
+
+/// El panel donde van los errores y avisos que no son de ningun grupo
+const SERVER_PANE: &str = "*server*";
+
+/// Cuantas lineas nos movemos con PageUp/PageDown
+const PAGE: usize = 10;
+
+/// Lo que puede despertar al loop de la interfaz
+enum Input {
+    Key(KeyEvent),
+    /// cambio el tamanio de la terminal, solo hay que redibujar
+    Resize,
+    Session(Event),
+}
+
+/// Los mensajes de un grupo (o los del server)
+struct Pane {
+    name: String,
+    lines: Vec<String>,
+    unread: usize,
+    /// cuantas lineas subimos desde el final, 0 es estar mirando lo ultimo que llego
+    scroll: usize,
+}
+
+impl Pane {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            lines: Vec::new(),
+            unread: 0,
+            scroll: 0,
+        }
+    }
+
+    fn push(&mut self, line: String) {
+        self.lines.push(line);
+        // si estamos leyendo mas arriba no le movemos la pantalla al usuario
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+}
+
+/// Todo el estado de la interfaz, separado del dibujo y de la terminal para poder testearlo
+pub struct App {
+    /// el primero siempre es el del server
+    panes: Vec<Pane>,
+    selected: usize,
+    input: String,
+    history: Vec<String>,
+    /// la posicion en `history` mientras la recorremos con las flechas
+    browsing: Option<usize>,
+    address: String,
+    admin_token: Option<String>,
+    transfers: Transfers,
+    status: Status,
+    quit: bool,
+}
+
+impl App {
+    fn new(address: &str, admin_token: Option<String>, transfers: Transfers) -> Self {
+        let mut server = Pane::new(SERVER_PANE);
+        server.push(
+            "commands: /name NAME [PASSWORD], /create GROUP, /join GROUP, /post GROUP MESSAGE..., \
+             /upload GROUP PATH, /get ATTACHMENT_ID PATH, anything else goes to the selected \
+             group. Tab switches groups, PageUp/PageDown scroll, Esc quits"
+                .to_string(),
+        );
+        Self {
+            panes: vec![server],
+            selected: 0,
+            input: String::new(),
+            history: Vec::new(),
+            browsing: None,
+            address: address.to_string(),
+            admin_token,
+            transfers,
+            status: Status::Connecting { attempt: 1 },
+            quit: false,
+        }
+    }
+
+    /// Actualiza el estado y devuelve los packets que hay que mandarle al server
+    fn handle(&mut self, input: Input) -> Vec<FromClient> {
+        match input {
+            Input::Key(key) => return self.handle_key(key),
+            Input::Resize => {}
+            Input::Session(Event::Reply(Ok(reply))) => self.receive(reply),
+            Input::Session(Event::Reply(Err(err))) => {
+                self.post_to(0, format!("bad packet from server: {}", err))
+            }
+            Input::Session(Event::Failed { command, error }) => {
+                self.post_to(0, format!("error: {} (after '{}')", error, command))
+            }
+            Input::Session(Event::Downloaded {
+                attachment_id,
+                path,
+            }) => self.post_to(
+                0,
+                format!("attachment #{} saved to {}", attachment_id, path.display()),
+            ),
+            Input::Session(Event::Status(status)) => self.set_status(status),
+        }
+        Vec::new()
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Vec<FromClient> {
+        match key.code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Enter => return self.submit(),
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                self.browsing = None;
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                self.browsing = None;
+            }
+            KeyCode::Up => self.history_back(),
+            KeyCode::Down => self.history_forward(),
+            KeyCode::Tab => self.select((self.selected + 1) % self.panes.len()),
+            KeyCode::BackTab => {
+                self.select((self.selected + self.panes.len() - 1) % self.panes.len())
+            }
+            KeyCode::PageUp => {
+                let pane = &mut self.panes[self.selected];
+                pane.scroll = (pane.scroll + PAGE).min(pane.lines.len().saturating_sub(1));
+            }
+            KeyCode::PageDown => {
+                let pane = &mut self.panes[self.selected];
+                pane.scroll = pane.scroll.saturating_sub(PAGE);
+            }
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    /// El usuario apreto Enter: lo que escribio es un comando o un mensaje para el grupo elegido
+    fn submit(&mut self) -> Vec<FromClient> {
+        let input = std::mem::take(&mut self.input);
+        self.browsing = None;
+        if input.trim().is_empty() {
+            return Vec::new();
+        }
+        self.history.push(input.clone());
+
+        // un comando de archivos puede ser muchos packets
+        let requests = match input.strip_prefix('/') {
+            Some(command) => match self.transfers.parse(command) {
+                Some(requests) => requests,
+                None => parse_command(command, self.admin_token.as_deref()).map(|r| vec![r]),
+            },
+            None if self.selected == 0 => {
+                Err("pick a group with Tab or type /join GROUP to post".to_string())
+            }
+            None => Ok(vec![FromClient::Post {
+                group_name: Arc::new(self.panes[self.selected].name.clone()),
+                message: Arc::new(input),
+            }]),
+        };
+        match requests {
+            Ok(requests) => {
+                // el panel del grupo aparece apenas lo pedimos, no cuando llega el primer mensaje
+                if let Some(
+                    FromClient::Join { group_name, .. }
+                    | FromClient::CreateGroup { group_name, .. },
+                ) = requests.first()
+                {
+                    let index = self.pane_index(group_name);
+                    self.select(index);
+                }
+                if !matches!(self.status, Status::Connected { .. }) {
+                    self.post_to(
+                        0,
+                        "not connected, it will be sent after reconnecting".to_string(),
+                    );
+                }
+                requests
+            }
+            Err(message) => {
+                self.post_to(0, message);
+                Vec::new()
+            }
+        }
+    }
+
+    fn receive(&mut self, reply: FromServer) {
+        match reply {
+            FromServer::Message {
+                group_name,
+                message,
+                ..
+            } => {
+                let index = self.pane_index(&group_name);
+                self.post_to(index, message.to_string());
+            }
+            // nuestro mensaje ya aparece en el panel cuando nos llega como a todos
+            FromServer::Ack { .. } => {}
+            // la sesion ya los convirtio en mensajes sueltos
+            FromServer::History { .. } => {}
+            FromServer::AttachmentAvailable(attachment) => {
+                let index = self.pane_index(&attachment.group_name);
+                let line = format!(
+                    "attachment #{}: {} ({} bytes), /get {} PATH to fetch it",
+                    attachment.id, attachment.file_name, attachment.size, attachment.id
+                );
+                self.post_to(index, line);
+            }
+            // los de las descargas que pedimos se los queda la sesion
+            FromServer::AttachmentChunk { .. } => {}
+            FromServer::Error(message) => self.post_to(0, format!("error: {}", message)),
+            FromServer::Notice(message) => self.post_to(0, format!("notice: {}", message)),
+            FromServer::ServerShutdown => {
+                self.post_to(0, "the server is shutting down".to_string())
+            }
+        }
+    }
+
+    /// Los cambios de estado van a la linea de abajo, y al panel del server cuando se corta o
+    /// vuelve la conexion
+    fn set_status(&mut self, status: Status) {
+        match &status {
+            Status::Connected { .. } | Status::Disconnected { .. } => {
+                self.post_to(0, status.to_string())
+            }
+            Status::Connecting { .. } => {}
+        }
+        self.status = status;
+    }
+
+    fn post_to(&mut self, index: usize, line: String) {
+        let pane = &mut self.panes[index];
+        pane.push(line);
+        if index != self.selected {
+            pane.unread += 1;
+        }
+    }
+
+    /// La posicion del panel del grupo, si no existe lo creamos
+    fn pane_index(&mut self, group_name: &str) -> usize {
+        match self.panes[1..]
+            .iter()
+            .position(|pane| pane.name == group_name)
+        {
+            Some(index) => index + 1,
+            None => {
+                self.panes.push(Pane::new(group_name));
+                self.panes.len() - 1
+            }
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index;
+        self.panes[index].unread = 0;
+    }
+
+    fn history_back(&mut self) {
+        let position = match self.browsing {
+            Some(0) => 0,
+            Some(position) => position - 1,
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+        self.browsing = Some(position);
+        self.input = self.history[position].clone();
+    }
+
+    fn history_forward(&mut self) {
+        match self.browsing {
+            Some(position) if position + 1 < self.history.len() => {
+                self.browsing = Some(position + 1);
+                self.input = self.history[position + 1].clone();
+            }
+            // pasamos la ultima: volvemos a la linea vacia
+            Some(_) => {
+                self.browsing = None;
+                self.input.clear();
+            }
+            None => {}
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [main, input_area, status_area] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [groups_area, messages_area] =
+        Layout::horizontal([Constraint::Length(24), Constraint::Min(10)]).areas(main);
+
+    let groups: Vec<ListItem> = app
+        .panes
+        .iter()
+        .map(|pane| match pane.unread {
+            0 => ListItem::new(pane.name.as_str()),
+            unread => ListItem::new(format!("{} ({})", pane.name, unread))
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+        })
+        .collect();
+    let groups = List::new(groups)
+        .block(Block::bordered().title("groups"))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(app.selected));
+    frame.render_stateful_widget(groups, groups_area, &mut state);
+
+    // mostramos las ultimas lineas que entran, corridas `scroll` lineas para arriba
+    let pane = &app.panes[app.selected];
+    let height = messages_area.height.saturating_sub(2) as usize;
+    let end = pane.lines.len() - pane.scroll.min(pane.lines.len());
+    let start = end.saturating_sub(height);
+    let lines: Vec<Line> = pane.lines[start..end]
+        .iter()
+        .map(|line| Line::raw(line.as_str()))
+        .collect();
+    let messages = Paragraph::new(lines).block(Block::bordered().title(pane.name.as_str()));
+    frame.render_widget(messages, messages_area);
+
+    let input = Paragraph::new(app.input.as_str()).block(Block::bordered().title("> "));
+    frame.render_widget(input, input_area);
+    let cursor = Line::raw(app.input.as_str()).width() as u16;
+    frame.set_cursor_position(Position::new(input_area.x + 1 + cursor, input_area.y + 1));
+
+    let status = Line::raw(format!(" {}: {}", app.address, app.status))
+        .style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_widget(Paragraph::new(status), status_area);
+}
+
+/// Maneja la terminal hasta que el usuario se va, lo que llega de la sesion y lo que se escribe
+/// entran por el mismo stream
+pub async fn run(
+    to_server: Sender<FromClient>,
+    from_server: Receiver<Event>,
+    transfers: Transfers,
+    options: &Options,
+) -> ChatResult<()> {
+    let events = from_server.map(|event| ChatResult::Ok(Input::Session(event)));
+    let keys = EventStream::new().filter_map(|event| match event {
+        Ok(event::Event::Key(key)) if key.kind == KeyEventKind::Press => Some(Ok(Input::Key(key))),
+        Ok(event::Event::Resize(..)) => Some(Ok(Input::Resize)),
+        Ok(_) => None,
+        Err(err) => Some(Err(err.into())),
+    });
+    let mut inputs = stream::select(events, keys);
+
+    let mut app = App::new(&options.address, options.admin_token.clone(), transfers);
+    let mut terminal = ratatui::try_init()?;
+    let result: ChatResult<()> = async {
+        while !app.quit {
+            terminal.draw(|frame| draw(frame, &app))?;
+            let requests = match inputs.next().await {
+                Some(input) => app.handle(input?),
+                None => break,
+            };
+            for request in requests {
+                // la sesion vive mientras tengamos `to_server`, asi que esto no falla
+                let _ = to_server.send(request).await;
+            }
+        }
+        Ok(())
+    }
+    .await;
+    // pase lo que pase le devolvemos la terminal como estaba
+    ratatui::restore();
+    result
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_chat_book::Attachment;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::time::Duration;
+
+    const ADDRESS: &str = "localhost:8088";
+
+    /// Los comandos de estos tests son todos de un solo packet
+    fn type_line(app: &mut App, line: &str) -> Option<FromClient> {
+        for c in line.chars() {
+            app.handle_key(KeyEvent::from(KeyCode::Char(c)));
+        }
+        let mut requests = app.handle_key(KeyEvent::from(KeyCode::Enter));
+        assert!(requests.len() <= 1);
+        requests.pop()
+    }
+
+    fn message(group: &str, text: &str) -> Input {
+        Input::Session(Event::Reply(Ok(FromServer::Message {
+            group_name: Arc::new(group.to_string()),
+            message: Arc::new(text.to_string()),
+            seq: 1,
+        })))
+    }
+
+    #[test]
+    fn test_join_then_post_to_selected_group() {
+        let mut app = App::new(ADDRESS, None, Transfers::default());
+        assert_eq!(
+            type_line(&mut app, "/join Dogs"),
+            Some(FromClient::Join {
+                group_name: Arc::new("Dogs".to_string()),
+                password: None,
+            })
+        );
+        assert_eq!(app.panes[app.selected].name, "Dogs");
+        assert_eq!(
+            type_line(&mut app, "woof woof"),
+            Some(FromClient::Post {
+                group_name: Arc::new("Dogs".to_string()),
+                message: Arc::new("woof woof".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_unread_counters() {
+        let mut app = App::new(ADDRESS, None, Transfers::default());
+        type_line(&mut app, "/join Dogs");
+        type_line(&mut app, "/join Cats");
+        app.handle(message("Dogs", "woof"));
+        app.handle(message("Dogs", "woof!"));
+        app.handle(message("Cats", "meow"));
+        assert_eq!(app.panes[1].unread, 2);
+        assert_eq!(app.panes[2].unread, 0);
+
+        // Tab desde Cats da la vuelta al server y despues a Dogs
+        app.handle_key(KeyEvent::from(KeyCode::Tab));
+        app.handle_key(KeyEvent::from(KeyCode::Tab));
+        assert_eq!(app.panes[app.selected].name, "Dogs");
+        assert_eq!(app.panes[1].unread, 0);
+        assert_eq!(app.panes[1].lines, ["woof", "woof!"]);
+    }
+
+    #[test]
+    fn test_errors_go_to_the_server_pane() {
+        let mut app = App::new(ADDRESS, None, Transfers::default());
+        assert_eq!(type_line(&mut app, "hello?"), None);
+        assert_eq!(type_line(&mut app, "/kick 127.0.0.1:4242"), None);
+        app.handle(Input::Session(Event::Reply(Ok(FromServer::Error(
+            "nope".to_string(),
+        )))));
+        app.handle(Input::Session(Event::Failed {
+            command: "join Cats".to_string(),
+            error: "Group 'Cats' does not exist".to_string(),
+        }));
+        let server = &app.panes[0];
+        assert!(server.lines.ends_with(&[
+            "pick a group with Tab or type /join GROUP to post".to_string(),
+            "Admin commands need --admin-token".to_string(),
+            "error: nope".to_string(),
+            "error: Group 'Cats' does not exist (after 'join Cats')".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut app = App::new(ADDRESS, None, Transfers::default());
+        type_line(&mut app, "/join Dogs");
+        type_line(&mut app, "first");
+        type_line(&mut app, "second");
+        app.handle_key(KeyEvent::from(KeyCode::Up));
+        assert_eq!(app.input, "second");
+        app.handle_key(KeyEvent::from(KeyCode::Up));
+        app.handle_key(KeyEvent::from(KeyCode::Up));
+        app.handle_key(KeyEvent::from(KeyCode::Up));
+        assert_eq!(app.input, "/join Dogs");
+        app.handle_key(KeyEvent::from(KeyCode::Down));
+        assert_eq!(app.input, "first");
+        app.handle_key(KeyEvent::from(KeyCode::Down));
+        app.handle_key(KeyEvent::from(KeyCode::Down));
+        assert_eq!(app.input, "");
+    }
+
+    #[test]
+    fn test_attachments() {
+        let mut app = App::new(ADDRESS, None, Transfers::default());
+        type_line(&mut app, "/join Dogs");
+        app.handle(Input::Session(Event::Reply(Ok(
+            FromServer::AttachmentAvailable(Attachment {
+                id: 3,
+                group_name: Arc::new("Dogs".to_string()),
+                file_name: "bark.txt".to_string(),
+                size: 9,
+                checksum: String::new(),
+            }),
+        ))));
+        assert_eq!(
+            app.panes[1].lines,
+            ["attachment #3: bark.txt (9 bytes), /get 3 PATH to fetch it"]
+        );
+        assert_eq!(
+            type_line(&mut app, "/get 3 bark.txt"),
+            Some(FromClient::Download { attachment_id: 3 })
+        );
+        app.handle(Input::Session(Event::Downloaded {
+            attachment_id: 3,
+            path: "bark.txt".into(),
+        }));
+        assert_eq!(
+            app.panes[0].lines.last().map(String::as_str),
+            Some("attachment #3 saved to bark.txt")
+        );
+    }
+
+    #[test]
+    fn test_messages_are_queued_while_disconnected() {
+        let mut app = App::new(ADDRESS, None, Transfers::default());
+        app.handle(Input::Session(Event::Status(Status::Connected {
+            rejoined: 0,
+        })));
+        type_line(&mut app, "/join Dogs");
+        app.handle(Input::Session(Event::Status(Status::Disconnected {
+            reason: "the server closed the connection".to_string(),
+            retry_in: Duration::from_millis(250),
+        })));
+        // lo que escribimos se encola en la sesion, pero le avisamos al usuario
+        assert!(type_line(&mut app, "anyone there?").is_some());
+        assert_eq!(
+            app.panes[0].lines.last().unwrap(),
+            "not connected, it will be sent after reconnecting"
+        );
+        assert_eq!(app.panes[0].unread, 2);
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut app = App::new(ADDRESS, None, Transfers::default());
+        type_line(&mut app, "/join Dogs");
+        app.handle(message("Dogs", "Samoyeds rock!!!"));
+        app.handle(message("Cats", "meow"));
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 10)).unwrap();
+        terminal.draw(|frame| draw(frame, &app)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("Samoyeds rock!!!"));
+        assert!(screen.contains("Cats (1)"));
+        assert!(screen.contains("localhost:8088: connecting..."));
+    }
+}
@@ -0,0 +1,49 @@
+//! Los comandos de administrador: solo se aceptan si vienen con el token que se le paso al server
+//! con `--admin-token`
+use crate::ChatServer;
+use async_chat_book::utils::secrets_match;
+use async_chat_book::{AdminCommand, FromServer};
+use async_std::future;
+use std::time::Duration;
+
+/// Lo maximo que esperamos a que se termine de escribir lo que tenia encolado un cliente que
+/// echamos, si su conexion esta trabada no nos quedamos colgados con el
+const KICK_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub async fn execute(chat: &ChatServer, token: &str, command: AdminCommand) -> Result<(), String> {
+    match &chat.config.admin_token {
+        None => return Err("admin commands are disabled".to_string()),
+        Some(expected) if !secrets_match(expected, token) => {
+            return Err("invalid admin token".to_string())
+        }
+        Some(_) => {}
+    }
+
+    match command {
+        AdminCommand::Kick { peer } => {
+            let outbound = chat
+                .connections
+                .find(&peer)
+                .ok_or_else(|| format!("no client connected from {}", peer))?;
+            let notice = FromServer::Error("you have been kicked by an administrator".to_string());
+            // si justo se estaba desconectando no importa que fallen
+            let _ = outbound.send(notice);
+            // `close` ya le avisa a `serve` que corte, el timeout es para no esperar la escritura
+            let _ = future::timeout(KICK_TIMEOUT, outbound.close()).await;
+            Ok(())
+        }
+        AdminCommand::CloseGroup { group_name } => match chat.groups.remove(&group_name) {
+            Some(group) => {
+                group.close();
+                Ok(())
+            }
+            None => Err(format!("Group '{}' does not exist", group_name)),
+        },
+        AdminCommand::Broadcast { message } => {
+            for (_, outbound) in chat.connections.snapshot() {
+                let _ = outbound.send(FromServer::Notice(message.clone()));
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Los archivos que se suben a los grupos
+//!
+//! Mientras se sube, cada archivo vive en los `Uploads` de su conexion (si se corta la conexion
+//! se pierde). Cuando termina bien pasa al `AttachmentStore` del server, que lo guarda en memoria
+//! hasta que se apaga el server
+use crate::group::Group;
+use async_chat_book::attachment::Checksum;
+use async_chat_book::Attachment;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Cuantos archivos puede estar subiendo a la vez cada conexion
+pub const MAX_UPLOADS: usize = 4;
+
+/// Los archivos que ya se subieron, para todo el server
+pub struct AttachmentStore {
+    /// el tamanio maximo de cada archivo
+    max_size: u64,
+    /// entre todos los archivos no pueden ocupar mas que esto
+    capacity: u64,
+    stored: Mutex<Stored>,
+}
+
+#[derive(Default)]
+struct Stored {
+    next_id: u64,
+    used: u64,
+    files: HashMap<u64, (Attachment, Arc<Vec<u8>>)>,
+}
+
+impl AttachmentStore {
+    pub fn new(max_size: u64, capacity: u64) -> Self {
+        Self {
+            max_size,
+            capacity,
+            stored: Mutex::new(Stored::default()),
+        }
+    }
+
+    /// Guarda el archivo y le pone un id, falla si ya no hay lugar
+    fn store(&self, mut attachment: Attachment, data: Vec<u8>) -> Result<Attachment, String> {
+        let mut stored = self.stored.lock().unwrap();
+        if stored.used + attachment.size > self.capacity {
+            return Err(format!(
+                "Cannot store '{}': the server is out of space for attachments",
+                attachment.file_name
+            ));
+        }
+        stored.next_id += 1;
+        stored.used += attachment.size;
+        attachment.id = stored.next_id;
+        let id = attachment.id;
+        stored
+            .files
+            .insert(id, (attachment.clone(), Arc::new(data)));
+        Ok(attachment)
+    }
+
+    pub fn get(&self, id: u64) -> Result<(Attachment, Arc<Vec<u8>>), String> {
+        self.stored
+            .lock()
+            .unwrap()
+            .files
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("Attachment #{} does not exist", id))
+    }
+
+    /// Los bytes que ocupan todos los archivos guardados
+    pub fn used(&self) -> u64 {
+        self.stored.lock().unwrap().used
+    }
+}
+
+/// Un archivo a medio subir
+struct Upload {
+    group: Arc<Group>,
+    attachment: Attachment,
+    data: Vec<u8>,
+    checksum: Checksum,
+}
+
+/// Los archivos que esta subiendo una conexion, por el id que eligio el cliente
+#[derive(Default)]
+pub struct Uploads(HashMap<u64, Upload>);
+
+impl Uploads {
+    /// `group` es el grupo al que va el archivo, ya chequeamos que el cliente este adentro
+    pub fn start(
+        &mut self,
+        store: &AttachmentStore,
+        upload_id: u64,
+        group: Arc<Group>,
+        attachment: Attachment,
+    ) -> Result<(), String> {
+        if attachment.size > store.max_size {
+            return Err(format!(
+                "Cannot upload '{}': it has {} bytes and the limit is {}",
+                attachment.file_name, attachment.size, store.max_size
+            ));
+        }
+        if self.0.contains_key(&upload_id) {
+            return Err(format!("Upload #{} already started", upload_id));
+        }
+        if self.0.len() == MAX_UPLOADS {
+            return Err(format!(
+                "Cannot upload '{}': only {} uploads at a time",
+                attachment.file_name, MAX_UPLOADS
+            ));
+        }
+        // NOTE(elsuizo:2021-12-02): no reservamos `size` de entrada, el cliente puede anunciar
+        // el maximo y no mandar nada. La memoria crece con los chunks que de verdad llegan
+        let upload = Upload {
+            group,
+            data: Vec::new(),
+            attachment,
+            checksum: Checksum::new(),
+        };
+        self.0.insert(upload_id, upload);
+        Ok(())
+    }
+
+    /// Si el pedazo se pasa del tamanio anunciado se cancela toda la subida
+    pub fn chunk(&mut self, upload_id: u64, data: &[u8]) -> Result<(), String> {
+        let upload = self.find(upload_id)?;
+        if (upload.data.len() + data.len()) as u64 > upload.attachment.size {
+            let message = format!(
+                "Upload #{} has more than the {} bytes announced, it was cancelled",
+                upload_id, upload.attachment.size
+            );
+            self.0.remove(&upload_id);
+            return Err(message);
+        }
+        upload.checksum.update(data);
+        upload.data.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// Chequea el archivo y lo guarda, devuelve el grupo al que hay que avisarle y el archivo
+    /// con su id. Si algo no coincide la subida se pierde
+    pub fn finish(
+        &mut self,
+        store: &AttachmentStore,
+        upload_id: u64,
+    ) -> Result<(Arc<Group>, Attachment), String> {
+        self.find(upload_id)?;
+        let upload = self.0.remove(&upload_id).unwrap();
+        let received = upload.data.len() as u64;
+        if received != upload.attachment.size {
+            return Err(format!(
+                "Upload #{} is incomplete: got {} of {} bytes",
+                upload_id, received, upload.attachment.size
+            ));
+        }
+        if upload.checksum.finish() != upload.attachment.checksum {
+            return Err(format!("Upload #{} does not match its checksum", upload_id));
+        }
+        let attachment = store.store(upload.attachment, upload.data)?;
+        Ok((upload.group, attachment))
+    }
+
+    /// Si la subida esta en curso en esta conexion
+    pub fn is_active(&self, upload_id: u64) -> bool {
+        self.0.contains_key(&upload_id)
+    }
+
+    fn find(&mut self, upload_id: u64) -> Result<&mut Upload, String> {
+        self.0
+            .get_mut(&upload_id)
+            .ok_or_else(|| format!("Upload #{} was not started", upload_id))
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::federation::Federation;
+    use crate::rate_limit::RateLimit;
+    use async_chat_book::GroupAccess;
+
+    fn group() -> Arc<Group> {
+        let name = Arc::new("Dogs".to_string());
+        let limit = RateLimit::new(1.0, 1.0);
+        Arc::new(Group::new(
+            name,
+            limit,
+            GroupAccess::Open,
+            None,
+            Arc::new([]),
+            Arc::new(Federation::new()),
+        ))
+    }
+
+    fn attachment(file_name: &str, data: &[u8]) -> Attachment {
+        Attachment {
+            id: 0,
+            group_name: Arc::new("Dogs".to_string()),
+            file_name: file_name.to_string(),
+            size: data.len() as u64,
+            checksum: Checksum::of(data),
+        }
+    }
+
+    #[test]
+    fn test_upload() {
+        let store = AttachmentStore::new(10, 100);
+        let mut uploads = Uploads::default();
+        let data = b"woof woof";
+        uploads
+            .start(&store, 7, group(), attachment("bark.txt", data))
+            .unwrap();
+        for chunk in data.chunks(4) {
+            uploads.chunk(7, chunk).unwrap();
+        }
+        let (_, stored) = uploads.finish(&store, 7).unwrap();
+        assert_eq!(stored.id, 1);
+        assert_eq!(store.used(), 9);
+        let (found, bytes) = store.get(1).unwrap();
+        assert_eq!(found, stored);
+        assert_eq!(bytes.as_slice(), data);
+
+        // la subida ya termino
+        assert_eq!(
+            uploads.chunk(7, b"!"),
+            Err("Upload #7 was not started".to_string())
+        );
+        assert_eq!(
+            store.get(2),
+            Err("Attachment #2 does not exist".to_string())
+        );
+    }
+
+    #[test]
+    fn test_upload_limits() {
+        let store = AttachmentStore::new(10, 15);
+        let mut uploads = Uploads::default();
+        assert_eq!(
+            uploads.start(&store, 1, group(), attachment("big", &[0; 11])),
+            Err("Cannot upload 'big': it has 11 bytes and the limit is 10".to_string())
+        );
+
+        uploads
+            .start(&store, 1, group(), attachment("short", b"abc"))
+            .unwrap();
+        assert_eq!(
+            uploads.chunk(1, b"abcd"),
+            Err("Upload #1 has more than the 3 bytes announced, it was cancelled".to_string())
+        );
+
+        uploads
+            .start(&store, 2, group(), attachment("wrong", b"abc"))
+            .unwrap();
+        uploads.chunk(2, b"abd").unwrap();
+        assert_eq!(
+            uploads.finish(&store, 2).err(),
+            Some("Upload #2 does not match its checksum".to_string())
+        );
+
+        uploads
+            .start(&store, 3, group(), attachment("half", b"abc"))
+            .unwrap();
+        uploads.chunk(3, b"a").unwrap();
+        assert_eq!(
+            uploads.finish(&store, 3).err(),
+            Some("Upload #3 is incomplete: got 1 of 3 bytes".to_string())
+        );
+
+        // el segundo no entra en el lugar que queda
+        for (upload_id, name) in [(4, "first"), (5, "second")] {
+            uploads
+                .start(&store, upload_id, group(), attachment(name, &[1; 10]))
+                .unwrap();
+            uploads.chunk(upload_id, &[1; 10]).unwrap();
+        }
+        assert!(uploads.finish(&store, 4).is_ok());
+        assert_eq!(
+            uploads.finish(&store, 5).err(),
+            Some("Cannot store 'second': the server is out of space for attachments".to_string())
+        );
+    }
+
+    #[test]
+    fn test_concurrent_uploads() {
+        let store = AttachmentStore::new(10, 100);
+        let mut uploads = Uploads::default();
+        for upload_id in 0..MAX_UPLOADS as u64 {
+            uploads
+                .start(&store, upload_id, group(), attachment("file", b"a"))
+                .unwrap();
+        }
+        assert_eq!(
+            uploads.start(&store, 0, group(), attachment("file", b"a")),
+            Err("Upload #0 already started".to_string())
+        );
+        assert_eq!(
+            uploads.start(&store, 99, group(), attachment("file", b"a")),
+            Err("Cannot upload 'file': only 4 uploads at a time".to_string())
+        );
+    }
+}
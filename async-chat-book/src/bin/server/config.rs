@@ -0,0 +1,206 @@
+//! Opciones del server que vienen de la linea de comandos
+use crate::rate_limit::RateLimit;
+use async_chat_book::tls;
+use async_chat_book::utils::{ChatResult, DEFAULT_MAX_PACKET_SIZE};
+use futures_rustls::pki_types::ServerName;
+use futures_rustls::{TlsAcceptor, TlsConnector};
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "Usage: server ADDRESS [--max-packet-size BYTES] \
+                         [--tls-cert CERT.pem --tls-key KEY.pem] \
+                         [--shutdown-timeout SECONDS] \
+                         [--client-limit PER_SECOND:BURST] [--group-limit PER_SECOND:BURST] \
+                         [--max-violations N] [--ws-address ADDRESS] \
+                         [--metrics-address ADDRESS] [--admin-token TOKEN] \
+                         [--plugin NAME]... [--max-attachment-size BYTES] \
+                         [--attachment-storage BYTES] [--peer-address ADDRESS] \
+                         [--peer ADDRESS]... [--peer-token TOKEN] \
+                         [--peer-tls-ca CERT.pem [--peer-tls-domain NAME]]";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub address: String,
+    /// packets mas grandes que esto se descartan y se le contesta al cliente con un error
+    pub max_packet_size: usize,
+    /// si estan los dos todas las conexiones van por TLS
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// cuanto esperamos a que se terminen de mandar los mensajes cuando nos piden apagarnos
+    pub shutdown_timeout: Duration,
+    /// cuantos packets por segundo puede mandar cada conexion
+    pub client_limit: RateLimit,
+    /// cuantos mensajes por segundo acepta cada grupo, sumando los de todos sus miembros
+    pub group_limit: RateLimit,
+    /// despues de pasarse del limite (o de mandar pedazos de archivos que no se estan subiendo)
+    /// tantas veces cerramos la conexion (se olvida de una violacion cada `VIOLATION_DECAY`)
+    pub max_violations: u32,
+    /// si esta escuchamos tambien conexiones de WebSocket en esta direccion
+    pub ws_address: Option<String>,
+    /// si esta servimos las metricas para Prometheus en `http://ADDRESS/metrics`
+    pub metrics_address: Option<String>,
+    /// sin token no se aceptan comandos de administrador
+    pub admin_token: Option<String>,
+    /// los plugins que usan todos los grupos, en el orden en el que se llaman
+    pub plugins: Vec<String>,
+    /// el tamanio maximo de cada archivo que se sube a un grupo
+    pub max_attachment_size: u64,
+    /// lo que pueden ocupar entre todos los archivos, se guardan en memoria
+    pub attachment_storage: u64,
+    /// si esta esperamos que otros servers se conecten en esta direccion para compartir los grupos
+    pub peer_address: Option<String>,
+    /// los `--peer-address` de los otros servers a los que nos conectamos
+    pub peers: Vec<String>,
+    /// el secreto que comparten todos los servers de la federacion, sin el no se acepta ningun
+    /// par. Hace falta si hay `--peer-address` o `--peer`
+    pub peer_token: Option<String>,
+    /// si esta nos conectamos a los pares por TLS, es el certificado en el que confiamos
+    pub peer_tls_ca: Option<PathBuf>,
+    /// contra que nombre se verifica el certificado de los pares, si no esta es el host de cada
+    /// `--peer`
+    pub peer_tls_domain: Option<String>,
+}
+
+impl Config {
+    pub fn from_args<I>(mut args: I) -> ChatResult<Self>
+    where
+        I: Iterator<Item = String>,
+    {
+        let address = args.next().ok_or(USAGE)?;
+        let mut config = Self {
+            address,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            tls_cert: None,
+            tls_key: None,
+            shutdown_timeout: Duration::from_secs(5),
+            client_limit: RateLimit::new(10.0, 20.0),
+            group_limit: RateLimit::new(100.0, 200.0),
+            max_violations: 10,
+            ws_address: None,
+            metrics_address: None,
+            admin_token: None,
+            plugins: Vec::new(),
+            max_attachment_size: 4 * 1024 * 1024,
+            attachment_storage: 64 * 1024 * 1024,
+            peer_address: None,
+            peers: Vec::new(),
+            peer_token: None,
+            peer_tls_ca: None,
+            peer_tls_domain: None,
+        };
+
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {}\n{}", flag, USAGE))?;
+            match flag.as_str() {
+                "--max-packet-size" => config.max_packet_size = value.parse()?,
+                "--tls-cert" => config.tls_cert = Some(value.into()),
+                "--tls-key" => config.tls_key = Some(value.into()),
+                "--client-limit" => config.client_limit = RateLimit::parse(&value)?,
+                "--group-limit" => config.group_limit = RateLimit::parse(&value)?,
+                "--max-violations" => config.max_violations = value.parse()?,
+                "--ws-address" => config.ws_address = Some(value),
+                "--metrics-address" => config.metrics_address = Some(value),
+                "--admin-token" => config.admin_token = Some(value),
+                "--plugin" => config.plugins.push(value),
+                "--max-attachment-size" => config.max_attachment_size = value.parse()?,
+                "--attachment-storage" => config.attachment_storage = value.parse()?,
+                "--peer-address" => config.peer_address = Some(value),
+                "--peer" => config.peers.push(value),
+                "--peer-token" => config.peer_token = Some(value),
+                "--peer-tls-ca" => config.peer_tls_ca = Some(value.into()),
+                "--peer-tls-domain" => config.peer_tls_domain = Some(value),
+                "--shutdown-timeout" => {
+                    // `from_secs_f64` entra en panico con negativos, NaN o numeros enormes
+                    config.shutdown_timeout =
+                        Duration::try_from_secs_f64(value.parse()?).map_err(|err| {
+                            format!("invalid --shutdown-timeout {:?}: {}\n{}", value, err, USAGE)
+                        })?
+                }
+                _ => return Err(format!("unknown option {}\n{}", flag, USAGE).into()),
+            }
+        }
+
+        if config.tls_cert.is_some() != config.tls_key.is_some() {
+            return Err(format!("--tls-cert and --tls-key go together\n{}", USAGE).into());
+        }
+        let federated = config.peer_address.is_some() || !config.peers.is_empty();
+        let has_token = matches!(&config.peer_token, Some(token) if !token.is_empty());
+        if federated && !has_token {
+            return Err(format!("--peer-address and --peer need a --peer-token\n{}", USAGE).into());
+        }
+        if config.peer_tls_domain.is_some() && config.peer_tls_ca.is_none() {
+            return Err(format!("--peer-tls-domain needs --peer-tls-ca\n{}", USAGE).into());
+        }
+
+        Ok(config)
+    }
+
+    /// `None` si el server no usa TLS
+    pub fn tls_acceptor(&self) -> ChatResult<Option<TlsAcceptor>> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Ok(Some(tls::acceptor(cert, key)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Con que nos conectamos al par de `--peer ADDRESS`, `None` si los pares no usan TLS
+    pub fn peer_connector(
+        &self,
+        address: &str,
+    ) -> ChatResult<Option<(TlsConnector, ServerName<'static>)>> {
+        let ca = match &self.peer_tls_ca {
+            Some(ca) => ca,
+            None => return Ok(None),
+        };
+        let domain = match &self.peer_tls_domain {
+            Some(domain) => domain.as_str(),
+            None => address.rsplit_once(':').map_or("", |(host, _)| host),
+        };
+        Ok(Some((tls::connector(ca)?, tls::server_name(domain)?)))
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> ChatResult<Config> {
+        Config::from_args(
+            std::iter::once("127.0.0.1:0")
+                .chain(args.iter().copied())
+                .map(String::from),
+        )
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let config = parse(&["--shutdown-timeout", "0.5"]).unwrap();
+        assert_eq!(config.shutdown_timeout, Duration::from_millis(500));
+
+        for invalid in ["-1", "NaN", "inf", "1e300"] {
+            let error = parse(&["--shutdown-timeout", invalid]).unwrap_err();
+            assert!(error.to_string().contains(USAGE), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_peer_token() {
+        for args in [
+            &["--peer-address", "127.0.0.1:0"][..],
+            &["--peer", "127.0.0.1:1"],
+            &["--peer", "127.0.0.1:1", "--peer-token", ""],
+        ] {
+            let error = parse(args).unwrap_err();
+            assert!(error.to_string().contains("--peer-token"), "{:?}", args);
+        }
+        let config = parse(&["--peer", "127.0.0.1:1", "--peer-token", "secret"]).unwrap();
+        assert_eq!(config.peer_token.as_deref(), Some("secret"));
+        // sin federacion no hace falta
+        assert!(parse(&[]).unwrap().peer_token.is_none());
+    }
+}
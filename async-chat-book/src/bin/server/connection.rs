@@ -1,63 +1,674 @@
+use async_chat_book::attachment::CHUNK_SIZE;
 /// Handle a single client's connection
+use async_chat_book::codec::Codec;
 use async_chat_book::utils::{self, ChatResult};
-use async_chat_book::{FromClient, FromServer};
+use async_chat_book::{Attachment, FromClient, FromServer, GroupAccess, Versioned};
+use async_chat_book::{LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION};
 use async_std::io::BufReader;
-use async_std::net::TcpStream;
 use async_std::prelude::*; // recordar que este es importante!!!
 use async_std::sync::Arc;
 
-use crate::group_table::GroupTable;
+use crate::attachments::Uploads;
+use crate::group::Group;
+use crate::metrics::{self, Metrics};
+use crate::rate_limit::{TokenBucket, Violations, RATE_LIMITED};
+use crate::{admin, ChatServer};
+use std::net::SocketAddr;
 
-pub async fn serve(socket: TcpStream, groups: Arc<GroupTable>) -> ChatResult<()> {
-    let outbound = Arc::new(Outbound::new(socket.clone()));
+/// `reader` y `writer` son las dos mitades de la conexion, que puede ser TCP, TLS o un WebSocket
+pub async fn serve<R, W>(
+    reader: R,
+    writer: W,
+    peer: SocketAddr,
+    chat: Arc<ChatServer>,
+) -> ChatResult<()>
+where
+    R: async_std::io::Read + Unpin + Send,
+    W: async_std::io::Write + Unpin + Send + 'static,
+{
+    let ChatServer {
+        config,
+        groups,
+        connections,
+        attachments,
+        metrics,
+        ..
+    } = &*chat;
+    let mut buffered = BufReader::new(reader);
+    let codec = Codec::negotiate(&mut buffered).await?;
+    let outbound = Outbound::new(Box::new(writer), codec, metrics.clone());
+    // cuando se dropea tambien se termina la tarea que le escribe al cliente
+    let registration = connections.register(peer, outbound.clone());
+    // hasta que el cliente elija uno no tiene nombre
+    let mut client = Member::new(outbound.clone());
+    // los archivos que el cliente esta subiendo, si se corta la conexion se pierden
+    let mut uploads = Uploads::default();
 
-    let buffered = BufReader::new(socket);
-    let mut from_client = utils::receive_as_json(buffered);
-    while let Some(request_result) = from_client.next().await {
-        let request = request_result?;
+    let mut bucket = TokenBucket::new(config.client_limit);
+    let mut violations = Violations::new();
 
-        let result = match request {
-            FromClient::Join { group_name } => {
-                let group = groups.get_or_create(group_name);
-                group.join(outbound.clone());
-                Ok(())
+    let mut from_client = codec.receive(buffered, config.max_packet_size);
+    loop {
+        // si un administrador nos echa dejamos de esperar al cliente
+        let next_request = async { Some(from_client.next().await) };
+        let closed = async {
+            outbound.closed().await;
+            None
+        };
+        let request_result = match next_request.race(closed).await {
+            Some(Some(request_result)) => request_result,
+            Some(None) | None => break,
+        };
+        let id = request_result.as_ref().ok().and_then(Versioned::id);
+        // cada packet, bueno o malo, gasta un token de la conexion. Los pedazos de un archivo que
+        // se esta subiendo no, esos ya estan limitados por el tamanio maximo de un archivo. Un
+        // pedazo de una subida que no existe paga como cualquier otro
+        let is_chunk = matches!(
+            request_result.as_ref().map(Versioned::packet),
+            Ok(FromClient::UploadChunk { .. })
+        );
+        let active_chunk = match request_result.as_ref().map(Versioned::packet) {
+            Ok(FromClient::UploadChunk { upload_id, .. }) => uploads.is_active(*upload_id),
+            _ => false,
+        };
+        if !active_chunk && !bucket.try_take() {
+            record_violation(&mut violations, config.max_violations, &outbound, id, peer).await?;
+            outbound.reply(FromServer::Error(RATE_LIMITED.to_string()), id)?;
+            continue;
+        }
+
+        // un packet mal formado no es motivo para cortar la conexion, le avisamos al cliente
+        let versioned = match request_result {
+            Ok(versioned) => versioned,
+            Err(err) if utils::is_recoverable(&err) => {
+                outbound.send(FromServer::Error(err.to_string()))?;
+                continue;
             }
+            Err(err) => return Err(err),
+        };
+        // le contestamos en la version en la que nos hablo, o en la mas parecida que conocemos
+        let version = versioned.protocol_version();
+        outbound.set_protocol_version(version.clamp(LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION));
+        if !async_chat_book::is_supported(version) {
+            let message = format!(
+                "unsupported protocol version {}, this server speaks versions {} to {}",
+                version, LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION
+            );
+            outbound.reply(FromServer::Error(message), id)?;
+            continue;
+        }
+        let request = versioned.into_packet();
 
-            FromClient::Post {
-                group_name,
-                message,
-            } => match groups.get(&group_name) {
-                Some(group) => {
-                    group.post(message);
-                    Ok(())
+        // si sale bien algunos pedidos tienen una respuesta para el cliente
+        let result: Result<Option<FromServer>, String> =
+            match request {
+                FromClient::SetName {
+                    name: new_name,
+                    password,
+                } => registration
+                    .set_name(&new_name, password.as_deref())
+                    .map(|claimed| {
+                        client.name = Some(new_name);
+                        client.claimed = claimed;
+                        None
+                    }),
+
+                FromClient::CreateGroup { group_name, access } => {
+                    let checked =
+                        invited(&access).try_for_each(|member| connections.check_claimed(member));
+                    let owner = client.claimed_name().map(str::to_string);
+                    checked
+                        .and_then(|()| groups.create(group_name, access, owner))
+                        .map(|group| {
+                            metrics::increment(&metrics.joins);
+                            client.join(group);
+                            None
+                        })
                 }
-                None => Err(format!("Group '{}' does not exist", group_name)),
-            },
-        };
 
-        if let Err(message) = result {
-            let report = FromServer::Error(message);
-            outbound.send(report).await?;
+                FromClient::Join {
+                    group_name,
+                    password,
+                } => groups.find(&group_name).and_then(|group| {
+                    group.admit(client.claimed_name(), password.as_deref())?;
+                    metrics::increment(&metrics.joins);
+                    client.join(group);
+                    Ok(None)
+                }),
+
+                FromClient::Post {
+                    group_name,
+                    message,
+                } => groups.find(&group_name).and_then(|group| {
+                    // si no, cualquiera podria escribir en un grupo con password sin entrar
+                    group.check_member(&outbound)?;
+                    let seq = group.post(client.name.as_deref(), message)?;
+                    metrics::increment(&metrics.posts);
+                    Ok(Some(FromServer::Ack { group_name, seq }))
+                }),
+
+                FromClient::History {
+                    group_name,
+                    from,
+                    to,
+                } => groups.find(&group_name).and_then(|group| {
+                    group.check_member(&outbound)?;
+                    let messages = group.history(from, to);
+                    Ok(Some(FromServer::History {
+                        group_name,
+                        messages,
+                    }))
+                }),
+
+                FromClient::Invite { group_name, member } => groups
+                    .find(&group_name)
+                    .and_then(|group| {
+                        connections.check_claimed(&member)?;
+                        group.invite(client.claimed_name(), member)
+                    })
+                    .map(|()| None),
+
+                FromClient::Promote { group_name, member } => groups
+                    .find(&group_name)
+                    .and_then(|group| {
+                        connections.check_claimed(&member)?;
+                        group.promote(client.claimed_name(), member)
+                    })
+                    .map(|()| None),
+
+                FromClient::UploadStart {
+                    upload_id,
+                    group_name,
+                    file_name,
+                    size,
+                    checksum,
+                } => groups.find(&group_name).and_then(|group| {
+                    group.check_member(&outbound)?;
+                    let attachment = Attachment {
+                        id: 0,
+                        group_name,
+                        file_name,
+                        size,
+                        checksum,
+                    };
+                    uploads.start(attachments, upload_id, group, attachment)?;
+                    Ok(None)
+                }),
+
+                FromClient::UploadChunk { upload_id, data } => {
+                    uploads.chunk(upload_id, &data).map(|()| None)
+                }
+
+                FromClient::UploadFinish { upload_id } => uploads
+                    .finish(attachments, upload_id)
+                    .map(|(group, attachment)| {
+                        group.announce(FromServer::AttachmentAvailable(attachment));
+                        None
+                    }),
+
+                FromClient::Download { attachment_id } => {
+                    let found = attachments
+                        .get(attachment_id)
+                        .and_then(|(attachment, data)| {
+                            groups
+                                .find(&attachment.group_name)?
+                                .check_member(&outbound)?;
+                            Ok((attachment, data))
+                        });
+                    match found {
+                        Ok((attachment, data)) => {
+                            outbound.send_attachment(attachment, &data, id).await?;
+                            Ok(None)
+                        }
+                        Err(message) => Err(message),
+                    }
+                }
+
+                FromClient::Admin { token, command } => {
+                    admin::execute(&chat, &token, command).await.map(|()| None)
+                }
+            };
+
+        // un pedazo rechazado cuenta como violacion, si no se podrian mandar sin limite
+        let rejected_chunk = is_chunk && result.is_err();
+        match result {
+            Ok(Some(reply)) => outbound.reply(reply, id)?,
+            Ok(None) => {}
+            Err(message) => outbound.reply(FromServer::Error(message), id)?,
+        }
+        if rejected_chunk {
+            record_violation(&mut violations, config.max_violations, &outbound, id, peer).await?;
         }
     }
 
     Ok(())
 }
 
-use async_std::sync::Mutex;
+/// Cuenta una violacion y si con esta ya son `max_violations` cierra la conexion
+async fn record_violation(
+    violations: &mut Violations,
+    max_violations: u32,
+    outbound: &Outbound,
+    id: Option<u64>,
+    peer: SocketAddr,
+) -> ChatResult<()> {
+    let count = violations.record();
+    if count >= max_violations {
+        let notice = "too many rate limit violations, closing the connection";
+        outbound.reply(FromServer::Error(notice.to_string()), id)?;
+        outbound.close().await?;
+        return Err(format!("{} disconnected after {} violations", peer, count).into());
+    }
+    Ok(())
+}
 
-pub struct Outbound(Mutex<TcpStream>);
+/// El cliente visto desde los grupos: su nombre y en cuales entro. Cuando se dropea (termine como
+/// termine `serve`) sale de todos, asi los plugins se enteran
+struct Member {
+    outbound: Arc<Outbound>,
+    name: Option<String>,
+    /// si `name` esta reclamado y el cliente mando la password, solo asi cuenta para los roles
+    claimed: bool,
+    groups: Vec<Arc<Group>>,
+}
+
+impl Member {
+    fn new(outbound: Arc<Outbound>) -> Self {
+        Self {
+            outbound,
+            name: None,
+            claimed: false,
+            groups: Vec::new(),
+        }
+    }
+
+    /// El nombre con el que se chequea si es owner, moderador o invitado
+    fn claimed_name(&self) -> Option<&str> {
+        self.name.as_deref().filter(|_| self.claimed)
+    }
+
+    fn join(&mut self, group: Arc<Group>) {
+        group.join(self.outbound.clone(), self.name.as_deref());
+        if !self.groups.iter().any(|joined| Arc::ptr_eq(joined, &group)) {
+            self.groups.push(group);
+        }
+    }
+}
+
+/// Los nombres que estan en la lista de invitados
+fn invited(access: &GroupAccess) -> impl Iterator<Item = &String> {
+    match access {
+        GroupAccess::InviteOnly(invited) => invited.iter(),
+        _ => [].iter(),
+    }
+}
+
+impl Drop for Member {
+    fn drop(&mut self) {
+        for group in self.groups.drain(..) {
+            group.leave(&self.outbound, self.name.as_deref());
+        }
+    }
+}
+
+use async_std::channel::{self, Receiver, Sender, TrySendError};
+use async_std::task;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// La mitad de la conexion por la que le escribimos al cliente
+pub type ClientWriter = Box<dyn async_std::io::Write + Send + Unpin>;
+
+/// Cuantos packets puede tener encolados un cliente antes de que empecemos a tirarlos
+pub const QUEUE_CAPACITY: usize = 1000;
+
+/// Un packet encolado y el id del pedido al que responde, si responde a alguno
+type Reply = (FromServer, Option<u64>);
+
+/// Lo que le mandamos a un cliente: los packets se encolan y una tarea aparte los escribe en la
+/// conexion, asi un cliente lento no hace esperar a nadie
+pub struct Outbound {
+    queue: Sender<Reply>,
+    /// la version del protocolo en la que le escribimos, es la del ultimo packet que mando el
+    /// cliente (hasta que mande alguno suponemos que es un cliente viejo)
+    protocol_version: Arc<AtomicU32>,
+    /// los packets que tiramos porque la cola estaba llena, el cliente se entera despues
+    dropped: Arc<AtomicUsize>,
+    metrics: Arc<Metrics>,
+    /// lo que devolvio la tarea que escribe cuando termino
+    written: Receiver<ChatResult<()>>,
+    /// nunca mandamos nada por aca, cerrar el channel es la senial de que la conexion se cerro
+    closing: (Sender<()>, Receiver<()>),
+}
 
 impl Outbound {
-    pub fn new(to_client: TcpStream) -> Outbound {
-        Self(Mutex::new(to_client))
+    pub fn new(to_client: ClientWriter, codec: Codec, metrics: Arc<Metrics>) -> Arc<Outbound> {
+        let (queue, packets) = channel::bounded(QUEUE_CAPACITY);
+        let (done, written) = channel::bounded(1);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let protocol_version = Arc::new(AtomicU32::new(LEGACY_PROTOCOL_VERSION));
+
+        let lost = dropped.clone();
+        let version = protocol_version.clone();
+        task::spawn(async move {
+            let result = write_packets(to_client, codec, &packets, &lost, &version).await;
+            // si no pudimos escribir que nadie siga encolando
+            packets.close();
+            let _ = done.send(result).await;
+        });
+
+        Arc::new(Self {
+            queue,
+            protocol_version,
+            dropped,
+            metrics,
+            written,
+            closing: channel::bounded(1),
+        })
+    }
+
+    /// Encola el packet sin esperar, solo falla si la conexion ya se cerro
+    pub fn send(&self, packet: FromServer) -> ChatResult<()> {
+        self.reply(packet, None)
     }
 
-    pub async fn send(&self, packet: FromServer) -> ChatResult<()> {
-        let mut guard = self.0.lock().await;
-        utils::send_as_json(&mut *guard, &packet).await?;
-        guard.flush().await?;
+    /// Como `send` pero para contestarle al pedido `id`
+    pub fn reply(&self, packet: FromServer, id: Option<u64>) -> ChatResult<()> {
+        if let FromServer::Error(_) = packet {
+            metrics::increment(&self.metrics.errors);
+        }
+        match self.queue.try_send((packet, id)) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err("connection closed".into()),
+        }
+    }
 
+    /// Le manda un archivo entero al cliente que lo pidio. A diferencia de `reply` espera a que
+    /// haya lugar en la cola en vez de tirar los pedazos que no entran
+    pub async fn send_attachment(
+        &self,
+        attachment: Attachment,
+        data: &[u8],
+        id: Option<u64>,
+    ) -> ChatResult<()> {
+        let attachment_id = attachment.id;
+        let mut packets = vec![FromServer::AttachmentAvailable(attachment)];
+        packets.extend(
+            data.chunks(CHUNK_SIZE)
+                .map(|chunk| FromServer::AttachmentChunk {
+                    attachment_id,
+                    data: chunk.to_vec(),
+                }),
+        );
+        for packet in packets {
+            self.queue
+                .send((packet, id))
+                .await
+                .map_err(|_| "connection closed")?;
+        }
         Ok(())
     }
+
+    pub fn set_protocol_version(&self, protocol_version: u32) {
+        self.protocol_version
+            .store(protocol_version, Ordering::Relaxed);
+    }
+
+    /// Los packets encolados que todavia no se escribieron
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// No se encola nada mas, la tarea que escribe termina de mandar lo que quedaba y suelta su
+    /// mitad de la conexion
+    pub fn finish(&self) {
+        self.queue.close();
+        self.closing.0.close();
+    }
+
+    /// Como `finish` pero esperando a que se termine de escribir todo (en TCP el socket se
+    /// cierra de verdad cuando `serve` suelta la otra mitad)
+    pub async fn close(&self) -> ChatResult<()> {
+        self.finish();
+        // si ya lo cerro otro el resultado se lo llevo el
+        self.written.recv().await.unwrap_or(Ok(()))
+    }
+
+    /// Termina cuando alguien llama a `finish` o a `close`
+    pub async fn closed(&self) {
+        let _ = self.closing.1.recv().await;
+    }
+}
+
+/// La tarea que escribe en la conexion todo lo que se va encolando
+async fn write_packets(
+    mut to_client: ClientWriter,
+    codec: Codec,
+    packets: &Receiver<Reply>,
+    dropped: &AtomicUsize,
+    protocol_version: &AtomicU32,
+) -> ChatResult<()> {
+    while let Ok((packet, id)) = packets.recv().await {
+        let version = protocol_version.load(Ordering::Relaxed);
+        let lost = dropped.swap(0, Ordering::Relaxed);
+        if lost > 0 {
+            let notice = format!("Dropped {} messages, the connection is too slow.", lost);
+            let notice = Versioned::new(FromServer::Error(notice), version, None);
+            codec.send(&mut to_client, &notice).await?;
+        }
+        // un packet que el cliente no conoce le romperia la conexion, mejor no mandarlo
+        if packet.protocol_version() <= version {
+            let packet = Versioned::new(packet, version, id);
+            codec.send(&mut to_client, &packet).await?;
+        }
+        // si hay mas packets esperando los mandamos todos juntos en un solo flush
+        if packets.is_empty() {
+            to_client.flush().await?;
+        }
+    }
+    // `close` no esta en el `WriteExt` de async_std
+    futures::io::AsyncWriteExt::close(&mut to_client).await?;
+    Ok(())
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::harness::{Script, TestServer};
+    use async_chat_book::codec::Codec;
+    use async_chat_book::{FromClient, FromServer, GroupAccess, Logged, PROTOCOL_VERSION};
+    use async_std::task;
+    use std::sync::Arc;
+
+    fn set_name(name: &str) -> FromClient {
+        FromClient::SetName {
+            name: name.to_string(),
+            password: None,
+        }
+    }
+
+    fn create(group_name: &str) -> FromClient {
+        FromClient::CreateGroup {
+            group_name: Arc::new(group_name.to_string()),
+            access: GroupAccess::Open,
+        }
+    }
+
+    fn join(group_name: &str) -> FromClient {
+        FromClient::Join {
+            group_name: Arc::new(group_name.to_string()),
+            password: None,
+        }
+    }
+
+    fn post(group_name: &str, message: &str) -> FromClient {
+        FromClient::Post {
+            group_name: Arc::new(group_name.to_string()),
+            message: Arc::new(message.to_string()),
+        }
+    }
+
+    fn history(group_name: &str, from: u64, to: u64) -> FromClient {
+        FromClient::History {
+            group_name: Arc::new(group_name.to_string()),
+            from,
+            to,
+        }
+    }
+
+    fn message(group_name: &str, message: &str, seq: u64) -> FromServer {
+        FromServer::Message {
+            group_name: Arc::new(group_name.to_string()),
+            message: Arc::new(message.to_string()),
+            seq,
+        }
+    }
+
+    fn error(error: &str) -> FromServer {
+        FromServer::Error(error.to_string())
+    }
+
+    fn codec_of(client: usize) -> Codec {
+        if client.is_multiple_of(2) {
+            Codec::JsonLines
+        } else {
+            Codec::LengthPrefixed
+        }
+    }
+
+    #[test]
+    fn test_fan_out() {
+        const MEMBERS: usize = 10;
+        task::block_on(async {
+            let server = TestServer::start().await;
+            // el 0 crea el grupo, los demas entran y cada uno postea una vez, en orden
+            let mut scripts: Vec<Script> = (0..MEMBERS)
+                .map(|client| {
+                    let script = Script::new(codec_of(client));
+                    let script = if client == 0 {
+                        script.send(create("Dogs")).barrier()
+                    } else {
+                        script.barrier().send(join("Dogs"))
+                    };
+                    let mut script = script.barrier();
+                    for poster in 0..MEMBERS {
+                        if poster == client {
+                            script = script.send(post("Dogs", &format!("woof from {}", client)));
+                        }
+                        let expected =
+                            message("Dogs", &format!("woof from {}", poster), poster as u64 + 1);
+                        script = script.expect(expected).barrier();
+                    }
+                    script
+                })
+                .collect();
+
+            // uno que no esta en el grupo no recibe nada (el probe del final lo chequea)
+            let mut outsider = Script::new(Codec::JsonLines).barrier().barrier();
+            for _ in 0..MEMBERS {
+                outsider = outsider.barrier();
+            }
+            scripts.push(outsider);
+
+            server.run(scripts).await;
+        });
+    }
+
+    #[test]
+    fn test_unknown_group() {
+        task::block_on(async {
+            let server = TestServer::start().await;
+            let script = Script::new(Codec::LengthPrefixed)
+                .send(post("Cats", "meow"))
+                .expect(error("Group 'Cats' does not exist"))
+                .send(join("Cats"))
+                .expect(error("Group 'Cats' does not exist"))
+                .send(create("Cats"))
+                .send(create("Cats"))
+                .expect(error("Group 'Cats' already exists"))
+                .send(post("Cats", "meow"))
+                .expect(message("Cats", "meow", 1));
+            server.run(vec![script]).await;
+        });
+    }
+
+    #[test]
+    fn test_acks_and_history() {
+        task::block_on(async {
+            let server = TestServer::start().await;
+            let dogs = Arc::new("Dogs".to_string());
+            let logged = |seq: u64, message: &str| Logged {
+                seq,
+                message: Arc::new(message.to_string()),
+            };
+            let ack = |seq| FromServer::Ack {
+                group_name: dogs.clone(),
+                seq,
+            };
+            let poster = Script::new(Codec::JsonLines)
+                .protocol_version(PROTOCOL_VERSION)
+                .send(create("Dogs"))
+                .send(post("Dogs", "one"))
+                .expect(message("Dogs", "one", 1))
+                .expect(ack(1))
+                .send(post("Dogs", "two"))
+                .expect(message("Dogs", "two", 2))
+                .expect(ack(2))
+                .send(history("Dogs", 2, 10))
+                .expect(FromServer::History {
+                    group_name: dogs.clone(),
+                    messages: vec![logged(2, "two")],
+                })
+                .barrier();
+            // los que no estan en el grupo no pueden leer el historial
+            let outsider = Script::new(Codec::LengthPrefixed)
+                .protocol_version(PROTOCOL_VERSION)
+                .barrier()
+                .send(history("Dogs", 1, 2))
+                .expect(error("You are not a member of 'Dogs'"));
+            server.run(vec![poster, outsider]).await;
+        });
+    }
+
+    #[test]
+    fn test_disconnects() {
+        task::block_on(async {
+            let server = TestServer::start().await;
+            let alice = Script::new(Codec::JsonLines)
+                .send(set_name("alice"))
+                .barrier()
+                .send(create("Dogs"))
+                .barrier()
+                .disconnect()
+                .barrier()
+                .barrier();
+            // el grupo sigue andando para los que quedan
+            let bob = Script::new(Codec::LengthPrefixed)
+                .barrier()
+                .barrier()
+                .send(join("Dogs"))
+                .barrier()
+                .send(post("Dogs", "anyone here?"))
+                .expect(message("Dogs", "anyone here?", 1))
+                .barrier();
+            // y el nombre de alice queda libre. El impostor espera a que alice tenga su nombre,
+            // si no a veces se lo gana y el que recibe el error es alice
+            let impostor = Script::new(Codec::JsonLines)
+                .barrier()
+                .send(set_name("alice"))
+                .expect(error("The name 'alice' is already in use"))
+                .barrier()
+                .barrier()
+                .barrier()
+                .send(set_name("alice"));
+            server.run(vec![alice, bob, impostor]).await;
+
+            assert_eq!(server.chat.groups.len(), 1);
+        });
+    }
 }
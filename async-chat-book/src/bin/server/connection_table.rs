@@ -0,0 +1,135 @@
+//! Las conexiones que estan activas en el server, las necesitamos para poder avisarles a todos
+//! cuando el server se apaga
+use crate::connection::Outbound;
+use async_chat_book::utils::secrets_match;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub struct ConnectionTable {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, (SocketAddr, Arc<Outbound>)>>,
+    /// los nombres que eligieron los clientes, no puede haber dos conexiones con el mismo
+    names: Mutex<HashMap<u64, String>>,
+    /// los nombres reclamados con una password, siguen siendo de quien los reclamo aunque se
+    /// desconecte. Siempre se toma despues de `names`
+    claims: Mutex<HashMap<String, String>>,
+}
+
+impl ConnectionTable {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            active: Mutex::new(HashMap::new()),
+            names: Mutex::new(HashMap::new()),
+            claims: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Solo a los nombres reclamados se los puede invitar o hacer moderadores, si no el primero
+    /// que se pusiera el nombre se quedaria con la invitacion
+    pub fn check_claimed(&self, name: &str) -> Result<(), String> {
+        if self.claims.lock().unwrap().contains_key(name) {
+            Ok(())
+        } else {
+            Err(format!(
+                "The name '{}' is not claimed, only claimed names can be invited or promoted",
+                name
+            ))
+        }
+    }
+
+    /// La conexion queda en la tabla hasta que se dropea el `Registration` que devolvemos, y en
+    /// ese momento tambien se cierra la cola del `Outbound`
+    pub fn register(&self, peer: SocketAddr, outbound: Arc<Outbound>) -> Registration<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.active.lock().unwrap().insert(id, (peer, outbound));
+        Registration { table: self, id }
+    }
+
+    /// Busca la conexion que viene de `peer` (escrito como `IP:PUERTO`)
+    pub fn find(&self, peer: &str) -> Option<Arc<Outbound>> {
+        let active = self.active.lock().unwrap();
+        active
+            .values()
+            .find(|(address, _)| address.to_string() == peer)
+            .map(|(_, outbound)| outbound.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.active.lock().unwrap().len()
+    }
+
+    /// Los packets encolados en todas las conexiones que todavia no se escribieron
+    pub fn pending_messages(&self) -> usize {
+        self.active
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(_, outbound)| outbound.pending())
+            .sum()
+    }
+
+    /// Una copia de las conexiones activas en este momento, ordenadas por orden de llegada
+    pub fn snapshot(&self) -> Vec<(SocketAddr, Arc<Outbound>)> {
+        let active = self.active.lock().unwrap();
+        let mut ids: Vec<_> = active.keys().copied().collect();
+        ids.sort_unstable();
+        ids.iter().map(|id| active[id].clone()).collect()
+    }
+}
+
+pub struct Registration<'a> {
+    table: &'a ConnectionTable,
+    id: u64,
+}
+
+impl Registration<'_> {
+    /// El nombre queda tomado hasta que se cierra la conexion (o se cambia por otro). Con
+    /// `password` ademas se reclama para siempre, o se prueba que es nuestro si ya estaba
+    /// reclamado. Devuelve si la conexion puede usar el nombre para los roles de los grupos, que
+    /// es cuando esta reclamado
+    pub fn set_name(&self, name: &str, password: Option<&str>) -> Result<bool, String> {
+        if name.trim().is_empty() {
+            return Err("The name can't be empty".to_string());
+        }
+        if password.is_some_and(|password| password.is_empty()) {
+            return Err("The password can't be empty".to_string());
+        }
+        let mut names = self.table.names.lock().unwrap();
+        let mut claims = self.table.claims.lock().unwrap();
+        let claimed = match (claims.get(name), password) {
+            (Some(expected), Some(password)) if secrets_match(expected, password) => true,
+            (Some(_), _) => {
+                return Err(format!(
+                    "The name '{}' is claimed, it needs the right password",
+                    name
+                ))
+            }
+            (None, password) => password.is_some(),
+        };
+        if names
+            .iter()
+            .any(|(id, taken)| *id != self.id && taken == name)
+        {
+            return Err(format!("The name '{}' is already in use", name));
+        }
+        if let (false, Some(password)) = (claims.contains_key(name), password) {
+            claims.insert(name.to_string(), password.to_string());
+        }
+        names.insert(self.id, name.to_string());
+        Ok(claimed)
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.table.names.lock().unwrap().remove(&self.id);
+        let removed = self.table.active.lock().unwrap().remove(&self.id);
+        // los grupos pueden seguir teniendo el `Outbound`, asi que lo cerramos a mano
+        if let Some((_, outbound)) = removed {
+            outbound.finish();
+        }
+    }
+}
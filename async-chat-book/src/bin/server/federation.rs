@@ -0,0 +1,509 @@
+//! Varios servers que comparten los grupos
+//!
+//! Cada server se conecta con sus pares (`--peer ADDRESS`) o los espera (`--peer-address
+//! ADDRESS`), y por esas conexiones se pasan los grupos que se crean y los mensajes que se
+//! reparten en ellos. Lo que pasa en un server sale con un `Origin` unico y cada server reenvia lo
+//! que le llega a sus otros pares, asi que se los puede conectar de cualquier forma (en cadena, en
+//! triangulo...): cuando algo vuelve a un server que ya lo vio ahi se corta y no da vueltas para
+//! siempre
+//!
+//! Solo se acepta a los pares que saben el `--peer-token`. El que llama manda su `Hello` con el
+//! token primero y el que espera recien contesta con el suyo cuando lo chequeo, asi no se lo
+//! regalamos a cualquiera que se conecte. Si el server tiene `--tls-cert` el puerto de los pares
+//! tambien va por TLS, y los que llaman se conectan con `--peer-tls-ca`
+//!
+//! NOTE(elsuizo:2021-12-05): solo se comparte lo necesario para charlar. Cada server numera los
+//! mensajes en su propio historial, y los invites, moderadores, archivos y grupos que cierra un
+//! admin quedan en el server donde pasaron. Si dos servers tienen un grupo con el mismo nombre
+//! pero con otro acceso son grupos distintos y no se pasan los mensajes. Las passwords de los
+//! grupos viajan tal cual, sin TLS cualquiera que mire la red las puede leer (y el token tambien)
+use crate::group_table::GroupTable;
+use crate::{log_error, ChatServer};
+use async_chat_book::codec::Codec;
+use async_chat_book::utils::{secrets_match, ChatResult};
+use async_chat_book::GroupAccess;
+use async_std::channel::{self, Receiver, Sender};
+use async_std::io::BufReader;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Entre servers siempre hablamos JSON, asi se puede mirar lo que pasa con `nc`
+const PEER_CODEC: Codec = Codec::JsonLines;
+/// Cuantos `Origin` recordamos para no repartir dos veces lo mismo. Alcanza con que sean mas que
+/// los que pueden estar viajando a la vez por la federacion
+const SEEN_CAPACITY: usize = 4096;
+/// Cuanto esperamos antes de volver a llamar a un par que no contesta o que se desconecto
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Lo que se mandan los servers entre ellos
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum PeerPacket {
+    /// Lo primero que manda cada lado de la conexion, `token` es el `--peer-token`
+    Hello {
+        server_id: String,
+        #[serde(default)]
+        token: String,
+    },
+    GroupCreated {
+        origin: Origin,
+        group_name: Arc<String>,
+        access: GroupAccess,
+    },
+    /// Un mensaje que ya paso por los plugins del server donde se posteo
+    Posted {
+        origin: Origin,
+        group_name: Arc<String>,
+        message: Arc<String>,
+    },
+}
+
+/// El server donde paso algo y el numero que le puso, no se repite en toda la federacion
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Origin {
+    pub server_id: String,
+    pub number: u64,
+}
+
+/// Los pares con los que estamos conectados y lo que ya pasamos
+pub struct Federation {
+    server_id: String,
+    next_number: AtomicU64,
+    next_link: AtomicU64,
+    peers: Mutex<Vec<Peer>>,
+    seen: Mutex<Seen>,
+    /// los grupos que aca tienen otro acceso que en algun par, lo que se postea alla no entra
+    conflicts: Mutex<HashSet<Arc<String>>>,
+}
+
+struct Peer {
+    /// identifica a la conexion, con el mismo par puede haber dos (una para cada lado)
+    link: u64,
+    // NOTE(elsuizo:2021-12-05): la cola no tiene limite porque se llena con el lock de los
+    // miembros de un grupo tomado. Si un par no da abasto preferimos gastar memoria a perder
+    // mensajes sin avisar
+    queue: Sender<PeerPacket>,
+}
+
+impl Federation {
+    pub fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default();
+        Self::with_id(format!("{:x}-{}", nanos, std::process::id()))
+    }
+
+    pub fn with_id(server_id: String) -> Self {
+        Self {
+            server_id,
+            next_number: AtomicU64::new(0),
+            next_link: AtomicU64::new(0),
+            peers: Mutex::new(Vec::new()),
+            seen: Mutex::new(Seen::default()),
+            conflicts: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Cuantas conexiones con otros servers estan abiertas
+    pub fn peers(&self) -> usize {
+        self.peers.lock().unwrap().len()
+    }
+
+    fn origin(&self) -> Origin {
+        Origin {
+            server_id: self.server_id.clone(),
+            number: self.next_number.fetch_add(1, Ordering::Relaxed) + 1,
+        }
+    }
+
+    /// Le avisa a los pares que se creo un grupo en este server
+    pub fn group_created(&self, group_name: Arc<String>, access: GroupAccess) {
+        self.forward(
+            None,
+            PeerPacket::GroupCreated {
+                origin: self.origin(),
+                group_name,
+                access,
+            },
+        );
+    }
+
+    /// Le pasa a los pares un mensaje que se repartio en este server
+    pub fn posted(&self, group_name: Arc<String>, message: Arc<String>) {
+        // sin pares no hace falta ni numerarlo, y si el grupo es otro en los pares no les toca
+        if self.peers() == 0 || self.conflicts.lock().unwrap().contains(&group_name) {
+            return;
+        }
+        self.forward(
+            None,
+            PeerPacket::Posted {
+                origin: self.origin(),
+                group_name,
+                message,
+            },
+        );
+    }
+
+    /// Encola el packet en todos los pares menos en el de la conexion `except`, que es de donde
+    /// vino
+    fn forward(&self, except: Option<u64>, packet: PeerPacket) {
+        let mut peers = self.peers.lock().unwrap();
+        // `send` solo falla si la conexion ya se cerro
+        peers.retain(|peer| {
+            Some(peer.link) == except || peer.queue.try_send(packet.clone()).is_ok()
+        });
+    }
+
+    /// Encola el packet solo para la conexion `link`
+    fn send_to(&self, link: u64, packet: PeerPacket) {
+        let peers = self.peers.lock().unwrap();
+        if let Some(peer) = peers.iter().find(|peer| peer.link == link) {
+            let _ = peer.queue.try_send(packet);
+        }
+    }
+
+    /// Registra una conexion con el server `server_id` y devuelve su numero y la cola de lo que
+    /// hay que mandarle
+    fn add_peer(&self, server_id: &str) -> Result<(u64, Receiver<PeerPacket>), String> {
+        if server_id == self.server_id {
+            return Err("a server cannot be its own peer".to_string());
+        }
+        let link = self.next_link.fetch_add(1, Ordering::Relaxed) + 1;
+        let (queue, outgoing) = channel::unbounded();
+        self.peers.lock().unwrap().push(Peer { link, queue });
+        Ok((link, outgoing))
+    }
+
+    fn remove_peer(&self, link: u64) {
+        self.peers.lock().unwrap().retain(|peer| peer.link != link);
+    }
+
+    /// Procesa un packet que llego por la conexion `link`. Lo que es nuevo se aplica en este
+    /// server y se reenvia a los demas pares, lo que ya vimos (o salio de aca) se descarta
+    fn receive(&self, link: u64, packet: PeerPacket, groups: &GroupTable) {
+        let fresh = |origin: &Origin| {
+            origin.server_id != self.server_id && self.seen.lock().unwrap().insert(origin)
+        };
+        match &packet {
+            PeerPacket::Hello { .. } => return,
+            PeerPacket::GroupCreated {
+                origin,
+                group_name,
+                access,
+            } => {
+                if !fresh(origin) {
+                    return;
+                }
+                // si ya existe puede ser el mismo grupo que nos llego por otro camino, o uno
+                // distinto con el mismo nombre. A ese no le pasamos nada, si no los mensajes de un
+                // grupo con password terminarian en uno abierto
+                if groups
+                    .create_relayed(group_name.clone(), access.clone())
+                    .is_err()
+                {
+                    let differs = groups
+                        .get(group_name)
+                        .is_some_and(|local| !same_access(&local.access(), access));
+                    if differs {
+                        self.conflicts.lock().unwrap().insert(group_name.clone());
+                        log_error(Err(format!(
+                            "group '{}' has a different access in server {}, not relaying it",
+                            group_name, origin.server_id
+                        )
+                        .into()));
+                    }
+                }
+            }
+            PeerPacket::Posted {
+                origin,
+                group_name,
+                message,
+            } => {
+                if !fresh(origin) {
+                    return;
+                }
+                let conflict = self.conflicts.lock().unwrap().contains(group_name);
+                if let Some(group) = groups.get(group_name).filter(|_| !conflict) {
+                    group.relayed(message.clone());
+                }
+            }
+        }
+        self.forward(Some(link), packet);
+    }
+}
+
+/// Si dos grupos con el mismo nombre son el mismo. La lista de invitados no cuenta, los invites
+/// quedan en el server donde pasaron
+fn same_access(local: &GroupAccess, announced: &GroupAccess) -> bool {
+    match (local, announced) {
+        (GroupAccess::Open, GroupAccess::Open) => true,
+        (GroupAccess::Password(local), GroupAccess::Password(announced)) => local == announced,
+        (GroupAccess::InviteOnly(_), GroupAccess::InviteOnly(_)) => true,
+        _ => false,
+    }
+}
+
+/// Los ultimos `SEEN_CAPACITY` origins que pasaron por este server
+#[derive(Default)]
+struct Seen {
+    origins: HashSet<Origin>,
+    order: VecDeque<Origin>,
+}
+
+impl Seen {
+    /// `false` si ya lo habiamos visto
+    fn insert(&mut self, origin: &Origin) -> bool {
+        if !self.origins.insert(origin.clone()) {
+            return false;
+        }
+        if self.order.len() == SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.origins.remove(&oldest);
+            }
+        }
+        self.order.push_back(origin.clone());
+        true
+    }
+}
+
+/// Espera que los otros servers se conecten en `--peer-address`, con el mismo certificado que
+/// los clientes si el server usa TLS
+pub async fn accept_peers(listener: TcpListener, chat: Arc<ChatServer>) -> ChatResult<()> {
+    let mut incoming = listener.incoming();
+    while let Some(socket) = incoming.next().await {
+        let socket = socket?;
+        let chat = chat.clone();
+        task::spawn(async move {
+            let linked = async {
+                socket.set_nodelay(true)?;
+                match &chat.acceptor {
+                    Some(acceptor) => {
+                        let socket = acceptor.accept(socket).await?;
+                        link(socket, Side::Accepted, chat.clone()).await
+                    }
+                    None => link(socket, Side::Accepted, chat.clone()).await,
+                }
+            };
+            log_error(linked.await)
+        });
+    }
+    Ok(())
+}
+
+/// Se conecta con el par de `--peer` y si la conexion se corta la vuelve a abrir, mientras viva
+/// el server
+pub async fn dial(address: String, chat: Arc<ChatServer>) {
+    let tls = match chat.config.peer_connector(&address) {
+        Ok(tls) => tls,
+        Err(err) => return log_error(Err(err)),
+    };
+    loop {
+        if let Ok(socket) = TcpStream::connect(&address).await {
+            let linked = async {
+                socket.set_nodelay(true)?;
+                match &tls {
+                    Some((connector, domain)) => {
+                        let socket = connector.connect(domain.clone(), socket).await?;
+                        link(socket, Side::Dialed, chat.clone()).await
+                    }
+                    None => link(socket, Side::Dialed, chat.clone()).await,
+                }
+            };
+            log_error(linked.await);
+        }
+        task::sleep(RETRY_DELAY).await;
+    }
+}
+
+/// Quien abrio la conexion, el que llama es el primero en mandar el `Hello`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Dialed,
+    Accepted,
+}
+
+/// Atiende la conexion con otro server hasta que se corta, sin importar quien llamo a quien
+async fn link<S>(socket: S, side: Side, chat: Arc<ChatServer>) -> ChatResult<()>
+where
+    S: async_std::io::Read + async_std::io::Write + Unpin + Send + 'static,
+{
+    use futures::io::AsyncReadExt;
+
+    let federation = &chat.federation;
+    let token = chat
+        .config
+        .peer_token
+        .as_deref()
+        .ok_or("the federation needs a --peer-token")?;
+    // como un stream TLS no se puede clonar lo partimos en una mitad para leer y otra para
+    // escribir
+    let (reader, mut writer) = socket.split();
+    let hello = PeerPacket::Hello {
+        server_id: federation.server_id.clone(),
+        token: token.to_string(),
+    };
+    if side == Side::Dialed {
+        PEER_CODEC.send(&mut writer, &hello).await?;
+    }
+
+    let mut packets = PEER_CODEC.receive(BufReader::new(reader), chat.config.max_packet_size);
+    let server_id = match packets.next().await {
+        Some(Ok(PeerPacket::Hello {
+            server_id,
+            token: given,
+        })) if secrets_match(token, &given) => server_id,
+        Some(Ok(PeerPacket::Hello { .. })) => {
+            return Err("the peer sent the wrong --peer-token".into())
+        }
+        _ => return Err("the peer did not start with a Hello".into()),
+    };
+    if side == Side::Accepted {
+        PEER_CODEC.send(&mut writer, &hello).await?;
+    }
+    let (link, outgoing) = federation.add_peer(&server_id)?;
+    // el par puede haber llegado tarde, le contamos los grupos que ya existen. Si alguno ya lo
+    // tenia no pasa nada
+    for group in chat.groups.list() {
+        let created = PeerPacket::GroupCreated {
+            origin: federation.origin(),
+            group_name: group.name().clone(),
+            access: group.access(),
+        };
+        federation.send_to(link, created);
+    }
+
+    let reading = async {
+        while let Some(packet) = packets.next().await {
+            federation.receive(link, packet?, &chat.groups);
+        }
+        Ok(())
+    };
+    let result = reading.race(send_packets(writer, outgoing)).await;
+    federation.remove_peer(link);
+    result
+}
+
+/// Le manda al par lo que se va encolando, termina cuando lo sacamos de la federacion
+async fn send_packets<W>(mut writer: W, outgoing: Receiver<PeerPacket>) -> ChatResult<()>
+where
+    W: async_std::io::Write + Unpin,
+{
+    while let Ok(packet) = outgoing.recv().await {
+        PEER_CODEC.send(&mut writer, &packet).await?;
+    }
+    Ok(())
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::Plugins;
+    use crate::rate_limit::RateLimit;
+
+    fn origin(server_id: &str, number: u64) -> Origin {
+        Origin {
+            server_id: server_id.to_string(),
+            number,
+        }
+    }
+
+    fn posted(origin: Origin, message: &str) -> PeerPacket {
+        PeerPacket::Posted {
+            origin,
+            group_name: Arc::new("Dogs".to_string()),
+            message: Arc::new(message.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_seen() {
+        let mut seen = Seen::default();
+        assert!(seen.insert(&origin("a", 1)));
+        assert!(!seen.insert(&origin("a", 1)));
+        assert!(seen.insert(&origin("b", 1)));
+        // los mas viejos se olvidan
+        for number in 2..=SEEN_CAPACITY as u64 {
+            seen.insert(&origin("a", number));
+        }
+        assert!(seen.insert(&origin("a", 1)));
+        assert!(!seen.insert(&origin("a", SEEN_CAPACITY as u64)));
+    }
+
+    #[test]
+    fn test_forward_once() {
+        let plugins: Plugins = Arc::new([]);
+        let federation = Arc::new(Federation::with_id("b".to_string()));
+        let groups = GroupTable::new(RateLimit::new(1.0, 1.0), plugins, federation.clone());
+        assert_eq!(
+            federation.add_peer("b").err(),
+            Some("a server cannot be its own peer".to_string())
+        );
+        let (from_a, to_a) = federation.add_peer("a").unwrap();
+        let (_, to_c) = federation.add_peer("c").unwrap();
+
+        let woof = posted(origin("a", 1), "woof");
+        federation.receive(from_a, woof.clone(), &groups);
+        // si vuelve por otro camino ya no se reenvia, y lo nuestro nunca vuelve a entrar
+        federation.receive(from_a, woof.clone(), &groups);
+        federation.receive(from_a, posted(origin("b", 1), "echo"), &groups);
+
+        assert_eq!(to_c.try_recv(), Ok(woof));
+        assert!(to_c.try_recv().is_err());
+        assert!(to_a.try_recv().is_err());
+
+        federation.posted(Arc::new("Dogs".to_string()), Arc::new("yip".to_string()));
+        assert_eq!(to_a.try_recv(), Ok(posted(origin("b", 1), "yip")));
+        assert_eq!(to_c.try_recv(), Ok(posted(origin("b", 1), "yip")));
+    }
+
+    #[test]
+    fn test_conflicting_access() {
+        let plugins: Plugins = Arc::new([]);
+        let federation = Arc::new(Federation::with_id("b".to_string()));
+        let groups = GroupTable::new(RateLimit::new(10.0, 10.0), plugins, federation.clone());
+        let dogs = Arc::new("Dogs".to_string());
+        let cats = Arc::new("Cats".to_string());
+        let dogs_here = groups
+            .create(dogs.clone(), GroupAccess::Open, None)
+            .unwrap();
+        let invited = GroupAccess::InviteOnly(vec!["alice".to_string()]);
+        let cats_here = groups.create(cats.clone(), invited, None).unwrap();
+        let (from_a, to_a) = federation.add_peer("a").unwrap();
+
+        let created = |number, group_name: &Arc<String>, access| PeerPacket::GroupCreated {
+            origin: origin("a", number),
+            group_name: group_name.clone(),
+            access,
+        };
+        // en `a` Dogs tiene password, es otro grupo. Cats es el mismo aunque los invitados sean
+        // otros
+        let password = GroupAccess::Password("woof".to_string());
+        federation.receive(from_a, created(1, &dogs, password), &groups);
+        let invited = GroupAccess::InviteOnly(vec!["bob".to_string()]);
+        federation.receive(from_a, created(2, &cats, invited), &groups);
+
+        federation.receive(from_a, posted(origin("a", 3), "secret woof"), &groups);
+        let meow = PeerPacket::Posted {
+            origin: origin("a", 4),
+            group_name: cats.clone(),
+            message: Arc::new("meow".to_string()),
+        };
+        federation.receive(from_a, meow, &groups);
+        assert!(dogs_here.history(0, u64::MAX).is_empty());
+        assert_eq!(cats_here.history(0, u64::MAX).len(), 1);
+
+        // y lo de nuestro Dogs tampoco sale
+        while to_a.try_recv().is_ok() {}
+        federation.posted(dogs, Arc::new("open woof".to_string()));
+        assert!(to_a.try_recv().is_err());
+    }
+}
@@ -1,18 +1,423 @@
 //! A chat group
 
 use crate::connection::Outbound;
-use async_std::task;
-use std::sync::Arc;
-use tokio::sync::broadcast;
+use crate::federation::Federation;
+use crate::plugin::{Membership, Plugins, Post};
+use crate::rate_limit::{RateLimit, TokenBucket, RATE_LIMITED};
+use async_chat_book::utils::secrets_match;
+use async_chat_book::{FromServer, GroupAccess, Logged};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Cuantos mensajes guarda cada grupo para los clientes que se perdieron alguno
+pub const HISTORY_SIZE: usize = 256;
 
 pub struct Group {
     name: Arc<String>,
-    sender: broadcast::Sender<Arc<String>>,
+    /// repartir un mensaje es solo encolarlo en cada miembro, asi que nunca esperamos con el lock
+    /// tomado
+    members: Mutex<Vec<Arc<Outbound>>>,
+    /// los ultimos mensajes, cada uno con su numero de secuencia. Siempre se toma despues de
+    /// `members`
+    history: Mutex<History>,
+    /// el limite es para todo el grupo, asi varios clientes juntos tampoco lo pueden inundar
+    bucket: Mutex<TokenBucket>,
+    roles: Mutex<Roles>,
+    plugins: Plugins,
+    /// lo que se reparte aca tambien se le pasa a los otros servers
+    federation: Arc<Federation>,
 }
 
 impl Group {
-    pub fn new(name: Arc<String>) -> Self {
-        let (sender, _receiver) = broadcast::channel(1000);
-        Self { name, sender }
+    /// `owner` es el nombre del que creo el grupo, si tenia uno
+    pub fn new(
+        name: Arc<String>,
+        limit: RateLimit,
+        access: GroupAccess,
+        owner: Option<String>,
+        plugins: Plugins,
+        federation: Arc<Federation>,
+    ) -> Self {
+        Self {
+            name,
+            members: Mutex::new(Vec::new()),
+            history: Mutex::new(History::default()),
+            bucket: Mutex::new(TokenBucket::new(limit)),
+            roles: Mutex::new(Roles::new(access, owner)),
+            plugins,
+            federation,
+        }
+    }
+
+    pub fn name(&self) -> &Arc<String> {
+        &self.name
+    }
+
+    pub fn access(&self) -> GroupAccess {
+        self.roles.lock().unwrap().access.clone()
+    }
+
+    /// Chequea que el cliente (con su nombre, si tiene uno) pueda entrar al grupo
+    pub fn admit(&self, name: Option<&str>, password: Option<&str>) -> Result<(), String> {
+        self.roles
+            .lock()
+            .unwrap()
+            .admit(name, password)
+            .map_err(|reason| format!("Cannot join '{}': {}", self.name, reason))
+    }
+
+    pub fn invite(&self, by: Option<&str>, member: String) -> Result<(), String> {
+        self.roles
+            .lock()
+            .unwrap()
+            .invite(by, member)
+            .map_err(|reason| format!("Cannot invite to '{}': {}", self.name, reason))
+    }
+
+    pub fn promote(&self, by: Option<&str>, member: String) -> Result<(), String> {
+        self.roles
+            .lock()
+            .unwrap()
+            .promote(by, member)
+            .map_err(|reason| format!("Cannot promote in '{}': {}", self.name, reason))
+    }
+
+    /// `name` es el del cliente que entra, si tiene uno, para los plugins
+    pub fn join(&self, outbound: Arc<Outbound>, name: Option<&str>) {
+        {
+            let mut members = self.members.lock().unwrap();
+            // si ya estaba no queremos que le lleguen los mensajes repetidos
+            if members.iter().any(|member| Arc::ptr_eq(member, &outbound)) {
+                return;
+            }
+            members.push(outbound);
+        }
+        let mut event = Membership::new(&self.name, name);
+        for plugin in self.plugins.iter() {
+            plugin.on_join(&mut event);
+        }
+        self.deliver_all(event.into_replies());
+    }
+
+    /// Saca al cliente del grupo, si estaba
+    pub fn leave(&self, outbound: &Arc<Outbound>, name: Option<&str>) {
+        {
+            let mut members = self.members.lock().unwrap();
+            let before = members.len();
+            members.retain(|member| !Arc::ptr_eq(member, outbound));
+            if members.len() == before {
+                return;
+            }
+        }
+        let mut event = Membership::new(&self.name, name);
+        for plugin in self.plugins.iter() {
+            plugin.on_leave(&mut event);
+        }
+        self.deliver_all(event.into_replies());
+    }
+
+    pub fn is_member(&self, outbound: &Arc<Outbound>) -> bool {
+        let members = self.members.lock().unwrap();
+        members.iter().any(|member| Arc::ptr_eq(member, outbound))
+    }
+
+    /// Como `is_member` pero con el error que le mandamos al cliente
+    pub fn check_member(&self, outbound: &Arc<Outbound>) -> Result<(), String> {
+        if self.is_member(outbound) {
+            Ok(())
+        } else {
+            Err(format!("You are not a member of '{}'", self.name))
+        }
+    }
+
+    /// Devuelve el numero de secuencia que le toco al mensaje. `author` es el nombre del que lo
+    /// mando, si tiene uno, para los plugins
+    pub fn post(&self, author: Option<&str>, message: Arc<String>) -> Result<u64, String> {
+        if !self.bucket.lock().unwrap().try_take() {
+            return Err(RATE_LIMITED.to_string());
+        }
+        let mut post = Post::new(&self.name, author, Arc::unwrap_or_clone(message));
+        for plugin in self.plugins.iter() {
+            plugin.on_post(&mut post);
+            if let Some(reason) = post.rejected() {
+                return Err(format!(
+                    "Message to '{}' was dropped by {}: {}",
+                    self.name,
+                    plugin.name(),
+                    reason
+                ));
+            }
+        }
+        let (message, replies) = post.into_parts();
+
+        // con `members` tomado nadie mas puede repartir, asi que los mensajes se encolan en el
+        // mismo orden que sus numeros y todos los miembros los ven en ese orden
+        let mut members = self.members.lock().unwrap();
+        let seq = self.deliver(&mut members, Arc::new(message));
+        // las respuestas de los plugins van justo despues del mensaje que las provoco
+        for reply in replies {
+            self.deliver(&mut members, Arc::new(reply));
+        }
+        Ok(seq)
+    }
+
+    /// Reparte un mensaje que se posteo en otro server, ya paso por los plugins de alla
+    pub fn relayed(&self, message: Arc<String>) {
+        let mut members = self.members.lock().unwrap();
+        self.fan_out(&mut members, message);
+    }
+
+    /// Reparte el mensaje y se lo pasa a los otros servers
+    fn deliver(&self, members: &mut Vec<Arc<Outbound>>, message: Arc<String>) -> u64 {
+        let seq = self.fan_out(members, message.clone());
+        self.federation.posted(self.name.clone(), message);
+        seq
+    }
+
+    /// Le da un numero al mensaje, lo guarda en el historial y lo encola en cada miembro
+    fn fan_out(&self, members: &mut Vec<Arc<Outbound>>, message: Arc<String>) -> u64 {
+        let seq = self.history.lock().unwrap().push(message.clone());
+        let packet = FromServer::Message {
+            group_name: self.name.clone(),
+            message,
+            seq,
+        };
+        // NOTE(elsuizo:2021-11-14): `send` solo falla cuando la conexion ya se cerro, aprovechamos
+        // para sacar a ese miembro del grupo
+        members.retain(|member| member.send(packet.clone()).is_ok());
+        seq
+    }
+
+    fn deliver_all(&self, messages: Vec<String>) {
+        if messages.is_empty() {
+            return;
+        }
+        let mut members = self.members.lock().unwrap();
+        for message in messages {
+            self.deliver(&mut members, Arc::new(message));
+        }
+    }
+
+    /// Los mensajes entre `from` y `to` (incluidos) que todavia estan en el historial
+    pub fn history(&self, from: u64, to: u64) -> Vec<Logged> {
+        self.history.lock().unwrap().range(from, to)
+    }
+
+    /// Le manda el packet a todos los miembros, sin numerarlo ni guardarlo en el historial
+    pub fn announce(&self, packet: FromServer) {
+        let mut members = self.members.lock().unwrap();
+        members.retain(|member| member.send(packet.clone()).is_ok());
+    }
+
+    /// Le avisa a todos los miembros que el grupo se cerro y los saca
+    pub fn close(&self) {
+        let notice = format!("Group '{}' was closed", self.name);
+        for member in self.members.lock().unwrap().drain(..) {
+            let _ = member.send(FromServer::Error(notice.clone()));
+        }
+    }
+}
+
+/// Los ultimos `HISTORY_SIZE` mensajes del grupo
+#[derive(Default)]
+struct History {
+    /// el numero del ultimo mensaje, el primero es el 1
+    last_seq: u64,
+    messages: VecDeque<Logged>,
+}
+
+impl History {
+    fn push(&mut self, message: Arc<String>) -> u64 {
+        self.last_seq += 1;
+        if self.messages.len() == HISTORY_SIZE {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(Logged {
+            seq: self.last_seq,
+            message,
+        });
+        self.last_seq
+    }
+
+    fn range(&self, from: u64, to: u64) -> Vec<Logged> {
+        self.messages
+            .iter()
+            .filter(|logged| (from..=to).contains(&logged.seq))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Quien puede entrar al grupo y quien lo administra, todo por nombre
+///
+/// NOTE(elsuizo:2021-11-21): los nombres que llegan aca son solo los reclamados con password
+/// (ver `Registration::set_name`), si no cualquiera que se pusiera el nombre del owner mientras
+/// esta desconectado pasaba a ser el owner
+struct Roles {
+    access: GroupAccess,
+    owner: Option<String>,
+    moderators: HashSet<String>,
+}
+
+impl Roles {
+    fn new(access: GroupAccess, owner: Option<String>) -> Self {
+        Self {
+            access,
+            owner,
+            moderators: HashSet::new(),
+        }
+    }
+
+    fn is_owner(&self, name: Option<&str>) -> bool {
+        name.is_some() && name == self.owner.as_deref()
+    }
+
+    fn can_moderate(&self, name: Option<&str>) -> bool {
+        self.is_owner(name) || name.is_some_and(|name| self.moderators.contains(name))
+    }
+
+    fn admit(&self, name: Option<&str>, password: Option<&str>) -> Result<(), &'static str> {
+        // el owner y los moderadores siempre pueden entrar
+        if self.can_moderate(name) {
+            return Ok(());
+        }
+        match &self.access {
+            GroupAccess::Open => Ok(()),
+            GroupAccess::Password(expected)
+                if password.is_some_and(|password| secrets_match(expected, password)) =>
+            {
+                Ok(())
+            }
+            GroupAccess::Password(_) => Err("wrong password"),
+            GroupAccess::InviteOnly(invited)
+                if name.is_some_and(|name| invited.iter().any(|member| member == name)) =>
+            {
+                Ok(())
+            }
+            GroupAccess::InviteOnly(_) => Err("you are not invited"),
+        }
+    }
+
+    fn invite(&mut self, by: Option<&str>, member: String) -> Result<(), &'static str> {
+        if !self.can_moderate(by) {
+            return Err("only the owner and the moderators can invite");
+        }
+        match &mut self.access {
+            GroupAccess::InviteOnly(invited) => {
+                if !invited.contains(&member) {
+                    invited.push(member);
+                }
+                Ok(())
+            }
+            _ => Err("the group is not invite-only"),
+        }
+    }
+
+    fn promote(&mut self, by: Option<&str>, member: String) -> Result<(), &'static str> {
+        if !self.is_owner(by) {
+            return Err("only the owner can promote moderators");
+        }
+        self.moderators.insert(member);
+        Ok(())
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = History::default();
+        let seqs: Vec<u64> = (0..HISTORY_SIZE + 2)
+            .map(|n| history.push(Arc::new(n.to_string())))
+            .collect();
+        assert_eq!(seqs.first(), Some(&1));
+        assert_eq!(seqs.last(), Some(&(HISTORY_SIZE as u64 + 2)));
+
+        // los dos primeros ya se fueron del historial
+        let logged = history.range(1, 4);
+        assert_eq!(
+            logged,
+            [
+                Logged {
+                    seq: 3,
+                    message: Arc::new("2".to_string())
+                },
+                Logged {
+                    seq: 4,
+                    message: Arc::new("3".to_string())
+                },
+            ]
+        );
+        assert!(history.range(5, 4).is_empty());
+        assert_eq!(history.range(0, u64::MAX).len(), HISTORY_SIZE);
+    }
+
+    #[test]
+    fn test_open_group() {
+        let roles = Roles::new(GroupAccess::Open, None);
+        assert_eq!(roles.admit(None, None), Ok(()));
+        assert_eq!(roles.admit(Some("tom"), Some("whatever")), Ok(()));
+    }
+
+    #[test]
+    fn test_password_group() {
+        let roles = Roles::new(GroupAccess::Password("woof".to_string()), None);
+        assert_eq!(roles.admit(None, Some("woof")), Ok(()));
+        assert_eq!(roles.admit(None, Some("meow")), Err("wrong password"));
+        assert_eq!(roles.admit(Some("tom"), None), Err("wrong password"));
+    }
+
+    #[test]
+    fn test_invite_only_group() {
+        let owner = Some("alice".to_string());
+        let invited = vec!["bob".to_string()];
+        let mut roles = Roles::new(GroupAccess::InviteOnly(invited), owner);
+        assert_eq!(roles.admit(Some("alice"), None), Ok(()));
+        assert_eq!(roles.admit(Some("bob"), None), Ok(()));
+        assert_eq!(roles.admit(Some("carol"), None), Err("you are not invited"));
+        assert_eq!(roles.admit(None, None), Err("you are not invited"));
+
+        assert_eq!(
+            roles.invite(Some("bob"), "carol".to_string()),
+            Err("only the owner and the moderators can invite")
+        );
+        assert_eq!(roles.invite(Some("alice"), "carol".to_string()), Ok(()));
+        assert_eq!(roles.admit(Some("carol"), None), Ok(()));
+    }
+
+    #[test]
+    fn test_moderators() {
+        let owner = Some("alice".to_string());
+        let mut roles = Roles::new(GroupAccess::InviteOnly(Vec::new()), owner);
+        assert_eq!(
+            roles.promote(Some("bob"), "bob".to_string()),
+            Err("only the owner can promote moderators")
+        );
+        assert_eq!(roles.promote(Some("alice"), "bob".to_string()), Ok(()));
+        // un moderador puede entrar e invitar, pero no nombrar a otros moderadores
+        assert_eq!(roles.admit(Some("bob"), None), Ok(()));
+        assert_eq!(roles.invite(Some("bob"), "carol".to_string()), Ok(()));
+        assert_eq!(
+            roles.promote(Some("bob"), "carol".to_string()),
+            Err("only the owner can promote moderators")
+        );
+    }
+
+    #[test]
+    fn test_group_without_owner_has_no_moderators() {
+        let mut roles = Roles::new(GroupAccess::InviteOnly(Vec::new()), None);
+        assert_eq!(
+            roles.invite(None, "bob".to_string()),
+            Err("only the owner and the moderators can invite")
+        );
+        assert_eq!(
+            roles.promote(None, "bob".to_string()),
+            Err("only the owner can promote moderators")
+        );
     }
 }
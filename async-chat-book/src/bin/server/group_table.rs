@@ -1,25 +1,85 @@
+use crate::federation::Federation;
 use crate::group::Group;
-use std::collections::HashMap;
+use crate::plugin::Plugins;
+use crate::rate_limit::RateLimit;
+use async_chat_book::GroupAccess;
+use std::collections::hash_map::{Entry, HashMap};
 use std::sync::{Arc, Mutex};
 
 // NOTE(elsuizo:2021-11-14): recordar que es una tuple-struct
-pub struct GroupTable(Mutex<HashMap<Arc<String>, Arc<Group>>>);
+// el segundo campo es el limite de mensajes con el que se crea cada grupo nuevo y el tercero
+// los plugins que usan todos los grupos. El cuarto son los otros servers con los que se
+// comparten los grupos
+pub struct GroupTable(
+    Mutex<HashMap<Arc<String>, Arc<Group>>>,
+    RateLimit,
+    Plugins,
+    Arc<Federation>,
+);
 
 impl GroupTable {
-    pub fn new() -> Self {
-        Self(Mutex::new(HashMap::new()))
+    pub fn new(group_limit: RateLimit, plugins: Plugins, federation: Arc<Federation>) -> Self {
+        Self(Mutex::new(HashMap::new()), group_limit, plugins, federation)
     }
 
     pub fn get(&self, name: &String) -> Option<Arc<Group>> {
-        self.0.lock().unwrap().get().cloned()
+        self.0.lock().unwrap().get(name).cloned()
     }
 
-    pub fn get_or_create(&self, name: Arc<String>) -> Arc<Group> {
-        self.0
-            .lock()
-            .unwrap()
-            .entry()
-            .or_insert_with(|| Arc::new(Group::new(name)))
-            .clone()
+    /// Como `get` pero con el error que le mandamos al cliente si el grupo no existe
+    pub fn find(&self, name: &String) -> Result<Arc<Group>, String> {
+        self.get(name)
+            .ok_or_else(|| format!("Group '{}' does not exist", name))
+    }
+
+    /// Los grupos se crean a proposito, asi un error de tipeo en un `Join` no crea uno nuevo
+    pub fn create(
+        &self,
+        name: Arc<String>,
+        access: GroupAccess,
+        owner: Option<String>,
+    ) -> Result<Arc<Group>, String> {
+        let group = self.insert(name.clone(), access.clone(), owner)?;
+        self.3.group_created(name, access);
+        Ok(group)
+    }
+
+    /// Un grupo que se creo en otro server, aca no tiene owner
+    pub fn create_relayed(
+        &self,
+        name: Arc<String>,
+        access: GroupAccess,
+    ) -> Result<Arc<Group>, String> {
+        self.insert(name, access, None)
+    }
+
+    fn insert(
+        &self,
+        name: Arc<String>,
+        access: GroupAccess,
+        owner: Option<String>,
+    ) -> Result<Arc<Group>, String> {
+        match self.0.lock().unwrap().entry(name.clone()) {
+            Entry::Occupied(_) => Err(format!("Group '{}' already exists", name)),
+            Entry::Vacant(entry) => {
+                let federation = self.3.clone();
+                let group = Group::new(name, self.1, access, owner, self.2.clone(), federation);
+                Ok(entry.insert(Arc::new(group)).clone())
+            }
+        }
+    }
+
+    /// Todos los grupos que hay en este momento
+    pub fn list(&self) -> Vec<Arc<Group>> {
+        self.0.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Saca el grupo de la tabla, para avisarle a los miembros hay que llamar a `Group::close`
+    pub fn remove(&self, name: &String) -> Option<Arc<Group>> {
+        self.0.lock().unwrap().remove(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
     }
 }
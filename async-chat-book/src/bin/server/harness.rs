@@ -0,0 +1,275 @@
+//! Un server de verdad corriendo adentro del test, para probar `serve` de punta a punta sin
+//! levantar el binario
+//!
+//! Cada cliente sigue un `Script`: manda packets, chequea en orden lo que le llega y se puede
+//! sincronizar con los demas con `barrier`. Al final de cada script chequeamos que no le haya
+//! llegado nada que no esperaba
+use crate::attachments::AttachmentStore;
+use crate::config::Config;
+use crate::connection_table::ConnectionTable;
+use crate::federation::Federation;
+use crate::group_table::GroupTable;
+use crate::metrics::Metrics;
+use crate::plugin::{self, Plugins};
+use crate::rate_limit::RateLimit;
+use crate::{accept_loop, log_error, ChatServer, Transport};
+use async_chat_book::codec::{Codec, PacketStream};
+use async_chat_book::utils::DEFAULT_MAX_PACKET_SIZE;
+use async_chat_book::{FromClient, FromServer, Versioned, LEGACY_PROTOCOL_VERSION};
+use async_std::channel::{self, Sender};
+use async_std::future;
+use async_std::io::BufReader;
+use async_std::net::{Shutdown, TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::sync::Barrier;
+use async_std::task;
+use futures::future::join_all;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Un grupo que nadie crea: postear ahi siempre da error, y cuando llega ese error sabemos que el
+/// server ya proceso todo lo que el cliente mando antes
+const PROBE_GROUP: &str = "*probe*";
+/// Lo maximo que esperamos un packet antes de dar el test por perdido
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct TestServer {
+    pub chat: Arc<ChatServer>,
+    pub address: SocketAddr,
+    /// cuando se dropea deja de aceptar conexiones
+    stop: Sender<()>,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Como `start` pero dejando cambiar la configuracion antes de arrancar
+    pub async fn start_with(configure: impl FnOnce(&mut Config)) -> Self {
+        let mut config = Self::config();
+        configure(&mut config);
+        let plugins = plugin::load(&config.plugins).unwrap();
+        Self::launch(config, plugins).await
+    }
+
+    /// Para probar plugins que no vienen con el server
+    pub async fn start_with_plugins(plugins: Plugins) -> Self {
+        Self::launch(Self::config(), plugins).await
+    }
+
+    fn config() -> Config {
+        let mut config = Config::from_args(std::iter::once("127.0.0.1:0".to_string())).unwrap();
+        // los scripts mandan todo de golpe, no queremos que los limites se metan en el medio
+        config.client_limit = RateLimit::new(1000.0, 1000.0);
+        config.group_limit = RateLimit::new(1000.0, 1000.0);
+        config
+    }
+
+    async fn launch(config: Config, plugins: Plugins) -> Self {
+        let listener = TcpListener::bind(&config.address).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let federation = Arc::new(Federation::new());
+        let chat = Arc::new(ChatServer {
+            groups: GroupTable::new(config.group_limit, plugins, federation.clone()),
+            connections: ConnectionTable::new(),
+            attachments: AttachmentStore::new(
+                config.max_attachment_size,
+                config.attachment_storage,
+            ),
+            federation,
+            metrics: Arc::new(Metrics::default()),
+            acceptor: None,
+            config,
+        });
+        let (stop, stopped) = channel::bounded(1);
+        let accepting = accept_loop(listener, Transport::Tcp, chat.clone(), stopped);
+        task::spawn(async move { log_error(accepting.await) });
+        Self {
+            chat,
+            address,
+            stop,
+        }
+    }
+
+    /// Corre todos los scripts a la vez, cada uno con su propia conexion. Si algun cliente no
+    /// recibe lo que esperaba el test falla diciendo cual fue
+    pub async fn run(&self, scripts: Vec<Script>) {
+        let barrier = Barrier::new(scripts.len());
+        let runs = scripts
+            .into_iter()
+            .enumerate()
+            .map(|(client, script)| script.run(client, self, &barrier));
+        join_all(runs).await;
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.stop.close();
+    }
+}
+
+enum Step {
+    Send(FromClient),
+    Expect(FromServer),
+    Barrier,
+    Disconnect,
+}
+
+/// Lo que hace un cliente, en orden
+pub struct Script {
+    codec: Codec,
+    protocol_version: u32,
+    steps: Vec<Step>,
+}
+
+impl Script {
+    /// Un cliente viejo, que manda y recibe los packets sueltos
+    pub fn new(codec: Codec) -> Self {
+        Self {
+            codec,
+            protocol_version: LEGACY_PROTOCOL_VERSION,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Para que el cliente hable otra version del protocolo, los ids no los chequeamos
+    pub fn protocol_version(mut self, protocol_version: u32) -> Self {
+        self.protocol_version = protocol_version;
+        self
+    }
+
+    pub fn send(mut self, packet: FromClient) -> Self {
+        self.steps.push(Step::Send(packet));
+        self
+    }
+
+    /// El siguiente packet que llega tiene que ser este
+    pub fn expect(mut self, packet: FromServer) -> Self {
+        self.steps.push(Step::Expect(packet));
+        self
+    }
+
+    /// Espera a que el server haya procesado todo lo que mandamos y a que todos los demas
+    /// clientes lleguen a su `barrier`. Todos los scripts tienen que tener la misma cantidad
+    pub fn barrier(mut self) -> Self {
+        self.steps.push(Step::Barrier);
+        self
+    }
+
+    /// Cierra la conexion y espera a que el server se entere
+    pub fn disconnect(mut self) -> Self {
+        self.steps.push(Step::Disconnect);
+        self
+    }
+
+    async fn run(self, client: usize, server: &TestServer, barrier: &Barrier) {
+        let codec = self.codec;
+        let mut socket = TcpStream::connect(server.address).await.unwrap();
+        codec.announce(&mut socket).await.unwrap();
+        let replies = codec.receive(BufReader::new(socket.clone()), DEFAULT_MAX_PACKET_SIZE);
+        let mut connection = Some(Connection {
+            client,
+            codec,
+            protocol_version: self.protocol_version,
+            socket,
+            replies,
+        });
+
+        for (number, step) in self.steps.into_iter().enumerate() {
+            match (step, &mut connection) {
+                (Step::Send(packet), Some(connection)) => connection.send(&packet).await,
+                (Step::Expect(expected), Some(connection)) => {
+                    let reply = connection.next().await;
+                    assert_eq!(
+                        reply,
+                        Some(expected),
+                        "client {} got an unexpected packet at step {}",
+                        client,
+                        number
+                    );
+                }
+                (Step::Barrier, connection) => {
+                    // un cliente desconectado igual tiene que esperar a los demas
+                    if let Some(connection) = connection {
+                        connection.probe().await;
+                    }
+                    barrier.wait().await;
+                }
+                (Step::Disconnect, slot @ Some(_)) => {
+                    let peer = slot.take().unwrap().close();
+                    let closed = async {
+                        while server.chat.connections.find(&peer.to_string()).is_some() {
+                            task::sleep(Duration::from_millis(10)).await;
+                        }
+                    };
+                    future::timeout(TIMEOUT, closed)
+                        .await
+                        .unwrap_or_else(|_| panic!("the server never noticed client {}", client));
+                }
+                (_, None) => panic!("client {} is disconnected at step {}", client, number),
+            }
+        }
+        // no le tiene que haber llegado nada que el script no esperaba
+        if let Some(mut connection) = connection {
+            connection.probe().await;
+        }
+    }
+}
+
+struct Connection {
+    client: usize,
+    codec: Codec,
+    protocol_version: u32,
+    socket: TcpStream,
+    replies: PacketStream<'static, Versioned<FromServer>>,
+}
+
+impl Connection {
+    async fn send(&mut self, packet: &FromClient) {
+        let packet = Versioned::new(packet, self.protocol_version, None);
+        self.codec.send(&mut self.socket, &packet).await.unwrap();
+    }
+
+    /// El siguiente packet del server o `None` si cerro la conexion
+    async fn next(&mut self) -> Option<FromServer> {
+        match future::timeout(TIMEOUT, self.replies.next()).await {
+            Ok(reply) => reply.map(|reply| {
+                let reply = reply.unwrap();
+                assert_eq!(
+                    reply.protocol_version(),
+                    self.protocol_version,
+                    "client {} got a packet in another version",
+                    self.client
+                );
+                reply.into_packet()
+            }),
+            Err(_) => panic!("client {} waited too long for a packet", self.client),
+        }
+    }
+
+    /// Postea en `PROBE_GROUP` y espera el error, que tiene que ser lo proximo que llegue
+    async fn probe(&mut self) {
+        let probe = FromClient::Post {
+            group_name: Arc::new(PROBE_GROUP.to_string()),
+            message: Arc::new(String::new()),
+        };
+        self.send(&probe).await;
+        let expected = FromServer::Error(format!("Group '{}' does not exist", PROBE_GROUP));
+        assert_eq!(
+            self.next().await,
+            Some(expected),
+            "client {} got a packet its script did not expect",
+            self.client
+        );
+    }
+
+    /// Devuelve la direccion desde la que nos veia el server
+    fn close(self) -> SocketAddr {
+        let peer = self.socket.local_addr().unwrap();
+        let _ = self.socket.shutdown(Shutdown::Both);
+        peer
+    }
+}
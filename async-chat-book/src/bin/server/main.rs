@@ -1,34 +1,207 @@
 use async_chat_book::utils::ChatResult;
+use async_std::channel::Receiver;
+use async_std::net::{TcpListener, TcpStream};
 use async_std::prelude::*;
+use async_std::task;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
+mod admin;
+mod attachments;
+mod config;
 mod connection;
+mod connection_table;
+mod federation;
 mod group;
 mod group_table;
+// un server adentro del mismo proceso para los tests de `serve`
+#[cfg(test)]
+mod harness;
+mod metrics;
+mod plugin;
+mod rate_limit;
+mod shutdown;
+mod websocket;
 
+use attachments::AttachmentStore;
+use config::Config;
 use connection::serve;
+use connection_table::ConnectionTable;
+use federation::Federation;
+use futures_rustls::TlsAcceptor;
+use group_table::GroupTable;
+use metrics::{Counted, Metrics};
+
+/// Todo lo que comparten las conexiones del server
+pub struct ChatServer {
+    pub config: Config,
+    pub groups: GroupTable,
+    pub connections: ConnectionTable,
+    pub attachments: AttachmentStore,
+    /// los otros servers con los que compartimos los grupos, sin `--peer` no hay ninguno
+    pub federation: Arc<Federation>,
+    pub metrics: Arc<Metrics>,
+    /// `None` si el server no usa TLS
+    pub acceptor: Option<TlsAcceptor>,
+}
+
+/// Como hablan los clientes que llegan por cada listener
+#[derive(Debug, Clone, Copy)]
+enum Transport {
+    /// El protocolo de siempre, sobre TCP pelado (o TLS)
+    Tcp,
+    /// JSON adentro de mensajes de texto de WebSocket, para los browsers
+    WebSocket,
+}
+
+/// Lo que puede despertar al loop que acepta conexiones
+enum Event {
+    Connection(Option<std::io::Result<TcpStream>>),
+    Shutdown,
+}
 
 fn main() -> ChatResult<()> {
-    let address = std::env::args().nth(1).expect("Usage: server ADDRESS");
-    let chat_group_table = Arc::new(group_table::GroupTable::new());
+    let config = Config::from_args(std::env::args().skip(1))?;
+    let federation = Arc::new(Federation::new());
+    let chat = Arc::new(ChatServer {
+        groups: GroupTable::new(
+            config.group_limit,
+            plugin::load(&config.plugins)?,
+            federation.clone(),
+        ),
+        connections: ConnectionTable::new(),
+        attachments: AttachmentStore::new(config.max_attachment_size, config.attachment_storage),
+        federation,
+        metrics: Arc::new(Metrics::default()),
+        acceptor: config.tls_acceptor()?,
+        config,
+    });
+    let shutdown_signal = shutdown::listen_for_signals()?;
 
-    async_std::task::block_on(async {
+    task::block_on(async {
         // este codigo es el mismo que vimos en la introduccion del capitulo
-        use async_std::{net, task};
-        let listener = net::TcpListener::bind(address).await?;
-
-        let mut new_connections = listener.incoming();
-        while let Some(socket_result) = new_connections.next().await {
-            let socket = socket_result?;
-            let groups = chat_group_table.clone();
-            task::spawn(async {
-                log_error(serve(socket, groups).await);
+        let listener = TcpListener::bind(&chat.config.address).await?;
+        let websockets = match &chat.config.ws_address {
+            Some(address) => {
+                let listener = TcpListener::bind(address).await?;
+                let accepting = accept_loop(
+                    listener,
+                    Transport::WebSocket,
+                    chat.clone(),
+                    shutdown_signal.clone(),
+                );
+                Some(task::spawn(accepting))
+            }
+            None => None,
+        };
+        if let Some(address) = &chat.config.metrics_address {
+            let listener = TcpListener::bind(address).await?;
+            let chat = chat.clone();
+            task::spawn(async move {
+                log_error(metrics::serve_metrics(listener, chat).await);
             });
         }
+
+        if let Some(address) = &chat.config.peer_address {
+            let listener = TcpListener::bind(address).await?;
+            let chat = chat.clone();
+            task::spawn(async move {
+                log_error(federation::accept_peers(listener, chat).await);
+            });
+        }
+        for address in &chat.config.peers {
+            task::spawn(federation::dial(address.clone(), chat.clone()));
+        }
+
+        accept_loop(listener, Transport::Tcp, chat.clone(), shutdown_signal).await?;
+        if let Some(websockets) = websockets {
+            websockets.await?;
+        }
+
+        // ya no aceptamos conexiones nuevas, despedimos a las que quedan
+        let summary = shutdown::drain(
+            &chat.groups,
+            &chat.connections,
+            chat.config.shutdown_timeout,
+        )
+        .await;
+        println!("{}", summary);
         Ok(())
     })
 }
 
+/// Acepta conexiones hasta que llega la senial de apagarse, cada una se atiende en su propia tarea
+async fn accept_loop(
+    listener: TcpListener,
+    transport: Transport,
+    chat: Arc<ChatServer>,
+    shutdown_signal: Receiver<()>,
+) -> ChatResult<()> {
+    let mut new_connections = listener.incoming();
+    loop {
+        let next_connection = async { Event::Connection(new_connections.next().await) };
+        let shutdown = async {
+            let _ = shutdown_signal.recv().await;
+            Event::Shutdown
+        };
+        let socket = match next_connection.race(shutdown).await {
+            Event::Connection(Some(socket_result)) => socket_result?,
+            Event::Connection(None) | Event::Shutdown => return Ok(()),
+        };
+        let chat = chat.clone();
+        task::spawn(async move {
+            log_error(handle_connection(socket, transport, chat).await);
+        });
+    }
+}
+
+/// Si el server usa TLS primero hacemos el handshake y despues atendemos al cliente igual que
+/// siempre
+async fn handle_connection(
+    socket: TcpStream,
+    transport: Transport,
+    chat: Arc<ChatServer>,
+) -> ChatResult<()> {
+    socket.set_nodelay(true)?;
+    let peer = socket.peer_addr()?;
+    metrics::increment(&chat.metrics.connections);
+    match &chat.acceptor {
+        Some(acceptor) => {
+            let socket = acceptor.accept(socket).await?;
+            serve_socket(socket, peer, transport, chat).await
+        }
+        None => serve_socket(socket, peer, transport, chat).await,
+    }
+}
+
+async fn serve_socket<S>(
+    socket: S,
+    peer: SocketAddr,
+    transport: Transport,
+    chat: Arc<ChatServer>,
+) -> ChatResult<()>
+where
+    S: async_std::io::Read + async_std::io::Write + Unpin + Send + 'static,
+{
+    use futures::io::AsyncReadExt;
+
+    match transport {
+        Transport::Tcp => {
+            // como un stream TLS no se puede clonar lo partimos en una mitad para leer y otra
+            // para escribir
+            let (reader, writer) = socket.split();
+            let reader = Counted::new(reader, chat.metrics.clone());
+            let writer = Counted::new(writer, chat.metrics.clone());
+            serve(reader, writer, peer, chat).await
+        }
+        Transport::WebSocket => {
+            let socket = Counted::new(socket, chat.metrics.clone());
+            let (reader, writer) = websocket::accept(socket, chat.config.max_packet_size).await?;
+            serve(reader, writer, peer, chat).await
+        }
+    }
+}
+
 fn log_error(result: ChatResult<()>) {
     if let Err(err) = result {
         eprintln!("Error: {}", err)
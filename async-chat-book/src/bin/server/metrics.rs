@@ -0,0 +1,215 @@
+//! Contadores de lo que pasa en el server, expuestos en formato de texto de Prometheus por HTTP
+//! en un puerto aparte
+use crate::ChatServer;
+use async_chat_book::utils::ChatResult;
+use async_std::io::{self, Read, Write};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use std::fmt::Write as _;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+#[derive(Default)]
+pub struct Metrics {
+    pub connections: AtomicU64,
+    pub joins: AtomicU64,
+    pub posts: AtomicU64,
+    pub errors: AtomicU64,
+    pub bytes_received: AtomicU64,
+    pub bytes_sent: AtomicU64,
+}
+
+/// Suma uno al contador
+pub fn increment(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+impl Metrics {
+    /// El texto que espera Prometheus, `active_connections`, `groups` y `attachment_bytes` son
+    /// gauges que sacamos de las tablas del server
+    pub fn render(
+        &self,
+        active_connections: usize,
+        groups: usize,
+        attachment_bytes: u64,
+    ) -> String {
+        let counters = [
+            (
+                "chat_connections_total",
+                "Connections accepted",
+                &self.connections,
+            ),
+            ("chat_joins_total", "Join requests handled", &self.joins),
+            (
+                "chat_posts_total",
+                "Messages posted to a group",
+                &self.posts,
+            ),
+            (
+                "chat_errors_total",
+                "Errors reported to clients",
+                &self.errors,
+            ),
+            (
+                "chat_received_bytes_total",
+                "Bytes read from clients",
+                &self.bytes_received,
+            ),
+            (
+                "chat_sent_bytes_total",
+                "Bytes written to clients",
+                &self.bytes_sent,
+            ),
+        ];
+        let gauges = [
+            (
+                "chat_active_connections",
+                "Connections currently open",
+                active_connections as u64,
+            ),
+            ("chat_groups", "Groups currently open", groups as u64),
+            (
+                "chat_attachment_bytes",
+                "Bytes used by stored attachments",
+                attachment_bytes,
+            ),
+        ];
+
+        let mut text = String::new();
+        for (name, help, counter) in counters {
+            let value = counter.load(Ordering::Relaxed);
+            let _ = write!(
+                text,
+                "# HELP {name} {help}.\n# TYPE {name} counter\n{name} {value}\n"
+            );
+        }
+        for (name, help, value) in gauges {
+            let _ = write!(
+                text,
+                "# HELP {name} {help}.\n# TYPE {name} gauge\n{name} {value}\n"
+            );
+        }
+        text
+    }
+}
+
+/// Atiende el endpoint `/metrics` hasta que termina el server
+pub async fn serve_metrics(listener: TcpListener, chat: Arc<ChatServer>) -> ChatResult<()> {
+    let mut incoming = listener.incoming();
+    while let Some(socket) = incoming.next().await {
+        let socket = socket?;
+        let chat = chat.clone();
+        task::spawn(async move {
+            if let Err(err) = answer_scrape(socket, &chat).await {
+                eprintln!("Error: metrics: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Un server de HTTP minimo: leemos el request hasta la linea vacia y contestamos
+async fn answer_scrape(mut socket: TcpStream, chat: &ChatServer) -> ChatResult<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.ends_with(b"\r\n\r\n") && request.len() < 8 * 1024 {
+        let read = socket.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let response = if request.starts_with(b"GET /metrics ") {
+        let body = chat.metrics.render(
+            chat.connections.len(),
+            chat.groups.len(),
+            chat.attachments.used(),
+        );
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    socket.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Envuelve la mitad de la conexion y va sumando los bytes que pasan por ella
+pub struct Counted<T> {
+    inner: T,
+    metrics: Arc<Metrics>,
+}
+
+impl<T> Counted<T> {
+    pub fn new(inner: T, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+impl<T: Read + Unpin> Read for Counted<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(read)) = result {
+            self.metrics
+                .bytes_received
+                .fetch_add(read as u64, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl<T: Write + Unpin> Write for Counted<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            self.metrics
+                .bytes_sent
+                .fetch_add(written as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        increment(&metrics.posts);
+        increment(&metrics.posts);
+        let text = metrics.render(3, 1, 1024);
+        assert!(text.contains("# TYPE chat_posts_total counter\nchat_posts_total 2\n"));
+        assert!(text.contains("# TYPE chat_active_connections gauge\nchat_active_connections 3\n"));
+        assert!(text.contains("\nchat_groups 1\n"));
+        assert!(text.contains("\nchat_attachment_bytes 1024\n"));
+    }
+}
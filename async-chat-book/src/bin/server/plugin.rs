@@ -0,0 +1,264 @@
+//! Comportamiento automatico para los grupos (bots, filtros, alertas...) sin tocar `serve`
+//!
+//! Cada plugin implementa `ChatPlugin` y se registra al arrancar con `--plugin NAME`. Los grupos
+//! llaman a los hooks en el orden en el que se registraron: cuando alguien entra, cuando sale
+//! (o se desconecta) y antes de repartir cada mensaje. Un hook puede cambiar el mensaje,
+//! rechazarlo o agregar respuestas que se reparten al grupo como cualquier otro mensaje
+use std::sync::Arc;
+
+mod dice;
+mod greeter;
+
+pub use dice::Dice;
+pub use greeter::Greeter;
+
+/// Los plugins que usa cada grupo, compartidos por todos
+pub type Plugins = Arc<[Box<dyn ChatPlugin>]>;
+
+/// Todos los hooks tienen una implementacion que no hace nada, cada plugin sobreescribe los que
+/// le interesan. Se llaman sin ningun lock tomado pero desde la tarea del cliente, asi que
+/// tienen que ser rapidos
+pub trait ChatPlugin: Send + Sync {
+    /// El nombre con el que se registra en la linea de comandos
+    fn name(&self) -> &str;
+
+    fn on_join(&self, _event: &mut Membership) {}
+
+    fn on_leave(&self, _event: &mut Membership) {}
+
+    fn on_post(&self, _post: &mut Post) {}
+}
+
+/// Los plugins que vienen con el server, por nombre
+pub fn bundled(name: &str) -> Option<Box<dyn ChatPlugin>> {
+    match name {
+        "dice" => Some(Box::new(Dice::new())),
+        "greeter" => Some(Box::new(Greeter)),
+        _ => None,
+    }
+}
+
+/// Arma los plugins de `--plugin` en el orden en el que se pasaron
+pub fn load(names: &[String]) -> Result<Plugins, String> {
+    names
+        .iter()
+        .map(|name| {
+            bundled(name).ok_or_else(|| {
+                format!(
+                    "unknown plugin '{}', the bundled ones are: dice, greeter",
+                    name
+                )
+            })
+        })
+        .collect()
+}
+
+/// Alguien entro o salio de un grupo
+pub struct Membership<'a> {
+    pub group_name: &'a str,
+    /// `None` si el cliente todavia no eligio un nombre
+    pub member: Option<&'a str>,
+    replies: Vec<String>,
+}
+
+impl<'a> Membership<'a> {
+    pub fn new(group_name: &'a str, member: Option<&'a str>) -> Self {
+        Self {
+            group_name,
+            member,
+            replies: Vec::new(),
+        }
+    }
+
+    /// Un mensaje que se reparte a todo el grupo
+    pub fn reply(&mut self, message: impl Into<String>) {
+        self.replies.push(message.into());
+    }
+
+    pub fn into_replies(self) -> Vec<String> {
+        self.replies
+    }
+}
+
+/// Un mensaje que todavia no se repartio
+pub struct Post<'a> {
+    pub group_name: &'a str,
+    pub author: Option<&'a str>,
+    /// lo que se va a repartir, los plugins lo pueden cambiar
+    pub message: String,
+    rejected: Option<String>,
+    replies: Vec<String>,
+}
+
+impl<'a> Post<'a> {
+    pub fn new(group_name: &'a str, author: Option<&'a str>, message: String) -> Self {
+        Self {
+            group_name,
+            author,
+            message,
+            rejected: None,
+            replies: Vec::new(),
+        }
+    }
+
+    /// El mensaje no se reparte y al que lo mando le llega un error con el motivo. Los plugins
+    /// que siguen ya no se llaman
+    pub fn reject(&mut self, reason: impl Into<String>) {
+        self.rejected = Some(reason.into());
+    }
+
+    pub fn rejected(&self) -> Option<&str> {
+        self.rejected.as_deref()
+    }
+
+    /// Un mensaje que se reparte a todo el grupo despues del que se posteo. Las respuestas no
+    /// pasan por los plugins, asi dos bots no se pueden contestar para siempre
+    pub fn reply(&mut self, message: impl Into<String>) {
+        self.replies.push(message.into());
+    }
+
+    /// El mensaje (ya transformado) y las respuestas
+    pub fn into_parts(self) -> (String, Vec<String>) {
+        (self.message, self.replies)
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::{Script, TestServer};
+    use async_chat_book::codec::Codec;
+    use async_chat_book::{FromClient, FromServer, GroupAccess};
+    use async_std::task;
+
+    /// Pasa todo a mayusculas
+    struct Shout;
+
+    impl ChatPlugin for Shout {
+        fn name(&self) -> &str {
+            "shout"
+        }
+
+        fn on_post(&self, post: &mut Post) {
+            post.message = post.message.to_uppercase();
+        }
+    }
+
+    /// No deja pasar spoilers, y avisa cuando los rechaza
+    struct NoSpoilers;
+
+    impl ChatPlugin for NoSpoilers {
+        fn name(&self) -> &str {
+            "no-spoilers"
+        }
+
+        fn on_post(&self, post: &mut Post) {
+            if post.message.to_lowercase().contains("spoiler") {
+                post.reject("no spoilers please");
+            }
+        }
+    }
+
+    fn set_name(name: &str) -> FromClient {
+        FromClient::SetName {
+            name: name.to_string(),
+            password: None,
+        }
+    }
+
+    fn create(group_name: &str) -> FromClient {
+        FromClient::CreateGroup {
+            group_name: Arc::new(group_name.to_string()),
+            access: GroupAccess::Open,
+        }
+    }
+
+    fn join(group_name: &str) -> FromClient {
+        FromClient::Join {
+            group_name: Arc::new(group_name.to_string()),
+            password: None,
+        }
+    }
+
+    fn post(group_name: &str, message: &str) -> FromClient {
+        FromClient::Post {
+            group_name: Arc::new(group_name.to_string()),
+            message: Arc::new(message.to_string()),
+        }
+    }
+
+    fn message(group_name: &str, message: &str, seq: u64) -> FromServer {
+        FromServer::Message {
+            group_name: Arc::new(group_name.to_string()),
+            message: Arc::new(message.to_string()),
+            seq,
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let names = ["greeter".to_string(), "dice".to_string()];
+        let plugins = load(&names).unwrap();
+        let loaded: Vec<&str> = plugins.iter().map(|plugin| plugin.name()).collect();
+        assert_eq!(loaded, ["greeter", "dice"]);
+        assert_eq!(
+            load(&["echo".to_string()]).err(),
+            Some("unknown plugin 'echo', the bundled ones are: dice, greeter".to_string())
+        );
+    }
+
+    #[test]
+    fn test_transform_and_reject() {
+        task::block_on(async {
+            let plugins: Vec<Box<dyn ChatPlugin>> = vec![Box::new(NoSpoilers), Box::new(Shout)];
+            let server = TestServer::start_with_plugins(plugins.into()).await;
+            let alice = Script::new(Codec::JsonLines)
+                .send(create("Movies"))
+                .barrier()
+                .barrier()
+                .send(post("Movies", "the ending is a spoiler"))
+                .expect(FromServer::Error(
+                    "Message to 'Movies' was dropped by no-spoilers: no spoilers please"
+                        .to_string(),
+                ))
+                .send(post("Movies", "no comments"))
+                .expect(message("Movies", "NO COMMENTS", 1))
+                .barrier();
+            // a los demas miembros solo les llega el mensaje transformado
+            let bob = Script::new(Codec::LengthPrefixed)
+                .barrier()
+                .send(join("Movies"))
+                .barrier()
+                .expect(message("Movies", "NO COMMENTS", 1))
+                .barrier();
+            server.run(vec![alice, bob]).await;
+        });
+    }
+
+    #[test]
+    fn test_greeter() {
+        task::block_on(async {
+            let server =
+                TestServer::start_with(|config| config.plugins = vec!["greeter".to_string()]).await;
+            let alice = Script::new(Codec::JsonLines)
+                .send(set_name("alice"))
+                .send(create("Dogs"))
+                .expect(message("Dogs", "alice joined Dogs", 1))
+                .barrier()
+                .expect(message("Dogs", "bob joined Dogs", 2))
+                .barrier()
+                .expect(message("Dogs", "bob left Dogs", 3));
+            let bob = Script::new(Codec::LengthPrefixed)
+                .send(set_name("bob"))
+                .barrier()
+                .send(join("Dogs"))
+                .expect(message("Dogs", "bob joined Dogs", 2))
+                .barrier()
+                .disconnect();
+            server.run(vec![alice, bob]).await;
+        });
+    }
+}
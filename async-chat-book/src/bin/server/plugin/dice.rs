@@ -0,0 +1,181 @@
+//! Tira dados cuando alguien postea `/roll NdM` (o solo `/roll`, que es un 1d6). Cualquier otra
+//! cosa, como `/rolling` o `/roll 2d6 for initiative`, es un mensaje comun
+use super::{ChatPlugin, Post};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_DICE: u32 = 100;
+const MAX_FACES: u32 = 1000;
+
+pub struct Dice {
+    /// el estado de un xorshift, no necesitamos nada mejor para tirar dados
+    state: Mutex<u64>,
+}
+
+impl Dice {
+    pub fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::with_seed(nanos)
+    }
+
+    /// Con la misma semilla salen siempre los mismos numeros, para los tests
+    pub fn with_seed(seed: u64) -> Self {
+        // NOTE(elsuizo:2021-11-14): el xorshift se queda en cero para siempre si arranca en cero
+        Self {
+            state: Mutex::new(seed | 1),
+        }
+    }
+
+    fn roll(&self, faces: u32) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        (*state % faces as u64) as u32 + 1
+    }
+}
+
+/// El `NdM` si el mensaje es el comando: `/roll` solo o seguido de una sola palabra
+fn command(message: &str) -> Option<&str> {
+    let mut words = message.split_whitespace();
+    if words.next() != Some("/roll") {
+        return None;
+    }
+    match (words.next(), words.next()) {
+        (None, _) => Some(""),
+        (Some(spec), None) => Some(spec),
+        (Some(_), Some(_)) => None,
+    }
+}
+
+/// Lee `NdM`, cuantos dados y de cuantas caras
+fn parse(spec: &str) -> Option<(u32, u32)> {
+    if spec.is_empty() {
+        return Some((1, 6));
+    }
+    let (dice, faces) = spec.split_once('d')?;
+    let dice = if dice.is_empty() {
+        1
+    } else {
+        dice.parse().ok()?
+    };
+    let faces = faces.parse().ok()?;
+    if (1..=MAX_DICE).contains(&dice) && (2..=MAX_FACES).contains(&faces) {
+        Some((dice, faces))
+    } else {
+        None
+    }
+}
+
+impl ChatPlugin for Dice {
+    fn name(&self) -> &str {
+        "dice"
+    }
+
+    fn on_post(&self, post: &mut Post) {
+        let spec = match command(&post.message) {
+            Some(spec) => spec,
+            None => return,
+        };
+        let (dice, faces) = match parse(spec) {
+            Some(roll) => roll,
+            None => {
+                let usage = format!(
+                    "usage: /roll NdM, with up to {} dice of 2 to {} faces",
+                    MAX_DICE, MAX_FACES
+                );
+                post.reject(usage);
+                return;
+            }
+        };
+        let rolls: Vec<u32> = (0..dice).map(|_| self.roll(faces)).collect();
+        let total: u32 = rolls.iter().sum();
+        let rolls: Vec<String> = rolls.iter().map(u32::to_string).collect();
+        let reply = format!(
+            "{} rolled {}d{}: {} = {}",
+            post.author.unwrap_or("someone"),
+            dice,
+            faces,
+            rolls.join(" + "),
+            total
+        );
+        post.reply(reply);
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(""), Some((1, 6)));
+        assert_eq!(parse("2d6"), Some((2, 6)));
+        assert_eq!(parse("d20"), Some((1, 20)));
+        assert_eq!(parse("0d6"), None);
+        assert_eq!(parse("2d1"), None);
+        assert_eq!(parse("101d6"), None);
+        assert_eq!(parse("2d"), None);
+        assert_eq!(parse("two dice"), None);
+    }
+
+    #[test]
+    fn test_command() {
+        assert_eq!(command("/roll"), Some(""));
+        assert_eq!(command("  /roll 2d6 "), Some("2d6"));
+        assert_eq!(command("/roll lots"), Some("lots"));
+        assert_eq!(command("/rolling"), None);
+        assert_eq!(command("/roll 2d6 for initiative"), None);
+        assert_eq!(command("let's /roll"), None);
+    }
+
+    #[test]
+    fn test_rolls_are_in_range() {
+        let dice = Dice::with_seed(0);
+        for faces in [2, 6, 20, MAX_FACES] {
+            assert!((0..1000)
+                .map(|_| dice.roll(faces))
+                .all(|roll| (1..=faces).contains(&roll)));
+        }
+    }
+
+    #[test]
+    fn test_on_post() {
+        let dice = Dice::with_seed(42);
+        let mut post = Post::new("Games", Some("alice"), "/roll 3d6".to_string());
+        dice.on_post(&mut post);
+        assert_eq!(post.rejected(), None);
+        let (message, replies) = post.into_parts();
+        // el mensaje original se reparte igual
+        assert_eq!(message, "/roll 3d6");
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].starts_with("alice rolled 3d6: "));
+
+        // con la misma semilla sale lo mismo
+        let again = Dice::with_seed(42);
+        let mut post = Post::new("Games", Some("alice"), "/roll 3d6".to_string());
+        again.on_post(&mut post);
+        assert_eq!(post.into_parts().1, replies);
+
+        let mut post = Post::new("Games", None, "/roll lots".to_string());
+        dice.on_post(&mut post);
+        assert_eq!(
+            post.rejected(),
+            Some("usage: /roll NdM, with up to 100 dice of 2 to 1000 faces")
+        );
+
+        // no son el comando, se reparten tal cual
+        for chat in ["just chatting", "/rolling", "/roll 2d6 for initiative"] {
+            let mut post = Post::new("Games", None, chat.to_string());
+            dice.on_post(&mut post);
+            assert_eq!(post.rejected(), None);
+            assert_eq!(post.into_parts(), (chat.to_string(), Vec::new()));
+        }
+    }
+}
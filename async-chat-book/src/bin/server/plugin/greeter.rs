@@ -0,0 +1,26 @@
+//! Avisa en el grupo cuando alguien entra o sale
+use super::{ChatPlugin, Membership};
+
+pub struct Greeter;
+
+impl Greeter {
+    fn announce(event: &mut Membership, what: &str) {
+        let member = event.member.unwrap_or("someone");
+        let notice = format!("{} {} {}", member, what, event.group_name);
+        event.reply(notice);
+    }
+}
+
+impl ChatPlugin for Greeter {
+    fn name(&self) -> &str {
+        "greeter"
+    }
+
+    fn on_join(&self, event: &mut Membership) {
+        Self::announce(event, "joined");
+    }
+
+    fn on_leave(&self, event: &mut Membership) {
+        Self::announce(event, "left");
+    }
+}
@@ -0,0 +1,182 @@
+//! Limites de cuantos mensajes por segundo aceptamos, con un token bucket: cada packet se lleva
+//! un token y los tokens se van reponiendo de a poco hasta un maximo (el `burst`)
+use async_chat_book::utils::ChatResult;
+use std::time::{Duration, Instant};
+
+/// Lo que le contestamos al cliente cuando se pasa del limite
+pub const RATE_LIMITED: &str = "rate limited";
+
+/// Cada cuanto le perdonamos una violacion a una conexion que no se pasa del limite
+pub const VIOLATION_DECAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: f64,
+}
+
+impl RateLimit {
+    pub fn new(per_second: f64, burst: f64) -> Self {
+        Self { per_second, burst }
+    }
+
+    /// Parsea un limite escrito como `PER_SECOND:BURST`, por ejemplo `10:20`
+    pub fn parse(text: &str) -> ChatResult<Self> {
+        let (per_second, burst) = text
+            .split_once(':')
+            .ok_or_else(|| format!("expected PER_SECOND:BURST, found {:?}", text))?;
+        let limit = Self::new(per_second.parse()?, burst.parse()?);
+        // `parse` acepta "NaN" e "inf", y los NaN pasan cualquier comparacion de abajo
+        let finite = limit.per_second.is_finite() && limit.burst.is_finite();
+        if !finite || limit.per_second <= 0.0 || limit.burst < 1.0 {
+            return Err(format!("invalid rate limit {:?}", text).into());
+        }
+        Ok(limit)
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Arranca lleno, asi se puede mandar un `burst` de entrada
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    /// Lo mismo que `try_take` pero diciendole que hora es, para los tests
+    pub fn try_take_at(&mut self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Cuantas veces se paso una conexion del limite
+///
+/// No vuelve a cero con un packet que entra bien: si no, un cliente que se pasa justo por arriba
+/// del limite (uno rechazado, uno aceptado, ...) nunca llegaria al maximo. Las violaciones se van
+/// olvidando de a una cada `VIOLATION_DECAY`
+#[derive(Debug)]
+pub struct Violations {
+    count: f64,
+    last_decay: Instant,
+}
+
+impl Violations {
+    pub fn new() -> Self {
+        Self {
+            count: 0.0,
+            last_decay: Instant::now(),
+        }
+    }
+
+    /// Anota una violacion y devuelve cuantas quedan contando esta
+    pub fn record(&mut self) -> u32 {
+        self.record_at(Instant::now())
+    }
+
+    /// Lo mismo que `record` pero diciendole que hora es, para los tests
+    pub fn record_at(&mut self, now: Instant) -> u32 {
+        let elapsed = now.saturating_duration_since(self.last_decay);
+        let forgiven = elapsed.as_secs_f64() / VIOLATION_DECAY.as_secs_f64();
+        self.count = (self.count - forgiven).max(0.0) + 1.0;
+        self.last_decay = now;
+        self.count.ceil() as u32
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_burst_then_refill() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(RateLimit::new(2.0, 3.0));
+        bucket.last_refill = start;
+
+        assert!((0..3).all(|_| bucket.try_take_at(start)));
+        assert!(!bucket.try_take_at(start));
+
+        // a 2 por segundo en medio segundo se repone un solo token
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.try_take_at(later));
+        assert!(!bucket.try_take_at(later));
+
+        // y nunca se junta mas que el burst
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(
+            (0..10).filter(|_| bucket.try_take_at(much_later)).count(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            RateLimit::parse("10:20").unwrap(),
+            RateLimit::new(10.0, 20.0)
+        );
+        assert_eq!(RateLimit::parse("0.5:1").unwrap(), RateLimit::new(0.5, 1.0));
+        assert!(RateLimit::parse("10").is_err());
+        assert!(RateLimit::parse("0:5").is_err());
+        assert!(RateLimit::parse("5:0").is_err());
+        assert!(RateLimit::parse("NaN:5").is_err());
+        assert!(RateLimit::parse("5:NaN").is_err());
+        assert!(RateLimit::parse("inf:5").is_err());
+        assert!(RateLimit::parse("5:inf").is_err());
+    }
+
+    #[test]
+    fn test_violations_add_up_between_good_packets() {
+        // se pasa del limite dos veces por segundo, los packets buenos del medio no lo salvan
+        let start = Instant::now();
+        let mut violations = Violations::new();
+        violations.last_decay = start;
+        let counts: Vec<u32> = (0..10)
+            .map(|n| violations.record_at(start + Duration::from_millis(500) * n))
+            .collect();
+        assert_eq!(counts.last(), Some(&10));
+        assert!(counts.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn test_violations_are_forgiven_over_time() {
+        let start = Instant::now();
+        let mut violations = Violations::new();
+        violations.last_decay = start;
+        for _ in 0..3 {
+            violations.record_at(start);
+        }
+        // pasaron dos `VIOLATION_DECAY`, de las tres queda una mas la nueva
+        assert_eq!(violations.record_at(start + VIOLATION_DECAY * 2), 2);
+        // y despues de mucho tiempo se olvida de todas
+        assert_eq!(violations.record_at(start + VIOLATION_DECAY * 100), 1);
+    }
+}
@@ -0,0 +1,82 @@
+//! Apagado prolijo del server cuando llega un SIGINT o un SIGTERM
+use crate::connection_table::ConnectionTable;
+use crate::group_table::GroupTable;
+use async_chat_book::utils::ChatResult;
+use async_chat_book::FromServer;
+use async_std::channel::{self, Receiver};
+use async_std::future;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Cuando llega la senial cerramos el channel, asi todos los clones del receiver se enteran
+/// (`recv` devuelve un error apenas el channel esta cerrado)
+pub fn listen_for_signals() -> ChatResult<Receiver<()>> {
+    let (sender, receiver) = channel::bounded(1);
+    ctrlc::set_handler(move || {
+        sender.close();
+    })?;
+    Ok(receiver)
+}
+
+/// Lo que imprimimos al final para saber como quedo todo
+pub struct Summary {
+    pub connections: Vec<SocketAddr>,
+    pub notified: usize,
+    pub groups: usize,
+    pub undelivered: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "server shut down with {} active connection(s) in {} group(s)",
+            self.connections.len(),
+            self.groups
+        )?;
+        for peer in &self.connections {
+            writeln!(f, "    {}", peer)?;
+        }
+        write!(
+            f,
+            "{} of {} client(s) notified, {} message(s) left undelivered",
+            self.notified,
+            self.connections.len(),
+            self.undelivered
+        )
+    }
+}
+
+/// Para cuando ya no aceptamos conexiones nuevas: a cada conexion le encolamos
+/// `FromServer::ServerShutdown` detras de los mensajes que todavia tenia pendientes y la cerramos
+/// cuando se termino de escribir todo
+///
+/// Todo con un limite de `grace` para no quedarnos colgados por culpa de un cliente lento
+///
+/// NOTE(elsuizo:2021-12-06): el historial de cada grupo vive solo en memoria (no hay archivo ni
+/// base donde guardarlo), asi que lo unico que se puede "flushear" es lo que todavia esta en la
+/// cola de cada `Outbound`, y eso es lo que esperamos aca. Si algun dia el historial se guarda en
+/// disco este es el lugar para escribirlo
+pub async fn drain(groups: &GroupTable, connections: &ConnectionTable, grace: Duration) -> Summary {
+    let active = connections.snapshot();
+    let notices = active.iter().map(|(_, outbound)| {
+        future::timeout(grace, async move {
+            outbound.send(FromServer::ServerShutdown)?;
+            outbound.close().await
+        })
+    });
+    let notified = futures::future::join_all(notices)
+        .await
+        .into_iter()
+        .filter(|result| matches!(result, Ok(Ok(()))))
+        .count();
+    let undelivered = active.iter().map(|(_, outbound)| outbound.pending()).sum();
+
+    Summary {
+        connections: active.into_iter().map(|(peer, _)| peer).collect(),
+        notified,
+        groups: groups.len(),
+        undelivered,
+    }
+}
@@ -0,0 +1,125 @@
+//! Gateway de WebSocket para que un browser pueda chatear en los mismos grupos
+//!
+//! Cada mensaje de texto del WebSocket es un packet JSON (igual que una linea del codec
+//! `JsonLines`), asi que adaptamos el WebSocket para que parezca una conexion mas y `serve` no
+//! se entera de la diferencia
+use async_chat_book::codec::Codec;
+use async_std::io;
+use async_tungstenite::tungstenite::protocol::WebSocketConfig;
+use async_tungstenite::tungstenite::{Error as WsError, Message};
+use futures::future;
+use futures::io::AsyncReadExt;
+use futures::sink::Sink;
+use futures::stream::{StreamExt, TryStreamExt};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Hace el handshake de WebSocket y devuelve la mitad para leer y la mitad para escribir, listas
+/// para pasarselas a `serve`
+pub async fn accept<S>(
+    socket: S,
+    max_packet_size: usize,
+) -> Result<
+    (
+        impl io::Read + Unpin + Send,
+        WebSocketWriter<impl Sink<Message, Error = WsError> + Unpin + Send>,
+    ),
+    WsError,
+>
+where
+    S: io::Read + io::Write + Unpin + Send,
+{
+    // le dejamos un poco de margen al WebSocket para que los packets apenas mas grandes que el
+    // limite lleguen a `serve` y el cliente reciba el error de siempre en vez de un corte
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(max_packet_size.saturating_mul(2)))
+        .max_frame_size(Some(max_packet_size.saturating_mul(2)));
+    let websocket = async_tungstenite::accept_async_with_config(socket, Some(config)).await?;
+    let (sink, stream) = websocket.split();
+
+    let lines = stream
+        .map_err(io::Error::other)
+        .try_filter_map(|message| {
+            let line = match message {
+                Message::Text(text) => Some(text.as_bytes().to_vec()),
+                Message::Binary(bytes) => Some(bytes.to_vec()),
+                // de los ping se encarga tungstenite, y un close termina el stream solo
+                _ => None,
+            };
+            future::ready(Ok(line.map(|mut line| {
+                line.push(b'\n');
+                line
+            })))
+        })
+        .into_async_read();
+
+    // del otro lado no hay nadie que negocie el codec, asi que lo hacemos nosotros
+    const TAG: &[u8] = &[Codec::JsonLines.tag()];
+    let reader = TAG.chain(lines);
+    Ok((reader, WebSocketWriter::new(sink)))
+}
+
+/// Junta lo que escribe el `Outbound` y manda cada linea como un mensaje de texto
+pub struct WebSocketWriter<S> {
+    sink: S,
+    buffer: Vec<u8>,
+}
+
+impl<S> WebSocketWriter<S> {
+    fn new(sink: S) -> Self {
+        Self {
+            sink,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<S> WebSocketWriter<S>
+where
+    S: Sink<Message, Error = WsError> + Unpin,
+{
+    /// Manda al sink todas las lineas completas que tengamos en el buffer
+    fn poll_send_lines(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            if let Err(err) = futures::ready!(Pin::new(&mut self.sink).poll_ready(cx)) {
+                return Poll::Ready(Err(io::Error::other(err)));
+            }
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let text = String::from_utf8_lossy(&line[..end]).into_owned();
+            Pin::new(&mut self.sink)
+                .start_send(Message::text(text))
+                .map_err(io::Error::other)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> io::Write for WebSocketWriter<S>
+where
+    S: Sink<Message, Error = WsError> + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().buffer.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_send_lines(cx))?;
+        Pin::new(&mut this.sink)
+            .poll_flush(cx)
+            .map_err(io::Error::other)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_send_lines(cx))?;
+        Pin::new(&mut this.sink)
+            .poll_close(cx)
+            .map_err(io::Error::other)
+    }
+}
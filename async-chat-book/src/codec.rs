@@ -0,0 +1,290 @@
+//! Los distintos formatos en los que viajan los packets por la conexion
+//!
+//! Lo primero que manda el cliente al conectarse es un byte (el `tag` del codec) y a partir de ahi
+//! los dos lados usan ese codec para todo lo que queda de la conexion
+use crate::utils::{self, ChatResult};
+use async_std::prelude::*;
+use futures::stream::BoxStream;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::Unpin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Un packet JSON por linea (el formato original del libro)
+    JsonLines,
+    /// Packets en MessagePack precedidos por su largo, no le importa el tamanio ni los `\n`
+    LengthPrefixed,
+}
+
+/// Stream de packets que devuelve `Codec::receive`, va en un `Box` porque cada codec tiene su
+/// propio type de stream
+pub type PacketStream<'a, P> = BoxStream<'a, ChatResult<P>>;
+
+impl Codec {
+    /// El byte que identifica al codec durante la negociacion
+    pub const fn tag(self) -> u8 {
+        match self {
+            Codec::JsonLines => b'j',
+            Codec::LengthPrefixed => b'b',
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            b'j' => Some(Codec::JsonLines),
+            b'b' => Some(Codec::LengthPrefixed),
+            _ => None,
+        }
+    }
+
+    /// Para elegir el codec desde la linea de comandos
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Codec::JsonLines),
+            "binary" => Some(Codec::LengthPrefixed),
+            _ => None,
+        }
+    }
+
+    /// Lado del cliente de la negociacion: le avisamos al server que codec vamos a usar
+    pub async fn announce<S>(self, outbound: &mut S) -> ChatResult<()>
+    where
+        S: async_std::io::Write + Unpin,
+    {
+        outbound.write_all(&[self.tag()]).await?;
+        outbound.flush().await?;
+        Ok(())
+    }
+
+    /// Lado del server de la negociacion: leemos el codec que eligio el cliente
+    pub async fn negotiate<S>(inbound: &mut S) -> ChatResult<Self>
+    where
+        S: async_std::io::Read + Unpin,
+    {
+        let mut tag = [0u8; 1];
+        inbound.read_exact(&mut tag).await?;
+        Codec::from_tag(tag[0]).ok_or_else(|| format!("unknown codec tag {:#04x}", tag[0]).into())
+    }
+
+    pub async fn send<S, P>(self, outbound: &mut S, packet: &P) -> ChatResult<()>
+    where
+        S: async_std::io::Write + Unpin,
+        P: Serialize,
+    {
+        match self {
+            Codec::JsonLines => utils::send_as_json(outbound, packet).await,
+            Codec::LengthPrefixed => utils::send_as_binary(outbound, packet).await,
+        }
+    }
+
+    /// Los packets de mas de `max_size` bytes o que no se pueden decodificar aparecen en el stream
+    /// como un `PacketError` y se puede seguir leyendo despues de ellos
+    pub fn receive<'a, S, P>(self, inbound: S, max_size: usize) -> PacketStream<'a, P>
+    where
+        S: async_std::io::BufRead + Unpin + Send + 'a,
+        P: DeserializeOwned + Send + 'a,
+    {
+        match self {
+            Codec::JsonLines => Box::pin(utils::receive_as_json(inbound, max_size)),
+            Codec::LengthPrefixed => Box::pin(utils::receive_as_binary(inbound, max_size)),
+        }
+    }
+}
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::utils::{PacketError, DEFAULT_MAX_PACKET_SIZE};
+    use crate::{FromClient, FromServer, Versioned};
+    use crate::{LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION};
+    use async_std::io::BufReader;
+    use async_std::os::unix::net::UnixStream;
+    use async_std::task;
+    use std::sync::Arc;
+
+    fn packets() -> Vec<FromClient> {
+        vec![
+            FromClient::Join {
+                group_name: Arc::new("Dogs".to_string()),
+                password: None,
+            },
+            FromClient::Post {
+                group_name: Arc::new("Dogs".to_string()),
+                message: Arc::new("Samoyeds rock!!!\nand they know it".to_string()),
+            },
+            FromClient::Post {
+                group_name: Arc::new("Dogs".to_string()),
+                message: Arc::new("woof ".repeat(100_000)),
+            },
+        ]
+    }
+
+    // mandamos packets de un lado del socket y los leemos del otro, como haria el server
+    fn round_trip(codec: Codec) {
+        task::block_on(async {
+            let (mut client, server) = UnixStream::pair().unwrap();
+
+            let writer = task::spawn(async move {
+                codec.announce(&mut client).await.unwrap();
+                for packet in packets() {
+                    codec.send(&mut client, &packet).await.unwrap();
+                }
+                client
+            });
+
+            let mut buffered = BufReader::new(server);
+            let negotiated = Codec::negotiate(&mut buffered).await.unwrap();
+            assert_eq!(negotiated, codec);
+
+            let received: Vec<FromClient> = negotiated
+                .receive(buffered, 1024 * 1024)
+                .take(packets().len())
+                .map(|packet| packet.unwrap())
+                .collect()
+                .await;
+            assert_eq!(received, packets());
+            writer.await;
+        })
+    }
+
+    #[test]
+    fn test_json_lines_round_trip() {
+        round_trip(Codec::JsonLines);
+    }
+
+    #[test]
+    fn test_length_prefixed_round_trip() {
+        round_trip(Codec::LengthPrefixed);
+    }
+
+    // los clientes viejos y los nuevos comparten el server, asi que los dos formatos tienen que
+    // poder venir mezclados en la misma conexion
+    fn versioned_round_trip(codec: Codec) {
+        task::block_on(async {
+            let (mut client, server) = UnixStream::pair().unwrap();
+            let sent: Vec<Versioned<FromClient>> = packets()
+                .into_iter()
+                .enumerate()
+                .map(|(id, packet)| {
+                    let version = if id % 2 == 0 {
+                        PROTOCOL_VERSION
+                    } else {
+                        LEGACY_PROTOCOL_VERSION
+                    };
+                    Versioned::new(packet, version, Some(id as u64))
+                })
+                .collect();
+            let to_send = sent.clone();
+            let writer = task::spawn(async move {
+                for packet in &to_send {
+                    codec.send(&mut client, packet).await.unwrap();
+                }
+            });
+
+            let received: Vec<Versioned<FromClient>> = codec
+                .receive(BufReader::new(server), 1024 * 1024)
+                .map(|packet| packet.unwrap())
+                .collect()
+                .await;
+            assert_eq!(received, sent);
+            writer.await;
+        })
+    }
+
+    #[test]
+    fn test_json_lines_versioned_round_trip() {
+        versioned_round_trip(Codec::JsonLines);
+    }
+
+    #[test]
+    fn test_length_prefixed_versioned_round_trip() {
+        versioned_round_trip(Codec::LengthPrefixed);
+    }
+
+    #[test]
+    fn test_stream_ends_when_peer_closes() {
+        task::block_on(async {
+            let (mut server, client) = UnixStream::pair().unwrap();
+            let reply = FromServer::Error("bye".to_string());
+            Codec::LengthPrefixed
+                .send(&mut server, &reply)
+                .await
+                .unwrap();
+            drop(server);
+
+            let mut replies = Codec::LengthPrefixed
+                .receive::<_, FromServer>(BufReader::new(client), DEFAULT_MAX_PACKET_SIZE);
+            assert_eq!(replies.next().await.unwrap().unwrap(), reply);
+            assert!(replies.next().await.is_none());
+        })
+    }
+
+    #[test]
+    fn test_unknown_tag() {
+        task::block_on(async {
+            let mut inbound: &[u8] = b"x{}";
+            assert!(Codec::negotiate(&mut inbound).await.is_err());
+        })
+    }
+
+    // despues de un packet demasiado grande o mal formado tenemos que poder seguir leyendo
+    fn recovers_from_bad_packets(codec: Codec, garbage: &[u8]) {
+        task::block_on(async {
+            let (mut client, server) = UnixStream::pair().unwrap();
+            let join = FromClient::Join {
+                group_name: Arc::new("Cats".to_string()),
+                password: None,
+            };
+            let huge = FromClient::Post {
+                group_name: Arc::new("Cats".to_string()),
+                message: Arc::new("meow".repeat(1000)),
+            };
+            codec.send(&mut client, &huge).await.unwrap();
+            client.write_all(garbage).await.unwrap();
+            codec.send(&mut client, &join).await.unwrap();
+            drop(client);
+
+            let received: Vec<_> = codec
+                .receive::<_, FromClient>(BufReader::new(server), 256)
+                .collect()
+                .await;
+            assert_eq!(received.len(), 3);
+            let too_large = received[0].as_ref().unwrap_err();
+            assert_eq!(
+                too_large.downcast_ref::<PacketError>(),
+                Some(&PacketError::TooLarge { max_size: 256 })
+            );
+            let malformed = received[1].as_ref().unwrap_err();
+            assert!(matches!(
+                malformed.downcast_ref::<PacketError>(),
+                Some(PacketError::Malformed(_))
+            ));
+            assert_eq!(received[2].as_ref().unwrap(), &join);
+        })
+    }
+
+    #[test]
+    fn test_json_lines_recovers_from_bad_packets() {
+        recovers_from_bad_packets(Codec::JsonLines, b"{\"Join\": nope}\n");
+    }
+
+    #[test]
+    fn test_length_prefixed_recovers_from_bad_packets() {
+        recovers_from_bad_packets(Codec::LengthPrefixed, b"\x00\x00\x00\x03abc");
+    }
+
+    #[test]
+    fn test_line_without_newline_is_bounded() {
+        task::block_on(async {
+            let endless = vec![b'a'; 10_000];
+            let mut lines = Codec::JsonLines.receive::<_, FromClient>(&endless[..], 100);
+            let err = lines.next().await.unwrap().unwrap_err();
+            assert!(utils::is_recoverable(&err));
+            assert!(lines.next().await.is_none());
+        })
+    }
+}
@@ -1,29 +1,258 @@
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+pub mod attachment;
+pub mod codec;
+pub mod tls;
 pub mod utils;
 
+/// La version del protocolo que hablan este server y este cliente
+pub const PROTOCOL_VERSION: u32 = 3;
+/// La version original, la de los packets sueltos sin sobre. El server la sigue entendiendo para
+/// que los clientes viejos no se rompan
+pub const LEGACY_PROTOCOL_VERSION: u32 = 1;
+
+/// Las versiones que entiende el server
+pub fn is_supported(protocol_version: u32) -> bool {
+    (LEGACY_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol_version)
+}
+
+/// El sobre en el que viaja cada packet desde la version 2
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Envelope<P> {
+    pub protocol_version: u32,
+    /// lo elige el cliente y el server lo repite en los errores, asi sabemos que comando fallo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub packet: P,
+}
+
+impl<P> Envelope<P> {
+    /// Un sobre de la version actual
+    pub fn new(packet: P, id: Option<u64>) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            id,
+            packet,
+        }
+    }
+}
+
+/// Lo que puede llegar por la conexion: un packet en su sobre o un packet suelto de la version 1
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum Versioned<P> {
+    Enveloped(Envelope<P>),
+    Legacy(P),
+}
+
+impl<P> Versioned<P> {
+    /// Arma el packet como lo espera alguien que habla `protocol_version`
+    pub fn new(packet: P, protocol_version: u32, id: Option<u64>) -> Self {
+        if protocol_version == LEGACY_PROTOCOL_VERSION {
+            Versioned::Legacy(packet)
+        } else {
+            Versioned::Enveloped(Envelope {
+                protocol_version,
+                id,
+                packet,
+            })
+        }
+    }
+
+    pub fn protocol_version(&self) -> u32 {
+        match self {
+            Versioned::Enveloped(envelope) => envelope.protocol_version,
+            Versioned::Legacy(_) => LEGACY_PROTOCOL_VERSION,
+        }
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        match self {
+            Versioned::Enveloped(envelope) => envelope.id,
+            Versioned::Legacy(_) => None,
+        }
+    }
+
+    pub fn packet(&self) -> &P {
+        match self {
+            Versioned::Enveloped(envelope) => &envelope.packet,
+            Versioned::Legacy(packet) => packet,
+        }
+    }
+
+    pub fn into_packet(self) -> P {
+        match self {
+            Versioned::Enveloped(envelope) => envelope.packet,
+            Versioned::Legacy(packet) => packet,
+        }
+    }
+}
+
 // TODO(elsuizo:2021-11-12): no podemos reemplazar a los types Post y Message por un type solo que
 // sea mas generico y que tenga un builder???
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub enum FromClient {
+    /// El nombre con el que nos conocen los demas. Con `password` lo reclamamos: desde ahi solo
+    /// lo puede usar el que tenga la password, y solo los nombres reclamados sirven para ser owner
+    /// o moderador de un grupo y para que nos inviten
+    SetName {
+        name: String,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    /// Crea el grupo (y entramos en el), falla si ya existe
+    CreateGroup {
+        group_name: Arc<String>,
+        access: GroupAccess,
+    },
+    /// Entra a un grupo que ya existe, `password` solo hace falta si el grupo tiene una
     Join {
         group_name: Arc<String>,
+        #[serde(default)]
+        password: Option<String>,
     },
+    /// Solo para los miembros del grupo
     Post {
         group_name: Arc<String>,
         message: Arc<String>,
     },
+    /// Agrega a `member` a la lista de invitados, solo lo pueden hacer el owner y los moderadores
+    Invite {
+        group_name: Arc<String>,
+        member: String,
+    },
+    /// Hace moderador a `member`, solo lo puede hacer el owner
+    Promote {
+        group_name: Arc<String>,
+        member: String,
+    },
+    /// Pide de nuevo los mensajes del grupo con numero de secuencia entre `from` y `to`
+    /// (incluidos), para cuando nos perdimos alguno. Solo para los miembros
+    History {
+        group_name: Arc<String>,
+        from: u64,
+        to: u64,
+    },
+    /// Empieza a subir un archivo para los miembros del grupo. `upload_id` lo elige el cliente y
+    /// solo vale en esta conexion, `checksum` es el SHA-256 del archivo entero en hexa
+    UploadStart {
+        upload_id: u64,
+        group_name: Arc<String>,
+        file_name: String,
+        size: u64,
+        checksum: String,
+    },
+    /// El siguiente pedazo del archivo, tienen que llegar en orden
+    UploadChunk {
+        upload_id: u64,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+    },
+    /// Ya mandamos todo: si el tamanio y el checksum coinciden el archivo queda guardado en el
+    /// server y a los miembros del grupo les llega un `AttachmentAvailable`
+    UploadFinish { upload_id: u64 },
+    /// Pide un archivo que se subio a un grupo en el que estamos, llega un `AttachmentAvailable`
+    /// y despues los pedazos
+    Download { attachment_id: u64 },
+    /// Comandos para administrar el server, solo funcionan con el token que se le paso al server
+    Admin {
+        token: String,
+        command: AdminCommand,
+    },
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+/// Quienes pueden entrar a un grupo
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum GroupAccess {
+    /// cualquiera
+    Open,
+    /// los que mandan esta password en el `Join`
+    Password(String),
+    /// solo los que estan en la lista (por nombre reclamado), el owner y los moderadores pueden
+    /// invitar a mas
+    InviteOnly(Vec<String>),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum AdminCommand {
+    /// Desconecta al cliente que se conecto desde `peer` (por ejemplo `127.0.0.1:4242`)
+    Kick { peer: String },
+    /// Cierra el grupo y saca a todos sus miembros
+    CloseGroup { group_name: Arc<String> },
+    /// Le manda un aviso a todos los clientes conectados
+    Broadcast { message: String },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub enum FromServer {
+    /// `seq` crece de a uno en cada grupo, si salta es que nos perdimos mensajes (los clientes
+    /// de la version 1 no lo conocen y lo ignoran)
     Message {
         group_name: Arc<String>,
         message: Arc<String>,
+        #[serde(default)]
+        seq: u64,
+    },
+    /// Le llega al que posteo cuando su mensaje ya se repartio, con el numero que le toco
+    Ack {
+        group_name: Arc<String>,
+        seq: u64,
+    },
+    /// Los mensajes que se pidieron con `FromClient::History` (los que todavia estaban)
+    History {
+        group_name: Arc<String>,
+        messages: Vec<Logged>,
+    },
+    /// Hay un archivo nuevo en el grupo, o es el que pedimos con `Download` y atras vienen los
+    /// pedazos
+    AttachmentAvailable(Attachment),
+    /// Un pedazo del archivo que pedimos, llegan en orden hasta completar el tamanio
+    AttachmentChunk {
+        attachment_id: u64,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
     },
     Error(String),
+    /// El server se esta apagando y va a cerrar la conexion
+    ServerShutdown,
+    /// Un aviso para todos de parte de un administrador
+    Notice(String),
+}
+
+impl FromServer {
+    /// La primera version del protocolo que tiene este packet, a los clientes mas viejos no se
+    /// lo mandamos porque no lo entenderian
+    pub fn protocol_version(&self) -> u32 {
+        match self {
+            FromServer::Message { .. }
+            | FromServer::Error(_)
+            | FromServer::ServerShutdown
+            | FromServer::Notice(_) => LEGACY_PROTOCOL_VERSION,
+            FromServer::Ack { .. } | FromServer::History { .. } => 2,
+            FromServer::AttachmentAvailable(_) | FromServer::AttachmentChunk { .. } => 3,
+        }
+    }
+}
+
+/// Un archivo guardado en el server
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Attachment {
+    /// lo elige el server, es el que se usa en `Download`
+    pub id: u64,
+    pub group_name: Arc<String>,
+    pub file_name: String,
+    pub size: u64,
+    /// el SHA-256 en hexa
+    pub checksum: String,
+}
+
+/// Un mensaje que quedo en el historial de un grupo
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Logged {
+    pub seq: u64,
+    pub message: Arc<String>,
 }
 //-------------------------------------------------------------------------
 //                        testing
@@ -51,4 +280,200 @@ mod tests {
             from_client
         );
     }
+
+    #[test]
+    fn test_admin_json() {
+        let from_client = FromClient::Admin {
+            token: "secret".to_string(),
+            command: AdminCommand::Kick {
+                peer: "127.0.0.1:4242".to_string(),
+            },
+        };
+        let json = serde_json::to_string(&from_client).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Admin":{"token":"secret","command":{"Kick":{"peer":"127.0.0.1:4242"}}}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<FromClient>(&json).unwrap(),
+            from_client
+        );
+    }
+
+    #[test]
+    fn test_join_json() {
+        let join = FromClient::Join {
+            group_name: Arc::new("Dogs".to_string()),
+            password: Some("woof".to_string()),
+        };
+        let json = serde_json::to_string(&join).unwrap();
+        assert_eq!(json, r#"{"Join":{"group_name":"Dogs","password":"woof"}}"#);
+        assert_eq!(serde_json::from_str::<FromClient>(&json).unwrap(), join);
+
+        // los clientes que no conocen las passwords siguen pudiendo entrar a los grupos abiertos
+        assert_eq!(
+            serde_json::from_str::<FromClient>(r#"{"Join":{"group_name":"Dogs"}}"#).unwrap(),
+            FromClient::Join {
+                group_name: Arc::new("Dogs".to_string()),
+                password: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_create_group_json() {
+        let create = FromClient::CreateGroup {
+            group_name: Arc::new("Cats".to_string()),
+            access: GroupAccess::InviteOnly(vec!["tom".to_string()]),
+        };
+        let json = serde_json::to_string(&create).unwrap();
+        assert_eq!(
+            json,
+            r#"{"CreateGroup":{"group_name":"Cats","access":{"InviteOnly":["tom"]}}}"#
+        );
+        assert_eq!(serde_json::from_str::<FromClient>(&json).unwrap(), create);
+    }
+
+    #[test]
+    fn test_versioned_from_client_json() {
+        let post = FromClient::Post {
+            group_name: Arc::new("Dogs".to_string()),
+            message: Arc::new("Samoyeds rock!!!".to_string()),
+        };
+
+        // version 1: el packet suelto, igual que antes
+        let legacy = Versioned::new(post.clone(), LEGACY_PROTOCOL_VERSION, Some(7));
+        let json = serde_json::to_string(&legacy).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Post":{"group_name":"Dogs","message":"Samoyeds rock!!!"}}"#
+        );
+        let parsed = serde_json::from_str::<Versioned<FromClient>>(&json).unwrap();
+        assert_eq!(parsed.protocol_version(), LEGACY_PROTOCOL_VERSION);
+        assert_eq!(parsed.id(), None);
+        assert_eq!(parsed.into_packet(), post);
+
+        // version 2: el mismo packet dentro del sobre
+        let current = Versioned::new(post.clone(), 2, Some(7));
+        let json = serde_json::to_string(&current).unwrap();
+        assert_eq!(
+            json,
+            r#"{"protocol_version":2,"id":7,"packet":{"Post":{"group_name":"Dogs","message":"Samoyeds rock!!!"}}}"#
+        );
+        let parsed = serde_json::from_str::<Versioned<FromClient>>(&json).unwrap();
+        assert_eq!(parsed, current);
+        assert_eq!(parsed.protocol_version(), 2);
+        assert_eq!(parsed.id(), Some(7));
+
+        // el id es opcional
+        let parsed = serde_json::from_str::<Versioned<FromClient>>(
+            r#"{"protocol_version":3,"packet":{"SetName":{"name":"tom"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed,
+            Versioned::Enveloped(Envelope::new(
+                FromClient::SetName {
+                    name: "tom".to_string(),
+                    password: None,
+                },
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn test_versioned_from_server_json() {
+        let error = FromServer::Error("Group 'Cats' does not exist".to_string());
+
+        let json = serde_json::to_string(&Versioned::new(error.clone(), 1, Some(3))).unwrap();
+        assert_eq!(json, r#"{"Error":"Group 'Cats' does not exist"}"#);
+        assert_eq!(
+            serde_json::from_str::<Versioned<FromServer>>(&json).unwrap(),
+            Versioned::Legacy(error.clone())
+        );
+
+        let json = serde_json::to_string(&Versioned::new(error.clone(), 2, Some(3))).unwrap();
+        assert_eq!(
+            json,
+            r#"{"protocol_version":2,"id":3,"packet":{"Error":"Group 'Cats' does not exist"}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Versioned<FromServer>>(&json).unwrap(),
+            Versioned::new(error, 2, Some(3))
+        );
+
+        let json =
+            serde_json::to_string(&Versioned::new(FromServer::ServerShutdown, 2, None)).unwrap();
+        assert_eq!(json, r#"{"protocol_version":2,"packet":"ServerShutdown"}"#);
+    }
+
+    #[test]
+    fn test_message_json() {
+        let message = FromServer::Message {
+            group_name: Arc::new("Dogs".to_string()),
+            message: Arc::new("woof".to_string()),
+            seq: 7,
+        };
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Message":{"group_name":"Dogs","message":"woof","seq":7}}"#
+        );
+        assert_eq!(serde_json::from_str::<FromServer>(&json).unwrap(), message);
+
+        // los mensajes sin numero (de un server viejo) tienen el 0
+        let json = r#"{"Message":{"group_name":"Dogs","message":"woof"}}"#;
+        assert!(matches!(
+            serde_json::from_str::<FromServer>(json).unwrap(),
+            FromServer::Message { seq: 0, .. }
+        ));
+
+        let ack = FromServer::Ack {
+            group_name: Arc::new("Dogs".to_string()),
+            seq: 7,
+        };
+        assert_eq!(ack.protocol_version(), 2);
+        let json = serde_json::to_string(&ack).unwrap();
+        assert_eq!(json, r#"{"Ack":{"group_name":"Dogs","seq":7}}"#);
+    }
+
+    #[test]
+    fn test_attachment_json() {
+        let chunk = FromClient::UploadChunk {
+            upload_id: 1,
+            data: vec![0, 255],
+        };
+        let json = serde_json::to_string(&chunk).unwrap();
+        assert_eq!(json, r#"{"UploadChunk":{"upload_id":1,"data":[0,255]}}"#);
+        assert_eq!(serde_json::from_str::<FromClient>(&json).unwrap(), chunk);
+
+        // en binario los bytes van como bytes y no como una lista de numeros
+        let chunk = FromServer::AttachmentChunk {
+            attachment_id: 1,
+            data: vec![7; 100],
+        };
+        let binary = rmp_serde::to_vec_named(&chunk).unwrap();
+        assert!(binary.len() < 150);
+        assert_eq!(rmp_serde::from_slice::<FromServer>(&binary).unwrap(), chunk);
+        assert_eq!(chunk.protocol_version(), PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_supported_versions() {
+        assert!(!is_supported(0));
+        assert!(is_supported(LEGACY_PROTOCOL_VERSION));
+        assert!(is_supported(PROTOCOL_VERSION));
+        assert!(!is_supported(PROTOCOL_VERSION + 1));
+    }
+
+    #[test]
+    fn test_from_server_json() {
+        let json = serde_json::to_string(&FromServer::ServerShutdown).unwrap();
+        assert_eq!(json, r#""ServerShutdown""#);
+        assert_eq!(
+            serde_json::from_str::<FromServer>(&json).unwrap(),
+            FromServer::ServerShutdown
+        );
+    }
 }
@@ -0,0 +1,58 @@
+//! Lo necesario para que el server y el cliente hablen por TLS (con rustls)
+//!
+//! Los certificados y la clave privada se leen de archivos PEM
+use crate::utils::ChatResult;
+use futures_rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use futures_rustls::rustls::{self, ClientConfig, RootCertStore, ServerConfig};
+use futures_rustls::{TlsAcceptor, TlsConnector};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+pub fn load_certs(path: &Path) -> ChatResult<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {}", path.display()).into());
+    }
+    Ok(certs)
+}
+
+pub fn load_private_key(path: &Path) -> ChatResult<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| format!("no private key found in {}", path.display()).into())
+}
+
+fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Lo que usa el server para hacer el handshake con cada conexion nueva
+pub fn acceptor(cert_path: &Path, key_path: &Path) -> ChatResult<TlsAcceptor> {
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(load_certs(cert_path)?, load_private_key(key_path)?)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Lo que usa el cliente para conectarse, `ca_path` es el certificado en el que confiamos (para
+/// un certificado self-signed es el mismo certificado del server)
+pub fn connector(ca_path: &Path) -> ChatResult<TlsConnector> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca_path)? {
+        roots.add(cert)?;
+    }
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// El nombre contra el que se verifica el certificado del server
+pub fn server_name(domain: &str) -> ChatResult<ServerName<'static>> {
+    Ok(ServerName::try_from(domain.to_string())?)
+}
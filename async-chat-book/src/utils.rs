@@ -19,15 +19,189 @@ where
 }
 
 use serde::de::DeserializeOwned;
+use std::fmt;
 
-pub fn receive_as_json<S, P>(inbound: S) -> impl Stream<Item = ChatResult<P>>
+/// Tamanio maximo por default de un packet (sin contar el `\n` o el largo del principio)
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 64 * 1024;
+
+/// Errores de un packet en particular, despues de uno de estos se puede seguir leyendo de la
+/// conexion sin problemas (a diferencia de los errores de I/O)
+#[derive(Debug, PartialEq)]
+pub enum PacketError {
+    TooLarge { max_size: usize },
+    Malformed(String),
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketError::TooLarge { max_size } => {
+                write!(f, "packet too large: the limit is {} bytes", max_size)
+            }
+            PacketError::Malformed(reason) => write!(f, "malformed packet: {}", reason),
+        }
+    }
+}
+
+impl Error for PacketError {}
+
+/// Compara dos secretos (tokens, passwords) recorriendolos enteros, asi lo que tarda no dice
+/// cuantos caracteres acertaste (el largo si se nota, pero eso no ayuda a adivinarlo)
+pub fn secrets_match(expected: &str, given: &str) -> bool {
+    let (expected, given) = (expected.as_bytes(), given.as_bytes());
+    if expected.len() != given.len() {
+        return false;
+    }
+    expected
+        .iter()
+        .zip(given)
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+/// Nos dice si vale la pena seguir leyendo de la conexion despues de este error
+pub fn is_recoverable(err: &ChatError) -> bool {
+    err.is::<PacketError>()
+}
+
+/// Como `lines()` pero sin guardar mas de `max_size` bytes de una linea: si la linea es mas larga
+/// la descartamos hasta el `\n` y devolvemos `PacketError::TooLarge`
+async fn read_bounded_line<S>(inbound: &mut S, max_size: usize) -> ChatResult<Option<String>>
 where
     S: async_std::io::BufRead + Unpin,
+{
+    use futures::io::AsyncBufReadExt;
+
+    let mut line = Vec::new();
+    let mut size = 0;
+    loop {
+        let available = inbound.fill_buf().await?;
+        if available.is_empty() {
+            // EOF: si no leimos nada se termino la conexion, sino es la ultima linea sin `\n`
+            if size == 0 {
+                return Ok(None);
+            }
+            break;
+        }
+        let (chunk, used) = match available.iter().position(|&byte| byte == b'\n') {
+            Some(end) => (&available[..end], end + 1),
+            None => (available, available.len()),
+        };
+        size += chunk.len();
+        if size <= max_size {
+            line.extend_from_slice(chunk);
+        } else {
+            line = Vec::new();
+        }
+        let found_newline = used > chunk.len();
+        inbound.consume_unpin(used);
+        if found_newline {
+            break;
+        }
+    }
+
+    if size > max_size {
+        return Err(PacketError::TooLarge { max_size }.into());
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    let line = String::from_utf8(line).map_err(|err| PacketError::Malformed(err.to_string()))?;
+    Ok(Some(line))
+}
+
+/// Leemos un packet JSON por linea, las lineas de mas de `max_size` bytes o que no se pueden
+/// parsear se reportan como `PacketError` y el stream sigue con la linea siguiente
+pub fn receive_as_json<S, P>(inbound: S, max_size: usize) -> impl Stream<Item = ChatResult<P>>
+where
+    S: async_std::io::BufRead + Unpin,
+    P: DeserializeOwned,
+{
+    futures::stream::unfold(Some(inbound), move |state| async move {
+        let mut inbound = state?;
+        match read_bounded_line(&mut inbound, max_size).await {
+            Ok(Some(line)) => {
+                let parsed = serde_json::from_str::<P>(&line)
+                    .map_err(|err| PacketError::Malformed(err.to_string()).into());
+                Some((parsed, Some(inbound)))
+            }
+            Ok(None) => None,
+            Err(err) if is_recoverable(&err) => Some((Err(err), Some(inbound))),
+            Err(err) => Some((Err(err), None)),
+        }
+    })
+}
+
+/// Manda el packet como MessagePack precedido por su largo en bytes (un `u32` big-endian)
+pub async fn send_as_binary<S, P>(outbound: &mut S, packet: &P) -> ChatResult<()>
+where
+    S: async_std::io::Write + Unpin,
+    P: Serialize,
+{
+    let body = rmp_serde::to_vec_named(packet)?;
+    let length = u32::try_from(body.len())?;
+    outbound.write_all(&length.to_be_bytes()).await?;
+    outbound.write_all(&body).await?;
+    Ok(())
+}
+
+use async_std::io::ErrorKind;
+
+/// La contraparte de `send_as_binary`: el stream termina cuando la conexion se cierra justo entre
+/// dos packets o despues del primer error de I/O. Los packets de mas de `max_size` bytes se
+/// descartan sin guardarlos en memoria
+pub fn receive_as_binary<S, P>(inbound: S, max_size: usize) -> impl Stream<Item = ChatResult<P>>
+where
+    S: async_std::io::Read + Unpin,
     P: DeserializeOwned,
 {
-    inbound.lines().map(|line_result| -> ChatResult<P> {
-        let line = line_result?;
-        let parsed = serde_json::from_str::<P>(&line)?;
-        Ok(parsed)
+    futures::stream::unfold(Some(inbound), move |state| async move {
+        let mut inbound = state?;
+        let mut length = [0u8; 4];
+        match inbound.read_exact(&mut length).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return None,
+            Err(err) => return Some((Err(err.into()), None)),
+        }
+
+        let length = u32::from_be_bytes(length) as u64;
+        if length > max_size as u64 {
+            let mut rest = (&mut inbound).take(length);
+            return match async_std::io::copy(&mut rest, &mut async_std::io::sink()).await {
+                Ok(skipped) if skipped == length => Some((
+                    Err(PacketError::TooLarge { max_size }.into()),
+                    Some(inbound),
+                )),
+                Ok(_) => Some((
+                    Err(async_std::io::Error::from(ErrorKind::UnexpectedEof).into()),
+                    None,
+                )),
+                Err(err) => Some((Err(err.into()), None)),
+            };
+        }
+
+        let mut body = vec![0u8; length as usize];
+        if let Err(err) = inbound.read_exact(&mut body).await {
+            return Some((Err(err.into()), None));
+        }
+        let parsed = rmp_serde::from_slice::<P>(&body)
+            .map_err(|err| PacketError::Malformed(err.to_string()).into());
+        Some((parsed, Some(inbound)))
     })
 }
+
+//-------------------------------------------------------------------------
+//                        testing
+//-------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_match() {
+        assert!(secrets_match("secret", "secret"));
+        assert!(!secrets_match("secret", "secreT"));
+        assert!(!secrets_match("secret", "secre"));
+        assert!(!secrets_match("secret", ""));
+    }
+}
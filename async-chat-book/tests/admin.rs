@@ -0,0 +1,142 @@
+//! tests/admin.rs
+//!
+//! Las metricas para Prometheus y los comandos de administrador
+use async_chat_book::codec::Codec;
+use async_chat_book::{AdminCommand, FromClient, FromServer, GroupAccess};
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+use std::sync::Arc;
+
+mod common;
+use common::{free_address, ServerProcess, TestClient};
+
+fn admin(token: &str, command: AdminCommand) -> FromClient {
+    FromClient::Admin {
+        token: token.to_string(),
+        command,
+    }
+}
+
+async fn scrape(address: &str) -> String {
+    let mut socket = TcpStream::connect(address).await.unwrap();
+    socket
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    socket.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    response
+}
+
+#[test]
+fn metrics_and_admin_commands() {
+    let metrics_address = free_address();
+    let server = ServerProcess::spawn([
+        "--metrics-address",
+        &metrics_address,
+        "--admin-token",
+        "secret",
+    ]);
+
+    task::block_on(async {
+        let group_name = Arc::new("Dogs".to_string());
+        let mut member = TestClient::connect(&server, Codec::JsonLines).await;
+        member
+            .send(FromClient::CreateGroup {
+                group_name: group_name.clone(),
+                access: GroupAccess::Open,
+            })
+            .await;
+        member
+            .send(FromClient::Post {
+                group_name: group_name.clone(),
+                message: Arc::new("woof".to_string()),
+            })
+            .await;
+        assert!(matches!(
+            member.next().await,
+            Some(FromServer::Message { .. })
+        ));
+
+        let metrics = scrape(&metrics_address).await;
+        for line in [
+            "chat_connections_total 1\n",
+            "chat_joins_total 1\n",
+            "chat_posts_total 1\n",
+            "chat_errors_total 0\n",
+            "chat_active_connections 1\n",
+            "chat_groups 1\n",
+        ] {
+            assert!(metrics.contains(line), "missing {:?} in\n{}", line, metrics);
+        }
+
+        let mut operator = TestClient::connect(&server, Codec::LengthPrefixed).await;
+        let notice = "maintenance at noon".to_string();
+        operator
+            .send(admin(
+                "wrong",
+                AdminCommand::Broadcast {
+                    message: notice.clone(),
+                },
+            ))
+            .await;
+        assert_eq!(
+            operator.next().await,
+            Some(FromServer::Error("invalid admin token".to_string()))
+        );
+
+        operator
+            .send(admin(
+                "secret",
+                AdminCommand::Broadcast {
+                    message: notice.clone(),
+                },
+            ))
+            .await;
+        assert_eq!(
+            member.next().await,
+            Some(FromServer::Notice(notice.clone()))
+        );
+        assert_eq!(operator.next().await, Some(FromServer::Notice(notice)));
+
+        operator
+            .send(admin(
+                "secret",
+                AdminCommand::CloseGroup {
+                    group_name: group_name.clone(),
+                },
+            ))
+            .await;
+        assert_eq!(
+            member.next().await,
+            Some(FromServer::Error("Group 'Dogs' was closed".to_string()))
+        );
+
+        operator
+            .send(admin(
+                "secret",
+                AdminCommand::Kick {
+                    peer: member.local_addr().to_string(),
+                },
+            ))
+            .await;
+        assert_eq!(
+            member.next().await,
+            Some(FromServer::Error(
+                "you have been kicked by an administrator".to_string()
+            ))
+        );
+        assert_eq!(member.next().await, None);
+
+        let metrics = scrape(&metrics_address).await;
+        assert!(
+            metrics.contains("chat_connections_total 2\n"),
+            "{}",
+            metrics
+        );
+        assert!(metrics.contains("chat_groups 0\n"), "{}", metrics);
+        assert!(metrics.contains("chat_errors_total 3\n"), "{}", metrics);
+    });
+}
@@ -0,0 +1,78 @@
+//! tests/attachments.rs
+//!
+//! Un cliente sube un archivo a un grupo y otro miembro lo baja con `get`, usando los binarios
+//! de verdad
+use std::path::PathBuf;
+
+mod common;
+use common::{ClientProcess, ServerProcess};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("chat-attachments-{}-{}", std::process::id(), name))
+}
+
+/// alice crea el grupo y bob entra, cuando vuelve esto los dos estan adentro
+fn meet_in_dogs(alice: &mut ClientProcess, bob: &mut ClientProcess) {
+    alice.type_line("create Dogs");
+    alice.type_line("post Dogs ready");
+    alice.expect("message posted to: Dogs: ready");
+    bob.type_line("join Dogs");
+    bob.type_line("post Dogs here");
+    alice.expect("message posted to: Dogs: here");
+}
+
+#[test]
+fn upload_and_download() {
+    let server = ServerProcess::spawn::<_, &str>([]);
+    let mut alice = ClientProcess::spawn(&server.address);
+    let mut bob = ClientProcess::spawn(&server.address);
+    meet_in_dogs(&mut alice, &mut bob);
+
+    // mas grande que un pedazo, para que viaje en varios
+    let data: Vec<u8> = (0..20_000).map(|n| (n % 251) as u8).collect();
+    let upload = temp_path("photo.bin");
+    std::fs::write(&upload, &data).unwrap();
+    alice.type_line(&format!("upload Dogs {}", upload.display()));
+    let available = format!(
+        "attachment #1 posted to Dogs: {} (20000 bytes)",
+        upload.file_name().unwrap().to_string_lossy()
+    );
+    alice.expect(&available);
+    bob.expect(&available);
+
+    let download = temp_path("downloaded.bin");
+    bob.type_line(&format!("get 1 {}", download.display()));
+    bob.expect(&format!("attachment #1 saved to {}", download.display()));
+    assert_eq!(std::fs::read(&download).unwrap(), data);
+
+    bob.type_line("get 2 nowhere.bin");
+    bob.expect("error from server: Attachment #2 does not exist (after 'get 2')");
+
+    for path in [upload, download] {
+        let _ = std::fs::remove_file(path);
+    }
+    alice.quit();
+    bob.quit();
+}
+
+#[test]
+fn attachments_over_the_limit_are_rejected() {
+    let server = ServerProcess::spawn(["--max-attachment-size", "100"]);
+    let mut alice = ClientProcess::spawn(&server.address);
+    let mut bob = ClientProcess::spawn(&server.address);
+    meet_in_dogs(&mut alice, &mut bob);
+
+    let upload = temp_path("big.bin");
+    std::fs::write(&upload, [0; 101]).unwrap();
+    alice.type_line(&format!("upload Dogs {}", upload.display()));
+    let file_name = upload.file_name().unwrap().to_string_lossy();
+    alice.expect(&format!(
+        "error from server: Cannot upload '{}': it has 101 bytes and the limit is 100 \
+         (after 'upload Dogs {}')",
+        file_name, file_name
+    ));
+    let _ = std::fs::remove_file(&upload);
+
+    alice.quit();
+    bob.quit();
+}
@@ -0,0 +1,207 @@
+//! Helpers compartidos por los tests de integracion que levantan los binarios del server y del
+//! cliente
+#![allow(dead_code)]
+
+use async_chat_book::codec::{Codec, PacketStream};
+use async_chat_book::utils::DEFAULT_MAX_PACKET_SIZE;
+use async_chat_book::{FromClient, FromServer};
+use async_std::io::BufReader;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+use std::ffi::OsStr;
+use std::io::{BufRead, Write};
+use std::net::SocketAddr;
+use std::process::{Child, ChildStdin, Command, Output, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// El server corriendo en otro proceso, lo matamos cuando termina el test (aunque falle)
+pub struct ServerProcess {
+    child: Option<Child>,
+    pub address: String,
+}
+
+impl ServerProcess {
+    /// Levanta el server en un puerto libre con las opciones extra de `args`
+    pub fn spawn<I, A>(args: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: AsRef<OsStr>,
+    {
+        Self::spawn_at(free_address(), args)
+    }
+
+    /// Como `spawn` pero en una direccion que elegimos, por ejemplo para volver a levantar el
+    /// server en el mismo lugar
+    pub fn spawn_at<I, A>(address: String, args: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: AsRef<OsStr>,
+    {
+        let child = Command::new(env!("CARGO_BIN_EXE_server"))
+            .arg(&address)
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start the server");
+        Self {
+            child: Some(child),
+            address,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.child.as_ref().unwrap().id()
+    }
+
+    /// Espera a que el server termine solo y devuelve lo que imprimio
+    pub fn wait_with_output(mut self) -> Output {
+        self.child.take().unwrap().wait_with_output().unwrap()
+    }
+
+    /// Se conecta reintentando hasta que el server este escuchando
+    pub async fn connect(&self) -> TcpStream {
+        for _ in 0..50 {
+            if let Ok(socket) = TcpStream::connect(&self.address).await {
+                return socket;
+            }
+            task::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("the server never started listening on {}", self.address);
+    }
+}
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+pub fn free_address() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Un cliente que manda y recibe packets ya decodificados
+pub struct TestClient {
+    to_server: TcpStream,
+    replies: PacketStream<'static, FromServer>,
+    codec: Codec,
+}
+
+impl TestClient {
+    pub async fn connect(server: &ServerProcess, codec: Codec) -> Self {
+        let mut socket = server.connect().await;
+        codec.announce(&mut socket).await.unwrap();
+        let replies = codec.receive(BufReader::new(socket.clone()), DEFAULT_MAX_PACKET_SIZE);
+        Self {
+            to_server: socket,
+            replies,
+            codec,
+        }
+    }
+
+    /// La direccion desde la que nos ve el server
+    pub fn local_addr(&self) -> SocketAddr {
+        self.to_server.local_addr().unwrap()
+    }
+
+    pub async fn send(&mut self, packet: FromClient) {
+        self.codec.send(&mut self.to_server, &packet).await.unwrap();
+    }
+
+    /// El siguiente packet del server o `None` si cerro la conexion
+    pub async fn next(&mut self) -> Option<FromServer> {
+        self.replies.next().await.map(|reply| reply.unwrap())
+    }
+}
+
+/// El binario del cliente en modo linea por linea, lo que imprime lo leemos desde otro thread
+pub struct ClientProcess {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    lines: Receiver<String>,
+    seen: Vec<String>,
+}
+
+impl ClientProcess {
+    pub fn spawn(address: &str) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_client"))
+            .arg(address)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start the client");
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in std::io::BufReader::new(stdout)
+                .lines()
+                .map_while(Result::ok)
+            {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            child,
+            stdin,
+            lines,
+            seen: Vec::new(),
+        }
+    }
+
+    pub fn type_line(&mut self, line: &str) {
+        let stdin = self.stdin.as_mut().unwrap();
+        writeln!(stdin, "{}", line).unwrap();
+        stdin.flush().unwrap();
+    }
+
+    /// Espera hasta que el cliente imprima una linea que empiece con `prefix`
+    pub fn expect(&mut self, prefix: &str) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            match self.lines.recv_timeout(timeout) {
+                Ok(line) => {
+                    let found = line.starts_with(prefix);
+                    self.seen.push(line);
+                    if found {
+                        return;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        panic!(
+            "the client never printed {:?}, it printed {:#?}",
+            prefix, self.seen
+        );
+    }
+
+    /// Todo lo que imprimio hasta ahora (lo que ya leyo `expect`)
+    pub fn seen(&self) -> &[String] {
+        &self.seen
+    }
+
+    /// Cerramos la entrada y esperamos a que el cliente termine solo
+    pub fn quit(mut self) {
+        drop(self.stdin.take());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                assert!(status.success());
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let _ = self.child.kill();
+        panic!("the client did not exit after closing its input");
+    }
+}
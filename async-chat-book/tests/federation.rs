@@ -0,0 +1,206 @@
+//! tests/federation.rs
+//!
+//! Varios servers conectados con `--peer` comparten los grupos: lo que se postea en uno les llega
+//! a los miembros de todos, una sola vez aunque haya mas de un camino. Solo entre los que saben el
+//! `--peer-token`
+use async_chat_book::codec::Codec;
+use async_chat_book::{FromClient, FromServer, GroupAccess};
+use async_std::io::BufReader;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::{future, task};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod common;
+use common::{free_address, ServerProcess, TestClient};
+
+/// Un grupo que nadie crea, el error de postear ahi nos dice que el server ya proceso lo anterior
+const PROBE_GROUP: &str = "*probe*";
+const PEER_TOKEN: &str = "the federation secret";
+
+fn create(group_name: &str) -> FromClient {
+    FromClient::CreateGroup {
+        group_name: Arc::new(group_name.to_string()),
+        access: GroupAccess::Open,
+    }
+}
+
+fn join(group_name: &str) -> FromClient {
+    FromClient::Join {
+        group_name: Arc::new(group_name.to_string()),
+        password: None,
+    }
+}
+
+fn post(group_name: &str, message: &str) -> FromClient {
+    FromClient::Post {
+        group_name: Arc::new(group_name.to_string()),
+        message: Arc::new(message.to_string()),
+    }
+}
+
+fn message(group_name: &str, message: &str, seq: u64) -> Option<FromServer> {
+    Some(FromServer::Message {
+        group_name: Arc::new(group_name.to_string()),
+        message: Arc::new(message.to_string()),
+        seq,
+    })
+}
+
+fn error(error: &str) -> Option<FromServer> {
+    Some(FromServer::Error(error.to_string()))
+}
+
+/// El grupo se crea en otro server y tarda un poco en llegar, probamos hasta poder entrar
+async fn join_when_it_arrives(client: &mut TestClient, group_name: &str) {
+    let probe_error = error(&format!("Group '{}' does not exist", PROBE_GROUP));
+    for _ in 0..50 {
+        client.send(join(group_name)).await;
+        client.send(post(PROBE_GROUP, "")).await;
+        let reply = client.next().await;
+        if reply == probe_error {
+            return;
+        }
+        assert_eq!(
+            reply,
+            error(&format!("Group '{}' does not exist", group_name))
+        );
+        assert_eq!(client.next().await, probe_error);
+        task::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("'{}' never reached the server", group_name);
+}
+
+#[test]
+fn two_servers_share_groups() {
+    let peer_address = free_address();
+    let first = ServerProcess::spawn([
+        "--peer-address",
+        peer_address.as_str(),
+        "--peer-token",
+        PEER_TOKEN,
+    ]);
+    let second =
+        ServerProcess::spawn(["--peer", peer_address.as_str(), "--peer-token", PEER_TOKEN]);
+
+    task::block_on(async {
+        let mut alice = TestClient::connect(&first, Codec::JsonLines).await;
+        let mut bob = TestClient::connect(&second, Codec::LengthPrefixed).await;
+        alice.send(create("Dogs")).await;
+        join_when_it_arrives(&mut bob, "Dogs").await;
+
+        alice.send(post("Dogs", "Samoyeds rock!!!")).await;
+        assert_eq!(alice.next().await, message("Dogs", "Samoyeds rock!!!", 1));
+        assert_eq!(bob.next().await, message("Dogs", "Samoyeds rock!!!", 1));
+
+        bob.send(post("Dogs", "they do")).await;
+        assert_eq!(bob.next().await, message("Dogs", "they do", 2));
+        assert_eq!(alice.next().await, message("Dogs", "they do", 2));
+    });
+}
+
+#[test]
+fn messages_arrive_once_in_a_triangle() {
+    // los tres estan conectados con los otros dos, cada mensaje puede llegar por dos caminos
+    let first_address = free_address();
+    let second_address = free_address();
+    let first = ServerProcess::spawn([
+        "--peer-address",
+        first_address.as_str(),
+        "--peer-token",
+        PEER_TOKEN,
+    ]);
+    let second = ServerProcess::spawn([
+        "--peer-address",
+        second_address.as_str(),
+        "--peer",
+        first_address.as_str(),
+        "--peer-token",
+        PEER_TOKEN,
+    ]);
+    let third = ServerProcess::spawn([
+        "--peer",
+        first_address.as_str(),
+        "--peer",
+        second_address.as_str(),
+        "--peer-token",
+        PEER_TOKEN,
+    ]);
+
+    task::block_on(async {
+        let mut alice = TestClient::connect(&first, Codec::JsonLines).await;
+        let mut bob = TestClient::connect(&second, Codec::JsonLines).await;
+        let mut carol = TestClient::connect(&third, Codec::JsonLines).await;
+        alice.send(create("Dogs")).await;
+        join_when_it_arrives(&mut bob, "Dogs").await;
+        join_when_it_arrives(&mut carol, "Dogs").await;
+
+        alice.send(post("Dogs", "woof")).await;
+        alice.send(post("Dogs", "woof woof")).await;
+        carol.send(post("Dogs", "yip")).await;
+        // si alguno llegara repetido lo veriamos antes que el siguiente
+        for client in [&mut alice, &mut bob, &mut carol] {
+            let mut received = Vec::new();
+            for _ in 0..3 {
+                match client.next().await {
+                    Some(FromServer::Message { message, .. }) => received.push(message.to_string()),
+                    other => panic!("expected a message, got {:?}", other),
+                }
+            }
+            received.sort();
+            assert_eq!(received, ["woof", "woof woof", "yip"]);
+        }
+
+        // y despues no queda nada dando vueltas
+        bob.send(post(PROBE_GROUP, "")).await;
+        assert_eq!(
+            bob.next().await,
+            error(&format!("Group '{}' does not exist", PROBE_GROUP))
+        );
+    });
+}
+
+/// Se conecta al puerto de los pares como si fuera otro server, manda su `Hello` y devuelve todo
+/// lo que le contestan hasta que cierran la conexion (o dejan de mandar)
+async fn say_hello(peer_address: &str, token: &str) -> String {
+    let mut socket = None;
+    for _ in 0..50 {
+        if let Ok(connected) = TcpStream::connect(peer_address).await {
+            socket = Some(connected);
+            break;
+        }
+        task::sleep(Duration::from_millis(100)).await;
+    }
+    let mut socket = socket.expect("the server never listened for peers");
+    let hello = format!(
+        "{{\"Hello\":{{\"server_id\":\"intruder\",\"token\":{:?}}}}}\n",
+        token
+    );
+    socket.write_all(hello.as_bytes()).await.unwrap();
+
+    let mut reply = String::new();
+    let mut reader = BufReader::new(socket);
+    let _ = future::timeout(Duration::from_secs(1), reader.read_line(&mut reply)).await;
+    reply
+}
+
+#[test]
+fn peers_need_the_token() {
+    let peer_address = free_address();
+    let _server = ServerProcess::spawn([
+        "--peer-address",
+        peer_address.as_str(),
+        "--peer-token",
+        PEER_TOKEN,
+    ]);
+
+    task::block_on(async {
+        // sin el token cierra la conexion sin contestar, ni siquiera con su propio token
+        assert_eq!(say_hello(&peer_address, "a guess").await, "");
+        assert_eq!(say_hello(&peer_address, "").await, "");
+
+        let reply = say_hello(&peer_address, PEER_TOKEN).await;
+        assert!(reply.starts_with("{\"Hello\""), "{}", reply);
+    });
+}
@@ -0,0 +1,231 @@
+//! tests/groups.rs
+//!
+//! Grupos con password, grupos a los que solo se entra con invitacion y los roles de owner y
+//! moderador, que son de los nombres reclamados con password
+use async_chat_book::codec::Codec;
+use async_chat_book::{FromClient, FromServer, GroupAccess};
+use async_std::task;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod common;
+use common::{ServerProcess, TestClient};
+
+fn set_name(name: &str) -> FromClient {
+    FromClient::SetName {
+        name: name.to_string(),
+        password: None,
+    }
+}
+
+fn claim(name: &str, password: &str) -> FromClient {
+    FromClient::SetName {
+        name: name.to_string(),
+        password: Some(password.to_string()),
+    }
+}
+
+fn create(group_name: &str, access: GroupAccess) -> FromClient {
+    FromClient::CreateGroup {
+        group_name: Arc::new(group_name.to_string()),
+        access,
+    }
+}
+
+fn join(group_name: &str, password: Option<&str>) -> FromClient {
+    FromClient::Join {
+        group_name: Arc::new(group_name.to_string()),
+        password: password.map(str::to_string),
+    }
+}
+
+fn post(group_name: &str, message: &str) -> FromClient {
+    FromClient::Post {
+        group_name: Arc::new(group_name.to_string()),
+        message: Arc::new(message.to_string()),
+    }
+}
+
+fn invite(group_name: &str, member: &str) -> FromClient {
+    FromClient::Invite {
+        group_name: Arc::new(group_name.to_string()),
+        member: member.to_string(),
+    }
+}
+
+fn promote(group_name: &str, member: &str) -> FromClient {
+    FromClient::Promote {
+        group_name: Arc::new(group_name.to_string()),
+        member: member.to_string(),
+    }
+}
+
+fn message(group_name: &str, message: &str, seq: u64) -> Option<FromServer> {
+    Some(FromServer::Message {
+        group_name: Arc::new(group_name.to_string()),
+        message: Arc::new(message.to_string()),
+        seq,
+    })
+}
+
+fn error(error: &str) -> Option<FromServer> {
+    Some(FromServer::Error(error.to_string()))
+}
+
+#[test]
+fn password_and_invite_only_groups() {
+    let server = ServerProcess::spawn::<_, &str>([]);
+
+    task::block_on(async {
+        // carol reclama su nombre primero, asi despues la pueden invitar
+        let mut carol = TestClient::connect(&server, Codec::JsonLines).await;
+        carol.send(claim("carol", "carol's password")).await;
+        carol.send(join("Nowhere", None)).await;
+        assert_eq!(carol.next().await, error("Group 'Nowhere' does not exist"));
+
+        let mut alice = TestClient::connect(&server, Codec::JsonLines).await;
+        alice.send(claim("alice", "alice's password")).await;
+        alice
+            .send(create("Secret", GroupAccess::InviteOnly(Vec::new())))
+            .await;
+        alice
+            .send(create("Kennel", GroupAccess::Password("woof".to_string())))
+            .await;
+        alice.send(create("Secret", GroupAccess::Open)).await;
+        assert_eq!(alice.next().await, error("Group 'Secret' already exists"));
+
+        // bob no tiene permiso para nada todavia
+        let mut bob = TestClient::connect(&server, Codec::LengthPrefixed).await;
+        bob.send(claim("bob", "bob's password")).await;
+        bob.send(join("Secret", None)).await;
+        assert_eq!(
+            bob.next().await,
+            error("Cannot join 'Secret': you are not invited")
+        );
+        bob.send(join("Kennel", Some("meow"))).await;
+        assert_eq!(
+            bob.next().await,
+            error("Cannot join 'Kennel': wrong password")
+        );
+        bob.send(join("Nowhere", None)).await;
+        assert_eq!(bob.next().await, error("Group 'Nowhere' does not exist"));
+        bob.send(invite("Secret", "carol")).await;
+        assert_eq!(
+            bob.next().await,
+            error("Cannot invite to 'Secret': only the owner and the moderators can invite")
+        );
+        bob.send(set_name("alice")).await;
+        assert_eq!(
+            bob.next().await,
+            error("The name 'alice' is claimed, it needs the right password")
+        );
+        // desde afuera tampoco puede escribir, a alice no le llega nada (el primer mensaje que
+        // ve en Kennel es el 1, mas abajo)
+        bob.send(post("Secret", "let me in")).await;
+        assert_eq!(bob.next().await, error("You are not a member of 'Secret'"));
+        bob.send(post("Kennel", "let me in")).await;
+        assert_eq!(bob.next().await, error("You are not a member of 'Kennel'"));
+
+        // con el password correcto entra
+        bob.send(join("Kennel", Some("woof"))).await;
+        bob.send(post("Kennel", "bob is in the kennel")).await;
+        assert_eq!(
+            bob.next().await,
+            message("Kennel", "bob is in the kennel", 1)
+        );
+        assert_eq!(
+            alice.next().await,
+            message("Kennel", "bob is in the kennel", 1)
+        );
+
+        // alice invita a bob y lo hace moderador, asi bob puede invitar a carol
+        alice.send(invite("Secret", "bob")).await;
+        alice.send(promote("Secret", "bob")).await;
+        alice.send(post("Secret", "welcome")).await;
+        assert_eq!(alice.next().await, message("Secret", "welcome", 1));
+        bob.send(join("Secret", None)).await;
+        bob.send(invite("Secret", "carol")).await;
+        bob.send(promote("Secret", "carol")).await;
+        assert_eq!(
+            bob.next().await,
+            error("Cannot promote in 'Secret': only the owner can promote moderators")
+        );
+
+        carol.send(join("Secret", None)).await;
+        carol.send(post("Secret", "hi from carol")).await;
+        for client in [&mut alice, &mut bob, &mut carol] {
+            assert_eq!(client.next().await, message("Secret", "hi from carol", 2));
+        }
+    });
+}
+
+#[test]
+fn roles_belong_to_claimed_names() {
+    let server = ServerProcess::spawn::<_, &str>([]);
+
+    task::block_on(async {
+        let mut alice = TestClient::connect(&server, Codec::JsonLines).await;
+        alice.send(claim("alice", "alice's password")).await;
+        alice
+            .send(create("Secret", GroupAccess::InviteOnly(Vec::new())))
+            .await;
+
+        // nadie reclamo "mallory", cualquiera se lo podria poner para entrar
+        let not_claimed = error(
+            "The name 'mallory' is not claimed, only claimed names can be invited or promoted",
+        );
+        alice.send(invite("Secret", "mallory")).await;
+        assert_eq!(alice.next().await, not_claimed);
+        alice.send(promote("Secret", "mallory")).await;
+        assert_eq!(alice.next().await, not_claimed);
+        let invite_only = GroupAccess::InviteOnly(vec!["mallory".to_string()]);
+        alice.send(create("Other", invite_only)).await;
+        assert_eq!(alice.next().await, not_claimed);
+
+        // un nombre sin reclamar sirve para charlar pero no para los roles
+        let mut eve = TestClient::connect(&server, Codec::LengthPrefixed).await;
+        eve.send(set_name("eve")).await;
+        eve.send(create("Eve's", GroupAccess::InviteOnly(Vec::new())))
+            .await;
+        eve.send(invite("Eve's", "alice")).await;
+        assert_eq!(
+            eve.next().await,
+            error("Cannot invite to 'Eve's': only the owner and the moderators can invite")
+        );
+
+        // alice se va y eve no puede quedarse con su nombre ni con sus grupos
+        drop(alice);
+        let claimed = error("The name 'alice' is claimed, it needs the right password");
+        eve.send(set_name("alice")).await;
+        assert_eq!(eve.next().await, claimed);
+        eve.send(claim("alice", "a guess")).await;
+        assert_eq!(eve.next().await, claimed);
+        eve.send(join("Secret", None)).await;
+        assert_eq!(
+            eve.next().await,
+            error("Cannot join 'Secret': you are not invited")
+        );
+
+        // con la password alice vuelve a ser la owner (cuando el server termine de sacar su
+        // conexion vieja)
+        let mut alice = TestClient::connect(&server, Codec::JsonLines).await;
+        let nowhere = error("Group 'Nowhere' does not exist");
+        let mut reclaimed = false;
+        for _ in 0..50 {
+            alice.send(claim("alice", "alice's password")).await;
+            alice.send(join("Nowhere", None)).await;
+            let reply = alice.next().await;
+            if reply == nowhere {
+                reclaimed = true;
+                break;
+            }
+            assert_eq!(reply, error("The name 'alice' is already in use"));
+            assert_eq!(alice.next().await, nowhere);
+            task::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(reclaimed, "alice never got her name back");
+        alice.send(join("Secret", None)).await;
+        alice.send(post("Secret", "alice is back")).await;
+        assert_eq!(alice.next().await, message("Secret", "alice is back", 1));
+    });
+}
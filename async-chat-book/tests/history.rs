@@ -0,0 +1,83 @@
+//! tests/history.rs
+//!
+//! Un server de mentira le manda al cliente mensajes con un salto en la numeracion: el cliente
+//! tiene que pedir los que faltan con `History` y mostrarlos
+use async_chat_book::codec::Codec;
+use async_chat_book::utils::DEFAULT_MAX_PACKET_SIZE;
+use async_chat_book::{Envelope, FromClient, FromServer, Logged, Versioned};
+use async_std::io::BufReader;
+use async_std::net::TcpListener;
+use async_std::prelude::*;
+use async_std::task;
+use std::sync::Arc;
+
+mod common;
+use common::ClientProcess;
+
+fn message(message: &str, seq: u64) -> FromServer {
+    FromServer::Message {
+        group_name: Arc::new("Dogs".to_string()),
+        message: Arc::new(message.to_string()),
+        seq,
+    }
+}
+
+#[test]
+fn client_asks_for_missing_messages() {
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut client = ClientProcess::spawn(&address);
+
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(socket.clone());
+        let codec = Codec::negotiate(&mut reader).await.unwrap();
+        let mut requests =
+            codec.receive::<_, Versioned<FromClient>>(reader, DEFAULT_MAX_PACKET_SIZE);
+        let mut writer = socket;
+
+        client.type_line("join Dogs");
+        let join = requests.next().await.unwrap().unwrap();
+        assert!(matches!(join.into_packet(), FromClient::Join { .. }));
+
+        // del 1 pasamos al 4
+        for reply in [message("one", 1), message("four", 4)] {
+            let reply = Envelope::new(reply, None);
+            codec.send(&mut writer, &reply).await.unwrap();
+        }
+        client.expect("message posted to: Dogs: one");
+        client.expect("message posted to: Dogs: four");
+
+        let request = requests.next().await.unwrap().unwrap();
+        let id = request.id();
+        assert_eq!(
+            request.into_packet(),
+            FromClient::History {
+                group_name: Arc::new("Dogs".to_string()),
+                from: 2,
+                to: 3,
+            }
+        );
+        let history = FromServer::History {
+            group_name: Arc::new("Dogs".to_string()),
+            messages: vec![
+                Logged {
+                    seq: 2,
+                    message: Arc::new("two".to_string()),
+                },
+                Logged {
+                    seq: 3,
+                    message: Arc::new("three".to_string()),
+                },
+            ],
+        };
+        codec
+            .send(&mut writer, &Envelope::new(history, id))
+            .await
+            .unwrap();
+        client.expect("message posted to: Dogs: two");
+        client.expect("message posted to: Dogs: three");
+
+        client.quit();
+    });
+}
@@ -0,0 +1,113 @@
+//! tests/load.rs
+//!
+//! Mil clientes repartidos en varios grupos postean todos a la vez: a cada uno le tienen que
+//! llegar todos los mensajes de su grupo. Imprime cuantos mensajes por segundo repartio el server
+//! (para verlo: `cargo test --release --test load -- --nocapture`)
+use async_chat_book::codec::Codec;
+use async_chat_book::{FromClient, FromServer, GroupAccess};
+use async_std::task;
+use futures::future::join_all;
+use std::sync::Arc;
+use std::time::Instant;
+
+mod common;
+use common::{ServerProcess, TestClient};
+
+const CLIENTS: usize = 1000;
+const GROUPS: usize = 10;
+const POSTS_PER_CLIENT: usize = 2;
+
+fn group_of(client: usize) -> Arc<String> {
+    Arc::new(format!("group-{}", client % GROUPS))
+}
+
+/// Se conecta y entra al grupo, cuando devuelve el server ya proceso el `Join` (los primeros
+/// `GROUPS` clientes son los que crean los grupos)
+async fn join(server: &ServerProcess, client: usize) -> TestClient {
+    let codec = if client.is_multiple_of(2) {
+        Codec::JsonLines
+    } else {
+        Codec::LengthPrefixed
+    };
+    let mut test_client = TestClient::connect(server, codec).await;
+    let request = if client < GROUPS {
+        FromClient::CreateGroup {
+            group_name: group_of(client),
+            access: GroupAccess::Open,
+        }
+    } else {
+        FromClient::Join {
+            group_name: group_of(client),
+            password: None,
+        }
+    };
+    test_client.send(request).await;
+    // el server atiende los packets de cada conexion en orden, asi que cuando llega el error
+    // de este post el join ya esta hecho
+    test_client
+        .send(FromClient::Post {
+            group_name: Arc::new("sync".to_string()),
+            message: Arc::new(String::new()),
+        })
+        .await;
+    assert!(matches!(
+        test_client.next().await,
+        Some(FromServer::Error(_))
+    ));
+    test_client
+}
+
+#[test]
+fn fan_out_to_a_thousand_clients() {
+    let server = ServerProcess::spawn([
+        "--client-limit",
+        "1000:1000",
+        "--group-limit",
+        "100000:100000",
+    ]);
+
+    task::block_on(async {
+        let mut clients = Vec::with_capacity(CLIENTS);
+        for client in 0..CLIENTS {
+            clients.push(join(&server, client).await);
+        }
+
+        let expected = CLIENTS / GROUPS * POSTS_PER_CLIENT;
+        let start = Instant::now();
+        let runs = clients
+            .into_iter()
+            .enumerate()
+            .map(|(client, mut test_client)| {
+                task::spawn(async move {
+                    let group_name = group_of(client);
+                    for n in 0..POSTS_PER_CLIENT {
+                        test_client
+                            .send(FromClient::Post {
+                                group_name: group_name.clone(),
+                                message: Arc::new(format!("{} from {}", n, client)),
+                            })
+                            .await;
+                    }
+                    for _ in 0..expected {
+                        match test_client.next().await {
+                            Some(FromServer::Message {
+                                group_name: from, ..
+                            }) => assert_eq!(from, group_name),
+                            other => panic!("client {} got {:?}", client, other),
+                        }
+                    }
+                })
+            });
+        join_all(runs).await;
+        let elapsed = start.elapsed();
+
+        let delivered = CLIENTS * expected;
+        println!(
+            "delivered {} messages to {} clients in {:.2?} ({:.0} messages/s)",
+            delivered,
+            CLIENTS,
+            elapsed,
+            delivered as f64 / elapsed.as_secs_f64()
+        );
+    });
+}
@@ -0,0 +1,134 @@
+//! tests/rate_limit.rs
+//!
+//! Un cliente que manda packets mas rapido de lo permitido (o pedazos de archivos que no esta
+//! subiendo) recibe errores y termina desconectado
+use async_chat_book::codec::Codec;
+use async_chat_book::utils::DEFAULT_MAX_PACKET_SIZE;
+use async_chat_book::{FromClient, FromServer, GroupAccess};
+use async_std::io::BufReader;
+use async_std::prelude::*;
+use async_std::task;
+use std::sync::Arc;
+
+mod common;
+use common::ServerProcess;
+
+fn post(group_name: &str, message: &str) -> FromClient {
+    FromClient::Post {
+        group_name: Arc::new(group_name.to_string()),
+        message: Arc::new(message.to_string()),
+    }
+}
+
+#[test]
+fn flooding_client_is_disconnected() {
+    let server = ServerProcess::spawn(["--client-limit", "1:3", "--max-violations", "3"]);
+
+    task::block_on(async {
+        let mut socket = server.connect().await;
+        let codec = Codec::JsonLines;
+        codec.announce(&mut socket).await.unwrap();
+        for n in 0..10 {
+            codec
+                .send(&mut socket, &post("Nowhere", &n.to_string()))
+                .await
+                .unwrap();
+        }
+
+        let replies: Vec<FromServer> = codec
+            .receive(BufReader::new(socket), DEFAULT_MAX_PACKET_SIZE)
+            .map(|reply| reply.unwrap())
+            .collect()
+            .await;
+        let missing = FromServer::Error("Group 'Nowhere' does not exist".to_string());
+        let limited = FromServer::Error("rate limited".to_string());
+        let closing =
+            FromServer::Error("too many rate limit violations, closing the connection".to_string());
+        assert_eq!(
+            replies,
+            vec![
+                missing.clone(),
+                missing.clone(),
+                missing,
+                limited.clone(),
+                limited,
+                closing
+            ]
+        );
+    });
+}
+
+#[test]
+fn chunks_of_unknown_uploads_count_as_violations() {
+    let server = ServerProcess::spawn(["--client-limit", "1:3", "--max-violations", "3"]);
+
+    task::block_on(async {
+        let mut socket = server.connect().await;
+        let codec = Codec::JsonLines;
+        codec.announce(&mut socket).await.unwrap();
+        // los pedazos de una subida que nunca empezo no se salvan del limite
+        for _ in 0..10 {
+            let chunk = FromClient::UploadChunk {
+                upload_id: 7,
+                data: vec![0; 16],
+            };
+            codec.send(&mut socket, &chunk).await.unwrap();
+        }
+
+        let replies: Vec<FromServer> = codec
+            .receive(BufReader::new(socket), DEFAULT_MAX_PACKET_SIZE)
+            .map(|reply| reply.unwrap())
+            .collect()
+            .await;
+        let not_started = FromServer::Error("Upload #7 was not started".to_string());
+        let closing =
+            FromServer::Error("too many rate limit violations, closing the connection".to_string());
+        assert_eq!(
+            replies,
+            vec![
+                not_started.clone(),
+                not_started.clone(),
+                not_started,
+                closing
+            ]
+        );
+    });
+}
+
+#[test]
+fn group_limit_is_shared() {
+    let server = ServerProcess::spawn(["--group-limit", "1:2"]);
+
+    task::block_on(async {
+        let mut socket = server.connect().await;
+        let codec = Codec::LengthPrefixed;
+        codec.announce(&mut socket).await.unwrap();
+        let create = FromClient::CreateGroup {
+            group_name: Arc::new("Dogs".to_string()),
+            access: GroupAccess::Open,
+        };
+        codec.send(&mut socket, &create).await.unwrap();
+        for n in 0..5 {
+            codec
+                .send(&mut socket, &post("Dogs", &n.to_string()))
+                .await
+                .unwrap();
+        }
+
+        let replies: Vec<FromServer> = codec
+            .receive(BufReader::new(socket), DEFAULT_MAX_PACKET_SIZE)
+            .take(5)
+            .map(|reply| reply.unwrap())
+            .collect()
+            .await;
+        let limited = replies
+            .iter()
+            .filter(|reply| **reply == FromServer::Error("rate limited".to_string()))
+            .count();
+        let delivered = replies
+            .iter()
+            .filter(|reply| matches!(reply, FromServer::Message { .. }))
+            .count();
+        assert_eq!((delivered, limited), (2, 3));
+    });
+}
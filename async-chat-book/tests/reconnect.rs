@@ -0,0 +1,156 @@
+//! tests/reconnect.rs
+//!
+//! Matamos el server en medio de la sesion y lo volvemos a levantar (o solo cortamos la
+//! conexion): el cliente se tiene que reconectar solo y volver a entrar en los grupos en los que
+//! estaba
+use async_chat_book::codec::Codec;
+use async_chat_book::{FromClient, FromServer};
+use async_std::task;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+mod common;
+use common::{free_address, ClientProcess, ServerProcess, TestClient};
+
+#[test]
+fn client_reconnects_and_rejoins_after_server_restart() {
+    let address = free_address();
+    let server = ServerProcess::spawn_at(address.clone(), ["--shutdown-timeout", "1"]);
+
+    let mut client = ClientProcess::spawn(&address);
+    client.expect("[status] connected");
+    client.type_line("create Dogs");
+    client.type_line("post Dogs woof");
+    client.expect("message posted to: Dogs: woof");
+
+    // se cae el server sin avisar
+    drop(server);
+    client.expect("[status] disconnected");
+
+    let server = ServerProcess::spawn_at(address, ["--shutdown-timeout", "1"]);
+    client.expect("[status] connected, rejoined 1 group(s)");
+
+    // si de verdad volvio a entrar en el grupo le llegan los mensajes de otro cliente
+    task::block_on(async {
+        let group_name = Arc::new("Dogs".to_string());
+        let message = Arc::new("welcome back".to_string());
+        let mut other = TestClient::connect(&server, Codec::JsonLines).await;
+        other
+            .send(FromClient::Join {
+                group_name: group_name.clone(),
+                password: None,
+            })
+            .await;
+        other
+            .send(FromClient::Post {
+                group_name: group_name.clone(),
+                message: message.clone(),
+            })
+            .await;
+        assert_eq!(
+            other.next().await,
+            Some(FromServer::Message {
+                group_name,
+                message,
+                seq: 1,
+            })
+        );
+    });
+    client.expect("message posted to: Dogs: welcome back");
+
+    client.quit();
+}
+
+#[test]
+fn rejected_joins_are_not_replayed() {
+    let address = free_address();
+    let server = ServerProcess::spawn_at(address.clone(), ["--shutdown-timeout", "1"]);
+
+    let mut client = ClientProcess::spawn(&address);
+    client.expect("[status] connected");
+    client.type_line("create Dogs");
+    client.type_line("join Dgos");
+    client.expect("error from server: Group 'Dgos' does not exist (after 'join Dgos')");
+
+    drop(server);
+    client.expect("[status] disconnected");
+
+    // el que fallo no se repite, solo vuelve a entrar en el que existe
+    let _server = ServerProcess::spawn_at(address, ["--shutdown-timeout", "1"]);
+    client.expect("[status] connected, rejoined 1 group(s)");
+    client.type_line("post Dogs woof");
+    client.expect("message posted to: Dogs: woof");
+
+    client.quit();
+}
+
+/// Un proxy entre el cliente y el server, para cortar la conexion sin reiniciar el server
+struct Proxy {
+    address: String,
+    /// los dos lados de cada conexion que esta pasando
+    sockets: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl Proxy {
+    fn spawn(server_address: String) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let sockets = Arc::new(Mutex::new(Vec::new()));
+        let open = sockets.clone();
+        thread::spawn(move || {
+            for client in listener.incoming().map_while(Result::ok) {
+                let server = match TcpStream::connect(&server_address) {
+                    Ok(server) => server,
+                    Err(_) => continue,
+                };
+                let pipe = |mut from: TcpStream, mut to: TcpStream| {
+                    thread::spawn(move || {
+                        let _ = std::io::copy(&mut from, &mut to);
+                        let _ = to.shutdown(Shutdown::Both);
+                    });
+                };
+                pipe(client.try_clone().unwrap(), server.try_clone().unwrap());
+                pipe(server.try_clone().unwrap(), client.try_clone().unwrap());
+                open.lock().unwrap().extend([client, server]);
+            }
+        });
+        Self { address, sockets }
+    }
+
+    /// Corta las conexiones que estan pasando, las nuevas pasan igual
+    fn cut(&self) {
+        for socket in self.sockets.lock().unwrap().drain(..) {
+            let _ = socket.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+#[test]
+fn replayed_creates_of_groups_that_still_exist_are_quiet() {
+    let server = ServerProcess::spawn::<_, &str>([]);
+    let proxy = Proxy::spawn(server.address.clone());
+
+    let mut client = ClientProcess::spawn(&proxy.address);
+    client.expect("[status] connected");
+    client.type_line("create Dogs");
+    client.type_line("post Dogs woof");
+    client.expect("message posted to: Dogs: woof");
+
+    // el server sigue andando y el grupo tambien, el `CreateGroup` que se repite falla
+    proxy.cut();
+    client.expect("[status] disconnected");
+    client.expect("[status] connected, rejoined 1 group(s)");
+    client.type_line("post Dogs still here");
+    client.expect("message posted to: Dogs: still here");
+    assert!(
+        !client
+            .seen()
+            .iter()
+            .any(|line| line.contains("already exists")),
+        "{:#?}",
+        client.seen()
+    );
+
+    client.quit();
+}
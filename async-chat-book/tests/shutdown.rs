@@ -0,0 +1,73 @@
+//! tests/shutdown.rs
+//!
+//! Le mandamos un SIGTERM al server y chequeamos que se despida de los clientes antes de salir
+#![cfg(unix)]
+
+use async_chat_book::codec::Codec;
+use async_chat_book::utils::DEFAULT_MAX_PACKET_SIZE;
+use async_chat_book::{FromClient, FromServer, GroupAccess};
+use async_std::io::BufReader;
+use async_std::prelude::*;
+use async_std::task;
+use std::process::Command;
+use std::sync::Arc;
+
+mod common;
+use common::ServerProcess;
+
+#[test]
+fn clients_are_notified_on_sigterm() {
+    let server = ServerProcess::spawn(["--shutdown-timeout", "2"]);
+
+    task::block_on(async {
+        let mut socket = server.connect().await;
+        let codec = Codec::JsonLines;
+        codec.announce(&mut socket).await.unwrap();
+        let group_name = Arc::new("Dogs".to_string());
+        let message = Arc::new("last one out turns off the lights".to_string());
+        let packets = [
+            FromClient::CreateGroup {
+                group_name: group_name.clone(),
+                access: GroupAccess::Open,
+            },
+            FromClient::Post {
+                group_name: group_name.clone(),
+                message: message.clone(),
+            },
+        ];
+        for packet in &packets {
+            codec.send(&mut socket, packet).await.unwrap();
+        }
+
+        let mut replies = codec.receive(BufReader::new(socket), DEFAULT_MAX_PACKET_SIZE);
+        let reply: FromServer = replies.next().await.unwrap().unwrap();
+        assert_eq!(
+            reply,
+            FromServer::Message {
+                group_name,
+                message,
+                seq: 1,
+            }
+        );
+
+        let status = Command::new("kill")
+            .args(["-TERM", &server.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let reply: FromServer = replies.next().await.unwrap().unwrap();
+        assert_eq!(reply, FromServer::ServerShutdown);
+        assert!(replies.next().await.is_none());
+    });
+
+    let output = server.wait_with_output();
+    assert!(output.status.success());
+    let summary = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        summary.contains("1 active connection(s) in 1 group(s)"),
+        "{}",
+        summary
+    );
+    assert!(summary.contains("1 of 1 client(s) notified"), "{}", summary);
+}
@@ -0,0 +1,202 @@
+//! tests/tls.rs
+//!
+//! Levantamos el binario del server con un certificado self-signed que generamos en el momento y
+//! nos conectamos con el mismo `TlsConnector` que usa el cliente. Los pares de la federacion usan
+//! el mismo certificado
+use async_chat_book::codec::Codec;
+use async_chat_book::tls;
+use async_chat_book::utils::DEFAULT_MAX_PACKET_SIZE;
+use async_chat_book::{FromClient, FromServer, GroupAccess};
+use async_std::io::BufReader;
+use async_std::prelude::*;
+use async_std::task;
+use futures::io::AsyncReadExt;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod common;
+use common::{free_address, ServerProcess, TestClient};
+
+/// Escribe un certificado self-signed para `localhost` y su clave en un directorio temporal
+fn self_signed_certificate(name: &str) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("async-chat-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    std::fs::write(&cert_path, cert.pem()).unwrap();
+    std::fs::write(&key_path, key_pair.serialize_pem()).unwrap();
+    (cert_path, key_path)
+}
+
+fn spawn_tls_server(cert: &Path, key: &Path) -> ServerProcess {
+    spawn_tls_server_with(cert, key, [])
+}
+
+fn spawn_tls_server_with<const N: usize>(
+    cert: &Path,
+    key: &Path,
+    extra: [&str; N],
+) -> ServerProcess {
+    let mut args = vec![OsString::from("--tls-cert"), cert.into()];
+    args.extend([OsString::from("--tls-key"), key.into()]);
+    args.extend(extra.iter().map(OsString::from));
+    ServerProcess::spawn(args)
+}
+
+#[test]
+fn chat_over_tls() {
+    let (cert, key) = self_signed_certificate("tls");
+    let server = spawn_tls_server(&cert, &key);
+
+    task::block_on(async {
+        let socket = server.connect().await;
+        let connector = tls::connector(&cert).unwrap();
+        let mut socket = connector
+            .connect(tls::server_name("localhost").unwrap(), socket)
+            .await
+            .expect("TLS handshake failed");
+
+        let codec = Codec::LengthPrefixed;
+        codec.announce(&mut socket).await.unwrap();
+        let (reader, mut writer) = socket.split();
+        let group_name = Arc::new("Dogs".to_string());
+        let message = Arc::new("Samoyeds rock!!!".to_string());
+        let packets = [
+            FromClient::CreateGroup {
+                group_name: group_name.clone(),
+                access: GroupAccess::Open,
+            },
+            FromClient::Post {
+                group_name: group_name.clone(),
+                message: message.clone(),
+            },
+        ];
+        for packet in &packets {
+            codec.send(&mut writer, packet).await.unwrap();
+        }
+        writer.flush().await.unwrap();
+
+        let mut replies = codec.receive(BufReader::new(reader), DEFAULT_MAX_PACKET_SIZE);
+        let reply: FromServer = replies.next().await.unwrap().unwrap();
+        assert_eq!(
+            reply,
+            FromServer::Message {
+                group_name,
+                message,
+                seq: 1,
+            }
+        );
+    });
+}
+
+#[test]
+fn untrusted_certificate_is_rejected() {
+    let (cert, key) = self_signed_certificate("tls-server");
+    let (other_cert, _) = self_signed_certificate("tls-other");
+    let server = spawn_tls_server(&cert, &key);
+
+    task::block_on(async {
+        let socket = server.connect().await;
+        let connector = tls::connector(&other_cert).unwrap();
+        let result = connector
+            .connect(tls::server_name("localhost").unwrap(), socket)
+            .await;
+        assert!(result.is_err());
+    });
+}
+
+#[test]
+fn peers_link_over_tls() {
+    let (cert, key) = self_signed_certificate("tls-peers");
+    let peer_address = free_address();
+    let token = "the federation secret";
+    let first = spawn_tls_server_with(
+        &cert,
+        &key,
+        [
+            "--peer-address",
+            peer_address.as_str(),
+            "--peer-token",
+            token,
+        ],
+    );
+    let mut args = vec![OsString::from("--peer"), peer_address.clone().into()];
+    args.extend([OsString::from("--peer-tls-ca"), cert.clone().into()]);
+    for arg in ["--peer-tls-domain", "localhost", "--peer-token", token] {
+        args.push(arg.into());
+    }
+    let second = ServerProcess::spawn(args);
+
+    task::block_on(async {
+        // bob esta en el server con TLS, alice en el otro
+        let socket = first.connect().await;
+        let connector = tls::connector(&cert).unwrap();
+        let mut socket = connector
+            .connect(tls::server_name("localhost").unwrap(), socket)
+            .await
+            .expect("TLS handshake failed");
+        let codec = Codec::JsonLines;
+        codec.announce(&mut socket).await.unwrap();
+        let (reader, mut bob) = socket.split();
+        let mut to_bob = codec.receive(BufReader::new(reader), DEFAULT_MAX_PACKET_SIZE);
+        let group_name = Arc::new("Dogs".to_string());
+        let create = FromClient::CreateGroup {
+            group_name: group_name.clone(),
+            access: GroupAccess::Open,
+        };
+        codec.send(&mut bob, &create).await.unwrap();
+        bob.flush().await.unwrap();
+
+        // el grupo llega cuando el segundo server termina de conectarse por TLS
+        let mut alice = TestClient::connect(&second, Codec::JsonLines).await;
+        let join = FromClient::Join {
+            group_name: group_name.clone(),
+            password: None,
+        };
+        // el error de postear en un grupo que nadie crea nos dice que el server ya proceso el join
+        let probe = FromClient::Post {
+            group_name: Arc::new("*probe*".to_string()),
+            message: Arc::new(String::new()),
+        };
+        let probe_error = Some(FromServer::Error(
+            "Group '*probe*' does not exist".to_string(),
+        ));
+        let mut joined = false;
+        for _ in 0..50 {
+            alice.send(join.clone()).await;
+            alice.send(probe.clone()).await;
+            let reply = alice.next().await;
+            if reply == probe_error {
+                joined = true;
+                break;
+            }
+            assert_eq!(
+                reply,
+                Some(FromServer::Error("Group 'Dogs' does not exist".to_string()))
+            );
+            assert_eq!(alice.next().await, probe_error);
+            task::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(joined, "'Dogs' never crossed the TLS link");
+
+        let message = Arc::new("woof".to_string());
+        alice
+            .send(FromClient::Post {
+                group_name: group_name.clone(),
+                message: message.clone(),
+            })
+            .await;
+        let expected = FromServer::Message {
+            group_name,
+            message,
+            seq: 1,
+        };
+        assert_eq!(alice.next().await, Some(expected.clone()));
+        let reply: FromServer = to_bob.next().await.unwrap().unwrap();
+        assert_eq!(reply, expected);
+    });
+}
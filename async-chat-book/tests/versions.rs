@@ -0,0 +1,118 @@
+//! tests/versions.rs
+//!
+//! Un cliente viejo (packets sueltos, version 1) y uno nuevo (con `Envelope`, version 2) en el
+//! mismo grupo: cada uno recibe los packets en la version que habla
+use async_chat_book::codec::Codec;
+use async_chat_book::utils::DEFAULT_MAX_PACKET_SIZE;
+use async_chat_book::{Envelope, FromClient, FromServer, GroupAccess, Versioned};
+use async_chat_book::{LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use async_std::io::BufReader;
+use async_std::prelude::*;
+use async_std::task;
+use std::sync::Arc;
+
+mod common;
+use common::{ServerProcess, TestClient};
+
+fn message(group_name: &str, message: &str, seq: u64) -> FromServer {
+    FromServer::Message {
+        group_name: Arc::new(group_name.to_string()),
+        message: Arc::new(message.to_string()),
+        seq,
+    }
+}
+
+#[test]
+fn old_and_new_clients_side_by_side() {
+    let server = ServerProcess::spawn::<_, &str>([]);
+
+    task::block_on(async {
+        let dogs = Arc::new("Dogs".to_string());
+        let mut old = TestClient::connect(&server, Codec::JsonLines).await;
+        old.send(FromClient::CreateGroup {
+            group_name: dogs.clone(),
+            access: GroupAccess::Open,
+        })
+        .await;
+        old.send(FromClient::Post {
+            group_name: dogs.clone(),
+            message: Arc::new("old".to_string()),
+        })
+        .await;
+        assert_eq!(old.next().await, Some(message("Dogs", "old", 1)));
+
+        let mut new = server.connect().await;
+        let codec = Codec::LengthPrefixed;
+        codec.announce(&mut new).await.unwrap();
+        let packets = [
+            FromClient::Join {
+                group_name: dogs.clone(),
+                password: None,
+            },
+            FromClient::Post {
+                group_name: Arc::new("Cats".to_string()),
+                message: Arc::new("meow".to_string()),
+            },
+            FromClient::Post {
+                group_name: dogs.clone(),
+                message: Arc::new("new".to_string()),
+            },
+        ];
+        for (id, packet) in packets.iter().enumerate() {
+            let envelope = Envelope::new(packet, Some(id as u64));
+            codec.send(&mut new, &envelope).await.unwrap();
+        }
+
+        let mut replies = codec.receive(BufReader::new(new), DEFAULT_MAX_PACKET_SIZE);
+        // el error dice a que pedido responde, los mensajes del grupo no responden a ninguno
+        let reply: Versioned<FromServer> = replies.next().await.unwrap().unwrap();
+        assert_eq!(
+            reply,
+            Versioned::Enveloped(Envelope::new(
+                FromServer::Error("Group 'Cats' does not exist".to_string()),
+                Some(1)
+            ))
+        );
+        let reply: Versioned<FromServer> = replies.next().await.unwrap().unwrap();
+        assert_eq!(reply.protocol_version(), PROTOCOL_VERSION);
+        assert_eq!(reply.id(), None);
+        assert_eq!(reply.into_packet(), message("Dogs", "new", 2));
+
+        // al viejo le llega suelto, como siempre
+        assert_eq!(old.next().await, Some(message("Dogs", "new", 2)));
+    });
+}
+
+#[test]
+fn unsupported_version_is_reported() {
+    let server = ServerProcess::spawn::<_, &str>([]);
+
+    task::block_on(async {
+        let mut socket = server.connect().await;
+        let codec = Codec::JsonLines;
+        codec.announce(&mut socket).await.unwrap();
+        let from_the_future = Envelope {
+            protocol_version: PROTOCOL_VERSION + 1,
+            id: Some(9),
+            packet: FromClient::SetName {
+                name: "marty".to_string(),
+                password: None,
+            },
+        };
+        codec.send(&mut socket, &from_the_future).await.unwrap();
+
+        let mut replies = codec.receive(BufReader::new(socket), DEFAULT_MAX_PACKET_SIZE);
+        let reply: Versioned<FromServer> = replies.next().await.unwrap().unwrap();
+        let error = format!(
+            "unsupported protocol version {}, this server speaks versions {} to {}",
+            PROTOCOL_VERSION + 1,
+            LEGACY_PROTOCOL_VERSION,
+            PROTOCOL_VERSION
+        );
+        // le contestamos en la version mas nueva que conocemos
+        assert_eq!(
+            reply,
+            Versioned::Enveloped(Envelope::new(FromServer::Error(error), Some(9)))
+        );
+    });
+}
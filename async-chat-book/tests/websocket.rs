@@ -0,0 +1,115 @@
+//! tests/websocket.rs
+//!
+//! Un cliente de TCP y uno de WebSocket chateando en el mismo grupo
+use async_chat_book::codec::Codec;
+use async_chat_book::utils::DEFAULT_MAX_PACKET_SIZE;
+use async_chat_book::{FromClient, FromServer, GroupAccess};
+use async_std::io::BufReader;
+use async_std::prelude::*;
+use async_std::task;
+use async_tungstenite::tungstenite::{Error as WsError, Message};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod common;
+use common::{free_address, ServerProcess};
+
+fn create(group_name: &str) -> FromClient {
+    FromClient::CreateGroup {
+        group_name: Arc::new(group_name.to_string()),
+        access: GroupAccess::Open,
+    }
+}
+
+fn join(group_name: &str) -> FromClient {
+    FromClient::Join {
+        group_name: Arc::new(group_name.to_string()),
+        password: None,
+    }
+}
+
+fn post(group_name: &str, message: &str) -> FromClient {
+    FromClient::Post {
+        group_name: Arc::new(group_name.to_string()),
+        message: Arc::new(message.to_string()),
+    }
+}
+
+fn message(group_name: &str, message: &str, seq: u64) -> FromServer {
+    FromServer::Message {
+        group_name: Arc::new(group_name.to_string()),
+        message: Arc::new(message.to_string()),
+        seq,
+    }
+}
+
+fn text(packet: &FromClient) -> Message {
+    Message::text(serde_json::to_string(packet).unwrap())
+}
+
+/// El siguiente packet que llega por el WebSocket, salteando pings y demas
+async fn next_ws_reply<S>(websocket: &mut S) -> FromServer
+where
+    S: Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    loop {
+        if let Message::Text(json) = websocket.next().await.unwrap().unwrap() {
+            return serde_json::from_str(&json).unwrap();
+        }
+    }
+}
+
+#[test]
+fn tcp_and_websocket_share_groups() {
+    let ws_address = free_address();
+    let server = ServerProcess::spawn(["--ws-address", &ws_address]);
+
+    task::block_on(async {
+        // el cliente de TCP crea el grupo y espera su propio mensaje para saber que ya esta
+        let mut tcp = server.connect().await;
+        let codec = Codec::JsonLines;
+        codec.announce(&mut tcp).await.unwrap();
+        codec.send(&mut tcp, &create("Dogs")).await.unwrap();
+        codec
+            .send(&mut tcp, &post("Dogs", "tcp ready"))
+            .await
+            .unwrap();
+        let mut tcp_replies = codec.receive(BufReader::new(tcp.clone()), DEFAULT_MAX_PACKET_SIZE);
+        let reply: FromServer = tcp_replies.next().await.unwrap().unwrap();
+        assert_eq!(reply, message("Dogs", "tcp ready", 1));
+
+        let url = format!("ws://{}", ws_address);
+        let mut websocket = None;
+        for _ in 0..50 {
+            if let Ok((connected, _)) = async_tungstenite::async_std::connect_async(&url).await {
+                websocket = Some(connected);
+                break;
+            }
+            task::sleep(Duration::from_millis(100)).await;
+        }
+        let mut websocket = websocket.expect("the WebSocket listener never started");
+
+        websocket.send(text(&join("Dogs"))).await.unwrap();
+        websocket
+            .send(text(&post("Dogs", "hello from the browser")))
+            .await
+            .unwrap();
+
+        let reply = next_ws_reply(&mut websocket).await;
+        assert_eq!(reply, message("Dogs", "hello from the browser", 2));
+        let reply: FromServer = tcp_replies.next().await.unwrap().unwrap();
+        assert_eq!(reply, message("Dogs", "hello from the browser", 2));
+
+        codec
+            .send(&mut tcp, &post("Dogs", "hello from TCP"))
+            .await
+            .unwrap();
+        let reply = next_ws_reply(&mut websocket).await;
+        assert_eq!(reply, message("Dogs", "hello from TCP", 3));
+
+        // y los errores de siempre tambien llegan por el WebSocket
+        websocket.send(Message::text("not json")).await.unwrap();
+        let reply = next_ws_reply(&mut websocket).await;
+        assert!(matches!(reply, FromServer::Error(error) if error.starts_with("malformed packet")));
+    });
+}